@@ -14,8 +14,12 @@ use rainway::{
     check_system_compatibility, error_on_duplicate_session, kill_rainway, launch_rainway,
 };
 use rust_embed::RustEmbed;
+use std::path::PathBuf;
 use ui::native::{show_error, show_error_with_url, try_elevate, local_app_data};
-use ui::view::{apply_update, download_update, launch_and_close, verify_update};
+use ui::view::{
+    apply_update, defer_update, download_update, launch_and_close, show_release_notes,
+    show_update_kind, verify_update,
+};
 use web_view::{Content, Icon, WVResult, WebView};
 #[derive(RustEmbed)]
 #[folder = "$CARGO_MANIFEST_DIR\\..\\resources"]
@@ -107,15 +111,10 @@ fn run() -> Result<(), BootstrapError> {
 
     log::info!("update type: {}", update.update_type);
 
-    if !rainway_installed {
-        log::info!("checking system compatibility.");
-        check_system_compatibility()?;
-    }
-
     //regardless of whether we need to update or install, we need the latest branch.
-    let config_branch = update.install_info.branch;
+    let config_branch = update.install_info.branch.clone();
     log::info!("user branch: {}", config_branch);
-    if let Err(e) = update.get_manifest(config_branch) {
+    if let Err(e) = update.get_manifest(&config_branch) {
         if rainway_installed {
             log::error!("unable to check for latest branch. starting currently installed version.");
             launch_rainway(&update.install_info.path);
@@ -131,16 +130,46 @@ fn run() -> Result<(), BootstrapError> {
     update.set_temp_file();
     log::debug!("temp file name: {}", update.temp_name);
 
+    // components=a,b changes which optional components are installed from now on.
+    if let Some(arg) = std::env::args().find(|x| x.starts_with("components=")) {
+        let names: Vec<String> = arg["components=".len()..]
+            .split(',')
+            .map(|c| c.to_string())
+            .collect();
+        if let Err(e) = update.select_components(&names) {
+            log::error!("unable to select components. {}", e);
+        }
+    }
+
     //check if Rainway requires an update if it's installed
     if rainway_installed {
         log::info!("validating Rainway installation.");
-        let valid = update.validate();
+        // a repair re-hashes every installed file against the manifest.
+        let repair = std::env::args().any(|x| x == "repair");
+        let valid = update.validate(repair);
         if valid {
             log::info!("Rainway is not outdated, starting.");
             launch_rainway(&update.install_info.path);
             return Ok(());
         }
         log::warn!("the current Rainway installation requires an update.");
+        log::info!("update kind: {}", update.get_update_kind());
+    }
+
+    // the requirements come from the manifest, so a release can raise them without a new bootstrapper.
+    log::info!("checking system compatibility.");
+    let install_path = if update.install_info.path.as_os_str().is_empty() {
+        PathBuf::from(std::env::var_os("ProgramFiles").unwrap_or_default())
+    } else {
+        update.install_info.path.clone()
+    };
+    if let Err(e) = check_system_compatibility(&update.manifest.requirements, &install_path) {
+        if rainway_installed {
+            log::error!("the latest release is not supported on this system. starting currently installed version. {}", e);
+            launch_rainway(&update.install_info.path);
+            return Ok(());
+        }
+        return Err(e);
     }
 
     // at this point we know we need to either update or install
@@ -222,9 +251,16 @@ fn load_resources() -> Result<Resources, BootstrapError> {
 /// handles WebView external function calls
 fn handler<T: 'static>(webview: &mut WebView<'_, T>, arg: &str, update: &ActiveUpdate) -> WVResult {
     match arg {
+        "ready" => {
+            show_release_notes(webview, update);
+            show_update_kind(webview, update);
+        }
         "download" => {
             download_update(webview, update);
         }
+        "defer" => {
+            defer_update(webview, update);
+        }
         "verify" => {
             verify_update(webview, update);
         }
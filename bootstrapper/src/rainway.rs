@@ -1,9 +1,10 @@
 use crate::ui::native::{show_error, try_elevate};
 use quiche::etc::constants::BootstrapError;
 use quiche::os::process::get_processes;
+use quiche::os::requirements::{Architecture, Requirement, RequirementReport, Requirements};
 use quiche::os::service::{install_service, service_exist, start_service, WindowsService, grant_start_access_rights};
 use quiche::os::windows::{
-    detach_rdp_session, get_dotnet_framework_version, get_system_info, WindowsVersion, is_elevated, is_run_as_admin
+    detach_rdp_session, get_system_info, is_elevated, is_run_as_admin, WindowsProbe
 };
 use std::path::PathBuf;
 use std::process;
@@ -87,20 +88,23 @@ pub fn kill_rainway() {
     }
 }
 
-/// checks if the current system is compatible with Rainway
-/// returns an error for the condition that is not met.
-pub fn check_system_compatibility() -> Result<(), BootstrapError> {
-    let system_info = get_system_info()?;
-
-    if !system_info.is_x64 {
-        log::error!("architecture unsupported");
-        return Err(BootstrapError::ArchitectureUnsupported);
+/// the requirements used when a manifest does not declare any of its own.
+fn default_requirements() -> Requirements {
+    Requirements {
+        minimum_os_version: Some("10.0".to_string()),
+        architectures: vec![Architecture::X64],
+        dotnet_release: Some(461808),
+        ..Requirements::default()
     }
+}
 
-    if system_info.windows_version != WindowsVersion::Windows10 {
-        log::error!("{} is unsupported", system_info.product_name);
-        return Err(BootstrapError::WindowsVersionUnsupported);
-    }
+/// checks if the current system meets the requirements of the release about to be installed
+/// returns an error listing every requirement that is not met.
+pub fn check_system_compatibility(
+    requirements: &Requirements,
+    install_path: &PathBuf,
+) -> Result<RequirementReport, BootstrapError> {
+    let system_info = get_system_info()?;
 
     if system_info.is_n_edition {
         log::warn!("Windows N detected.");
@@ -112,15 +116,26 @@ pub fn check_system_compatibility() -> Result<(), BootstrapError> {
         }*/
     }
 
-    let dotnet = get_dotnet_framework_version();
-    if dotnet.is_none() {
-        return Err(BootstrapError::NeedDotNetFramework);
+    let requirements = if requirements.is_empty() {
+        default_requirements()
+    } else {
+        requirements.clone()
+    };
+    let probe = WindowsProbe {
+        install_path: install_path.to_path_buf(),
+    };
+    let report = requirements.evaluate(&probe);
+    for check in &report.checks {
+        log::info!("[{}] {}", if check.passed { "PASS" } else { "FAIL" }, check);
     }
-    let dotnet_version = dotnet.unwrap_or_default();
-    if dotnet_version < 461808 {
+    if report.passed() {
+        log::info!("current system is compatible.");
+        return Ok(report);
+    }
+    // an outdated .NET Framework has its own error so the user is offered the download page.
+    let failures = report.failures();
+    if failures.iter().all(|c| c.requirement == Requirement::DotNet) {
         return Err(BootstrapError::NeedDotNetFramework);
     }
-    log::info!("The current .NET Framework version is: {}", dotnet_version);
-    log::info!("current system is compatible.");
-    Ok(())
+    Err(BootstrapError::SystemRequirementsNotMet(report.to_string()))
 }
@@ -52,6 +52,13 @@ pub fn format_callback(function_name: String, arg: String) -> String {
     return formatted_string.to_string();
 }
 
+/// Formats a callback with several arguments in to a javascript function call
+pub fn format_callback_args(function_name: &str, args: &[String]) -> String {
+    log::info!("Formatting callback: \"{}\" with {} arguments", function_name, args.len());
+    let escaped_args: Vec<String> = args.iter().map(|a| escape_string(a.to_string())).collect();
+    format!("{}({})", function_name, escaped_args.join(", "))
+}
+
 fn format_callback_result(
     result: Result<String, String>,
     callback: String,
@@ -1,5 +1,5 @@
 use crate::rainway::launch_rainway;
-use crate::ui::callback::run_async;
+use crate::ui::callback::{format_callback_args, run_async};
 
 use quiche::updater::{apply, download_with_callback, install, verify, ActiveUpdate, UpdateType};
 use web_view::WebView;
@@ -36,6 +36,59 @@ pub fn apply_update<T: 'static>(webview: &mut WebView<'_, T>, update: &ActiveUpd
     );
 }
 
+/// tells the UI what kind of update is being offered and whether the user can put it off.
+pub fn show_update_kind<T: 'static>(webview: &mut WebView<'_, T>, update: &ActiveUpdate) {
+    let remaining = match update.deferrals_remaining() {
+        Some(r) => r.to_string(),
+        None => String::default(),
+    };
+    let callback = format_callback_args(
+        "updateKind",
+        &[
+            update.get_update_kind().to_string(),
+            update.get_version(),
+            update.can_defer().to_string(),
+            remaining,
+        ],
+    );
+    if let Err(e) = webview.eval(&callback) {
+        log::warn!("unable to show the update kind. {}", e);
+    }
+}
+
+/// records that the user put the update off and starts the installed version.
+/// if the update can no longer be deferred the download is started instead.
+pub fn defer_update<T: 'static>(webview: &mut WebView<'_, T>, update: &ActiveUpdate) {
+    match update.defer() {
+        Ok(_) => launch_and_close(webview, update),
+        Err(e) => {
+            log::warn!("{}", e);
+            if let Err(e) = webview.eval("startDownload()") {
+                log::warn!("unable to start the download. {}", e);
+            }
+        }
+    }
+}
+
+/// sends the title, notes and publish date of the release being downloaded to the UI.
+pub fn show_release_notes<T: 'static>(webview: &mut WebView<'_, T>, update: &ActiveUpdate) {
+    let manifest = &update.manifest;
+    if manifest.title.is_empty() && manifest.notes.is_empty() {
+        return;
+    }
+    let callback = format_callback_args(
+        "releaseNotes",
+        &[
+            manifest.title.clone(),
+            manifest.notes.clone(),
+            manifest.published.clone(),
+        ],
+    );
+    if let Err(e) = webview.eval(&callback) {
+        log::warn!("unable to show the release notes. {}", e);
+    }
+}
+
 pub fn download_update<T: 'static>(webview: &mut WebView<'_, T>, update: &ActiveUpdate) {
     let download_complete = "downloadComplete";
     let error_callback = "downloadFailed";
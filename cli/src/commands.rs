@@ -0,0 +1,267 @@
+use crate::serve::{load_identity, run, ServeOptions};
+use clap::ArgMatches;
+use quiche::bakery::{
+    diff_manifests, promote_branch, promote_release, ramp_branch, read_toml, verify_release,
+    Recipe,
+};
+use quiche::etc::constants::BootstrapError;
+use quiche::io::sign::generate_signing_key;
+use quiche::net::http::download_toml;
+use quiche::updater::{get_releases, Manifest, ReleaseBranch, Releases};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// the command could not run to completion.
+pub const EXIT_FAILURE: i32 = 1;
+/// the command ran, but what it checked is not valid.
+pub const EXIT_INVALID: i32 = 2;
+
+/// a failed command along with the exit code the process should end with.
+/// `result` holds any output that is still useful to the caller, such as a failed verification.
+pub struct Failure {
+    pub code: i32,
+    pub message: String,
+    pub result: Value,
+}
+
+impl Failure {
+    fn new(code: i32, message: String) -> Self {
+        Failure {
+            code,
+            message,
+            result: Value::Null,
+        }
+    }
+}
+
+impl From<BootstrapError> for Failure {
+    fn from(error: BootstrapError) -> Self {
+        Failure::new(EXIT_FAILURE, error.to_string())
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(error: std::io::Error) -> Self {
+        Failure::new(EXIT_FAILURE, error.to_string())
+    }
+}
+
+pub type CommandResult = Result<Value, Failure>;
+
+fn to_json<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+/// prepares, bakes and stages a release from a recipe.
+pub fn bake(matches: &ArgMatches<'_>) -> CommandResult {
+    let recipe_path = Path::new(matches.value_of("recipe").unwrap_or_default());
+    if !recipe_path.is_file() {
+        return Err(Failure::new(
+            EXIT_FAILURE,
+            format!("The recipe at {} does not exist.", recipe_path.display()),
+        ));
+    }
+    let mut recipe = Recipe::read(recipe_path)?;
+    log::info!("read Quiche recipe from {}", recipe_path.display());
+    if let Err(e) = recipe.prepare() {
+        return Err(Failure::new(
+            EXIT_FAILURE,
+            format!("the recipe ingredients could not be prepared. {}", e),
+        ));
+    }
+    log::info!("Quiche recipe prepared. attempted to bake.");
+
+    let dinner = recipe.bake()?;
+    let manifest = to_json(&dinner.manifest);
+    recipe.stage(dinner)?;
+    log::info!("dinner is served! the release was successfully baked.");
+    Ok(json!({
+        "version": recipe.version,
+        "branch": recipe.branch.as_str(),
+        "output_dir": recipe.output_dir,
+        "manifest": manifest,
+    }))
+}
+
+/// re-checks the signature and artifacts of a staged release directory.
+pub fn verify(matches: &ArgMatches<'_>) -> CommandResult {
+    let release_dir = Path::new(matches.value_of("release").unwrap_or_default());
+    let check = verify_release(release_dir)?;
+    log::info!("[{}] manifest.toml signature", status(check.signed));
+    for artifact in &check.artifacts {
+        log::info!("[{}] {}", status(artifact.valid), artifact.name);
+    }
+    if !check.is_valid() {
+        return Err(Failure {
+            code: EXIT_INVALID,
+            message: format!("the release {} failed verification.", check.version),
+            result: to_json(&check),
+        });
+    }
+    log::info!("the release {} is valid.", check.version);
+    Ok(to_json(&check))
+}
+
+/// points a branch at a staged release, or at the release another branch is currently on.
+pub fn promote(matches: &ArgMatches<'_>) -> CommandResult {
+    let to = branch_value(matches, "to")?;
+    let signing_key = matches.value_of("signing-key").map(Path::new);
+    let releases = match matches.value_of("release") {
+        Some(release_dir) => promote_release(Path::new(release_dir), to.clone(), signing_key)?,
+        None => {
+            let output_root = Path::new(matches.value_of("output").unwrap_or_default());
+            let from = branch_value(matches, "from")?;
+            promote_branch(output_root, from, to.clone(), signing_key)?
+        }
+    };
+    log::info!("{} now points at {}.", to, releases.get(&to)?.version);
+    Ok(to_json(&releases))
+}
+
+/// changes the percentage of machines a branch offers its release to.
+pub fn ramp(matches: &ArgMatches<'_>) -> CommandResult {
+    let output_root = Path::new(matches.value_of("output").unwrap_or_default());
+    let branch = branch_value(matches, "branch")?;
+    let percentage = match matches.value_of("percent").unwrap_or_default().parse::<u8>() {
+        Ok(p) => p,
+        Err(e) => return Err(Failure::new(EXIT_FAILURE, e.to_string())),
+    };
+    let signing_key = matches.value_of("signing-key").map(Path::new);
+    let releases = ramp_branch(output_root, branch.clone(), percentage, signing_key)?;
+    log::info!(
+        "{} now offers {} to {}% of machines.",
+        branch,
+        releases.get(&branch)?.version,
+        percentage
+    );
+    Ok(to_json(&releases))
+}
+
+/// shows what release each branch points at, either locally or on the release host.
+pub fn list(matches: &ArgMatches<'_>) -> CommandResult {
+    let releases = match matches.value_of("output") {
+        Some(output_root) => {
+            let mut release_path = PathBuf::from(output_root);
+            release_path.push("Releases.toml");
+            read_toml::<Releases>(&release_path)?
+        }
+        None => get_releases()?,
+    };
+    for (branch, release) in &releases.branches {
+        log::info!("{} == {} ({})", branch, release.version, release.manifest_url);
+        if let (Some(percentage), Some(fallback)) = (release.rollout, &release.fallback) {
+            log::info!(
+                "{} is rolling out to {}% of machines, the rest stay on {}.",
+                branch,
+                percentage,
+                fallback.version
+            );
+        }
+    }
+    Ok(to_json(&releases))
+}
+
+/// compares the files of two manifest, each of which can be a path or a URL.
+pub fn diff(matches: &ArgMatches<'_>) -> CommandResult {
+    let from = load_manifest(matches.value_of("from").unwrap_or_default())?;
+    let to = load_manifest(matches.value_of("to").unwrap_or_default())?;
+    let diff = diff_manifests(&from, &to);
+    for file in &diff.added {
+        log::info!("[ADDED] {}", file);
+    }
+    for file in &diff.removed {
+        log::info!("[REMOVED] {}", file);
+    }
+    for file in &diff.changed {
+        log::info!("[CHANGED] {}", file);
+    }
+    log::info!(
+        "{} -> {}: {} added, {} removed, {} changed, {} unchanged, {} bytes.",
+        diff.from_version,
+        diff.to_version,
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len(),
+        diff.unchanged,
+        diff.size_change
+    );
+    Ok(to_json(&diff))
+}
+
+/// serves an output directory over http and https until the process is stopped.
+pub fn serve(matches: &ArgMatches<'_>) -> CommandResult {
+    let root = PathBuf::from(matches.value_of("output").unwrap_or_default());
+    if !root.is_dir() {
+        return Err(Failure::new(
+            EXIT_FAILURE,
+            format!("{} is not a directory.", root.display()),
+        ));
+    }
+    let address: IpAddr = match matches.value_of("address").unwrap_or_default().parse() {
+        Ok(a) => a,
+        Err(e) => return Err(Failure::new(EXIT_FAILURE, e.to_string())),
+    };
+    let port = |name: &str| matches.value_of(name).unwrap_or_default().parse().unwrap_or(0);
+    let hostnames = matches
+        .values_of("hostname")
+        .map(|v| v.map(String::from).collect())
+        .unwrap_or_default();
+    let identity = match load_identity(
+        Path::new(matches.value_of("cert").unwrap_or_default()),
+        Path::new(matches.value_of("key").unwrap_or_default()),
+        hostnames,
+    ) {
+        Ok(i) => i,
+        Err(e) => return Err(Failure::new(EXIT_FAILURE, e)),
+    };
+    let options = ServeOptions {
+        root: root.clone(),
+        latency: Duration::from_millis(matches.value_of("latency").unwrap_or_default().parse().unwrap_or(0)),
+        fail_rate: matches.value_of("fail-rate").unwrap_or_default().parse().unwrap_or(0),
+    };
+    let http_address = SocketAddr::new(address, port("port"));
+    let https_address = SocketAddr::new(address, port("https-port"));
+    match run(options, http_address, https_address, identity) {
+        Ok(_) => Ok(json!({ "root": root })),
+        Err(e) => Err(Failure::new(EXIT_FAILURE, e)),
+    }
+}
+
+/// generates a new key for signing release metadata.
+pub fn keygen(matches: &ArgMatches<'_>) -> CommandResult {
+    let key_path = Path::new(matches.value_of("key").unwrap_or_default());
+    let public_key = generate_signing_key(key_path)?;
+    log::info!(
+        "wrote a new signing key to {}. add \"{}\" to release_public_keys before building clients.",
+        key_path.display(),
+        public_key
+    );
+    Ok(json!({
+        "path": key_path,
+        "public_key": public_key,
+    }))
+}
+
+fn load_manifest(source: &str) -> Result<Manifest, BootstrapError> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        download_toml::<Manifest>(source)
+    } else {
+        read_toml::<Manifest>(Path::new(source))
+    }
+}
+
+/// parses a branch name argument, any name made of letters, digits, dashes and underscores is valid.
+fn branch_value(matches: &ArgMatches<'_>, name: &str) -> Result<ReleaseBranch, BootstrapError> {
+    matches.value_of(name).unwrap_or_default().parse()
+}
+
+fn status(valid: bool) -> &'static str {
+    if valid {
+        "OK"
+    } else {
+        "INVALID"
+    }
+}
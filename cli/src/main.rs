@@ -1,52 +1,222 @@
-use clap::{App, Arg};
+mod commands;
+mod serve;
+
+use clap::{App, AppSettings, Arg, SubCommand};
+use commands::Failure;
+use quiche::updater::ReleaseBranch;
 use fern::colors::{Color, ColoredLevelConfig};
-use quiche::bakery::Recipe;
+use serde_json::json;
 use std::env;
 use std::fs::File;
-use std::path::Path;
 
 fn main() {
-    println!("{}", LOGO);
-
     let matches = App::new("Quiche CLI")
         .version("1.0")
         .author("Andrew Sampson <andrew@rainway.com>")
         .about("Build and fetch Rainway releases with ease.")
-        .arg(
-            Arg::with_name("recipe")
-                .short("r")
-                .long("recipe")
-                .value_name("FILE")
-                .required(true)
-                .help("Sets a recipe for a release")
-                .takes_value(true),
-        )
+        .setting(AppSettings::SubcommandRequiredElseHelp)
         .arg(
             clap::Arg::with_name("verbose")
                 .short("v")
                 .long("verbose")
+                .global(true)
                 .help("Increases logging verbosity each use for up to 3 times"),
         )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("Writes the result as JSON to stdout, logs are written to stderr"),
+        )
         .arg(
             Arg::with_name("release-override")
                 .short("u")
                 .value_name("URL")
+                .global(true)
                 .help("Overrides the default release URL.")
                 .takes_value(true),
         )
+        .subcommand(
+            SubCommand::with_name("bake")
+                .about("Bakes and stages a release from a recipe")
+                .arg(
+                    Arg::with_name("recipe")
+                        .short("r")
+                        .long("recipe")
+                        .value_name("FILE")
+                        .required(true)
+                        .help("Sets a recipe for a release")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Re-checks the signature and artifacts of a staged release")
+                .arg(
+                    Arg::with_name("release")
+                        .value_name("RELEASE_DIR")
+                        .required(true)
+                        .help("The directory of a staged release, e.g. <output_dir>/nightly/2.0.0"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("promote")
+                .about("Points a branch at a staged release without re-baking it")
+                .arg(
+                    Arg::with_name("release")
+                        .long("release")
+                        .value_name("RELEASE_DIR")
+                        .conflicts_with_all(&["from", "output"])
+                        .help("The directory of a staged release, e.g. <output_dir>/nightly/2.0.0")
+                        .takes_value(true),
+                )
+                .arg(output_arg().required_unless("release"))
+                .arg(
+                    branch_arg("from")
+                        .required_unless("release")
+                        .help("The branch holding the release to promote"),
+                )
+                .arg(
+                    branch_arg("to")
+                        .required(true)
+                        .help("The branch that will serve the release"),
+                )
+                .arg(signing_key_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("ramp")
+                .about("Changes the percentage of machines a branch offers its release to")
+                .arg(output_arg().required(true))
+                .arg(
+                    branch_arg("branch")
+                        .required(true)
+                        .help("The branch that is rolling out a release"),
+                )
+                .arg(
+                    Arg::with_name("percent")
+                        .value_name("PERCENT")
+                        .required(true)
+                        .validator(|v| match v.parse::<u8>() {
+                            Ok(p) if p <= 100 => Ok(()),
+                            _ => Err("must be a whole number between 0 and 100".to_string()),
+                        })
+                        .help("0 halts the rollout, 100 completes it"),
+                )
+                .arg(signing_key_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Shows what release each branch points at")
+                .arg(output_arg().help("Reads Releases.toml from a local output directory instead of the release host")),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compares the files of two manifest")
+                .arg(
+                    Arg::with_name("from")
+                        .value_name("FROM")
+                        .required(true)
+                        .help("The path or URL of the older manifest.toml"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .value_name("TO")
+                        .required(true)
+                        .help("The path or URL of the newer manifest.toml"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Serves an output directory over HTTP and HTTPS for local testing")
+                .arg(output_arg().required(true))
+                .arg(
+                    Arg::with_name("address")
+                        .long("address")
+                        .value_name("IP")
+                        .default_value("127.0.0.1")
+                        .help("The address to listen on")
+                        .takes_value(true),
+                )
+                .arg(port_arg("port", "8080").help("The port to serve HTTP on"))
+                .arg(port_arg("https-port", "8443").help("The port to serve HTTPS on"))
+                .arg(
+                    Arg::with_name("cert")
+                        .long("cert")
+                        .value_name("FILE")
+                        .default_value("serve.crt")
+                        .help("The PEM certificate for HTTPS, a self-signed one is written here if it is missing")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("key")
+                        .long("key")
+                        .value_name("FILE")
+                        .default_value("serve.key")
+                        .help("The PKCS#8 PEM key of the certificate")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("hostname")
+                        .long("hostname")
+                        .value_name("NAME")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .default_value("localhost")
+                        .help("A hostname a generated certificate is valid for")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("latency")
+                        .long("latency")
+                        .value_name("MS")
+                        .default_value("0")
+                        .validator(|v| match v.parse::<u64>() {
+                            Ok(_) => Ok(()),
+                            Err(_) => Err("must be a number of milliseconds".to_string()),
+                        })
+                        .help("Delays every response")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("fail-rate")
+                        .long("fail-rate")
+                        .value_name("PERCENT")
+                        .default_value("0")
+                        .validator(|v| match v.parse::<u8>() {
+                            Ok(p) if p <= 100 => Ok(()),
+                            _ => Err("must be a whole number between 0 and 100".to_string()),
+                        })
+                        .help("The percentage of requests that fail with 503")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("keygen")
+                .about("Generates an Ed25519 key for signing release metadata")
+                .arg(
+                    Arg::with_name("key")
+                        .value_name("FILE")
+                        .required(true)
+                        .help("Where the secret key will be written"),
+                ),
+        )
         .get_matches();
 
-    let verbosity: u64 = matches.occurrences_of("verbose");
-    setup_logging(verbosity).expect("failed to initialize logging.");
+    let json = matches.is_present("json");
+    if !json {
+        println!("{}", LOGO);
+    }
 
-    log::debug!("checking for recipe file.");
-    let recipe_path = match matches.value_of("recipe") {
-        Some(s) => Path::new(s),
-        None => panic!("The provided recipe path is empty."),
+    let (command, command_matches) = matches.subcommand();
+    let command_matches = match command_matches {
+        Some(m) => m,
+        None => unreachable!("a subcommand is required."),
     };
+    let verbosity: u64 = command_matches.occurrences_of("verbose");
+    setup_logging(verbosity, json).expect("failed to initialize logging.");
 
     log::debug!("checking for release host override.");
-    let release_override = matches.value_of("release-override").unwrap_or("");
+    let release_override = command_matches.value_of("release-override").unwrap_or("");
     if !release_override.is_empty() {
         env::set_var("RELEASE_OVERRIDE", release_override);
         log::info!(
@@ -55,30 +225,85 @@ fn main() {
         );
     }
 
-    let mut recipe = Recipe::from(recipe_path);
-    log::info!("read Quiche recipe from {}", recipe_path.display());
-    if let Err(e) = recipe.prepare() {
-        log::error!("the recipe ingredients could not be prepared. {}", e);
-        panic!("preparations failed.");
-    }
-    log::info!("Quiche recipe prepared. attempted to bake.");
-
-    let dinner = match recipe.bake() {
-        Ok(d) => d,
-        Err(e) => {
-            log::error!("the recipe failed to bake properly. {}", e);
-            panic!("bake failure.");
-        }
+    let result = match command {
+        "bake" => commands::bake(command_matches),
+        "verify" => commands::verify(command_matches),
+        "promote" => commands::promote(command_matches),
+        "ramp" => commands::ramp(command_matches),
+        "list" => commands::list(command_matches),
+        "diff" => commands::diff(command_matches),
+        "serve" => commands::serve(command_matches),
+        "keygen" => commands::keygen(command_matches),
+        _ => unreachable!("unknown subcommand {}", command),
     };
 
-    if let Err(e) = recipe.stage(dinner) {
-        log::error!("the recipe could not be staged. {}", e);
-        panic!("stage failure.");
+    match result {
+        Ok(output) => {
+            if json {
+                println!("{}", json!({ "success": true, "result": output }));
+            }
+        }
+        Err(Failure {
+            code,
+            message,
+            result,
+        }) => {
+            log::error!("{} failed. {}", command, message);
+            if json {
+                println!(
+                    "{}",
+                    json!({ "success": false, "error": message, "result": result })
+                );
+            }
+            std::process::exit(code);
+        }
     }
-    log::info!("dinner is served! the release was successfully baked.");
 }
 
-fn setup_logging(verbosity: u64) -> Result<(), fern::InitError> {
+/// the root output directory that holds Releases.toml, the same as a recipe's output_dir.
+fn output_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("output")
+        .short("o")
+        .long("output")
+        .value_name("DIR")
+        .help("The output directory releases are staged in")
+        .takes_value(true)
+}
+
+fn branch_arg<'a, 'b>(name: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name(name)
+        .long(name)
+        .value_name("BRANCH")
+        .validator(|v| match v.parse::<ReleaseBranch>() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        })
+        .takes_value(true)
+}
+
+fn port_arg<'a, 'b>(name: &'a str, default: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name(name)
+        .long(name)
+        .value_name("PORT")
+        .default_value(default)
+        .validator(|v| match v.parse::<u16>() {
+            Ok(_) => Ok(()),
+            Err(_) => Err("must be a port number".to_string()),
+        })
+        .takes_value(true)
+}
+
+fn signing_key_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("signing-key")
+        .short("k")
+        .long("signing-key")
+        .value_name("FILE")
+        .help("Signs the updated Releases.toml with the given key")
+        .takes_value(true)
+}
+
+/// when `json` is set, stdout is reserved for the result so logs are written to stderr.
+fn setup_logging(verbosity: u64, json: bool) -> Result<(), fern::InitError> {
     let colors = ColoredLevelConfig::new()
         .trace(Color::BrightCyan)
         .debug(Color::BrightMagenta)
@@ -94,6 +319,8 @@ fn setup_logging(verbosity: u64) -> Result<(), fern::InitError> {
         2 => base_config.level(log::LevelFilter::Warn),
         _3_or_more => base_config.level(log::LevelFilter::Error),
     };
+    // hyper logs every read and write of a connection at debug, which drowns out `serve`.
+    base_config = base_config.level_for("hyper", log::LevelFilter::Info);
 
     // Separate file config so we can include colors in the terminal
     let file_config = fern::Dispatch::new()
@@ -107,20 +334,23 @@ fn setup_logging(verbosity: u64) -> Result<(), fern::InitError> {
         })
         .chain(File::create(format!("{}.log", env!("CARGO_PKG_NAME")))?);
 
-    let stdout_config = fern::Dispatch::new()
-        .format(move |out, message, record| {
-            out.finish(format_args!(
-                "[{}][{}] {}",
-                record.target(),
-                colors.color(record.level()),
-                message
-            ))
-        })
-        .chain(std::io::stdout());
+    let console_config = fern::Dispatch::new().format(move |out, message, record| {
+        out.finish(format_args!(
+            "[{}][{}] {}",
+            record.target(),
+            colors.color(record.level()),
+            message
+        ))
+    });
+    let console_config = if json {
+        console_config.chain(std::io::stderr())
+    } else {
+        console_config.chain(std::io::stdout())
+    };
 
     base_config
         .chain(file_config)
-        .chain(stdout_config)
+        .chain(console_config)
         .apply()?;
 
     Ok(())
@@ -0,0 +1,426 @@
+use hyper::body::Bytes;
+use hyper::header::{self, HeaderValue};
+use hyper::server::conn::Http;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use native_tls::Identity;
+use rand::Rng;
+use std::convert::Infallible;
+use std::io::SeekFrom;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+
+/// how many bytes of a file are read before they are sent on to the client.
+const SEND_BUFFER_SIZE: usize = 64 * 1024;
+
+/// what a local release server serves and how badly it behaves.
+pub struct ServeOptions {
+    /// the output directory of a recipe, requests are resolved against it.
+    pub root: PathBuf,
+    /// how long every request is held before it is answered.
+    pub latency: Duration,
+    /// the percentage of requests that fail with 503 Service Unavailable.
+    pub fail_rate: u8,
+}
+
+impl ServeOptions {
+    fn should_fail(&self) -> bool {
+        self.fail_rate > 0 && rand::thread_rng().gen_range(0, 100) < self.fail_rate
+    }
+}
+
+/// loads the certificate and PKCS#8 key used for https.
+/// when either is missing a self-signed certificate for the given hostnames is generated and saved in their place,
+/// so clients only have to trust it once.
+pub fn load_identity(
+    cert_path: &Path,
+    key_path: &Path,
+    hostnames: Vec<String>,
+) -> Result<Identity, String> {
+    if !cert_path.is_file() || !key_path.is_file() {
+        log::info!(
+            "generating a self-signed certificate for {}.",
+            hostnames.join(", ")
+        );
+        let certificate = match rcgen::generate_simple_self_signed(hostnames) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("unable to generate a certificate. {}", e)),
+        };
+        let pem = match certificate.serialize_pem() {
+            Ok(p) => p,
+            Err(e) => return Err(format!("unable to serialize the certificate. {}", e)),
+        };
+        if let Err(e) = std::fs::write(cert_path, pem) {
+            return Err(format!("unable to write {}. {}", cert_path.display(), e));
+        }
+        if let Err(e) = std::fs::write(key_path, certificate.serialize_private_key_pem()) {
+            return Err(format!("unable to write {}. {}", key_path.display(), e));
+        }
+    }
+    let cert = match std::fs::read(cert_path) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("unable to read {}. {}", cert_path.display(), e)),
+    };
+    let key = match std::fs::read(key_path) {
+        Ok(k) => k,
+        Err(e) => return Err(format!("unable to read {}. {}", key_path.display(), e)),
+    };
+    match Identity::from_pkcs8(&cert, &key) {
+        Ok(i) => Ok(i),
+        Err(e) => Err(format!("unable to load the certificate. {}", e)),
+    }
+}
+
+/// serves the output directory over http and https until the process is stopped.
+pub fn run(
+    options: ServeOptions,
+    http_address: SocketAddr,
+    https_address: SocketAddr,
+    identity: Identity,
+) -> Result<(), String> {
+    let acceptor = match native_tls::TlsAcceptor::new(identity) {
+        Ok(a) => tokio_tls::TlsAcceptor::from(a),
+        Err(e) => return Err(format!("unable to set up https. {}", e)),
+    };
+    let mut runtime = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => return Err(e.to_string()),
+    };
+    let options = Arc::new(options);
+    runtime.block_on(async move {
+        let mut listener = match TcpListener::bind(https_address).await {
+            Ok(l) => l,
+            Err(e) => return Err(format!("unable to listen on {}. {}", https_address, e)),
+        };
+        let https_options = options.clone();
+        tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((s, _)) => s,
+                    Err(e) => {
+                        log::warn!("unable to accept a connection. {}", e);
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let options = https_options.clone();
+                tokio::spawn(async move {
+                    let stream = match acceptor.accept(stream).await {
+                        Ok(s) => s,
+                        Err(e) => {
+                            log::warn!("tls handshake failed. {}", e);
+                            return;
+                        }
+                    };
+                    let service = service_fn(move |request| respond(request, options.clone()));
+                    if let Err(e) = Http::new().serve_connection(stream, service).await {
+                        log::warn!("https connection closed. {}", e);
+                    }
+                });
+            }
+        });
+
+        let http_options = options.clone();
+        let server = match Server::try_bind(&http_address) {
+            Ok(b) => b.serve(make_service_fn(move |_| {
+                let options = http_options.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| {
+                        respond(request, options.clone())
+                    }))
+                }
+            })),
+            Err(e) => return Err(format!("unable to listen on {}. {}", http_address, e)),
+        };
+        log::info!(
+            "serving {} on http://{} and https://{}.",
+            options.root.display(),
+            http_address,
+            https_address
+        );
+        match server.await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    })
+}
+
+async fn respond(
+    request: Request<Body>,
+    options: Arc<ServeOptions>,
+) -> Result<Response<Body>, Infallible> {
+    if options.latency > Duration::from_millis(0) {
+        tokio::time::delay_for(options.latency).await;
+    }
+    let response = if options.should_fail() {
+        empty_response(StatusCode::SERVICE_UNAVAILABLE)
+    } else {
+        serve_file(&request, &options.root).await
+    };
+    log::info!(
+        "{} {} {}",
+        request.method(),
+        request.uri().path(),
+        response.status().as_u16()
+    );
+    Ok(response)
+}
+
+async fn serve_file(request: &Request<Body>, root: &Path) -> Response<Body> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        let mut response = empty_response(StatusCode::METHOD_NOT_ALLOWED);
+        response
+            .headers_mut()
+            .insert(header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+        return response;
+    }
+    let path = match resolve_path(root, request.uri().path()) {
+        Some(p) => p,
+        None => return empty_response(StatusCode::BAD_REQUEST),
+    };
+    let metadata = match tokio::fs::metadata(&path).await {
+        Ok(m) if m.is_file() => m,
+        _ => return empty_response(StatusCode::NOT_FOUND),
+    };
+    let len = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let etag = format!("\"{:x}-{:x}\"", len, modified);
+
+    let headers = request.headers();
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        if etag_matches(if_none_match.to_str().unwrap_or_default(), &etag) {
+            let mut response = empty_response(StatusCode::NOT_MODIFIED);
+            insert_header(&mut response, header::ETAG, &etag);
+            return response;
+        }
+    }
+    // a range only applies to the file the client already has part of.
+    let range_applies = match headers.get(header::IF_RANGE) {
+        Some(if_range) => if_range.to_str().unwrap_or_default() == etag,
+        None => true,
+    };
+    let range = match headers.get(header::RANGE) {
+        Some(range) if range_applies => parse_range(range.to_str().unwrap_or_default(), len),
+        _ => Ok(None),
+    };
+
+    let mut response = empty_response(StatusCode::OK);
+    insert_header(&mut response, header::ETAG, &etag);
+    insert_header(&mut response, header::ACCEPT_RANGES, "bytes");
+    insert_header(&mut response, header::CONTENT_TYPE, content_type(&path));
+    let (start, end) = match range {
+        Ok(Some((start, end))) => {
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            insert_header(
+                &mut response,
+                header::CONTENT_RANGE,
+                &format!("bytes {}-{}/{}", start, end, len),
+            );
+            (start, end + 1)
+        }
+        Ok(None) => (0, len),
+        Err(_) => {
+            let mut response = empty_response(StatusCode::RANGE_NOT_SATISFIABLE);
+            insert_header(
+                &mut response,
+                header::CONTENT_RANGE,
+                &format!("bytes */{}", len),
+            );
+            return response;
+        }
+    };
+    insert_header(
+        &mut response,
+        header::CONTENT_LENGTH,
+        &(end - start).to_string(),
+    );
+    if request.method() == Method::HEAD {
+        return response;
+    }
+
+    let mut file = match tokio::fs::File::open(&path).await {
+        Ok(f) => f,
+        Err(_) => return empty_response(StatusCode::NOT_FOUND),
+    };
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        if file.seek(SeekFrom::Start(start)).await.is_err() {
+            sender.abort();
+            return;
+        }
+        let mut remaining = end - start;
+        let mut buffer = vec![0u8; SEND_BUFFER_SIZE];
+        while remaining > 0 {
+            let max = std::cmp::min(remaining, buffer.len() as u64) as usize;
+            let read = match file.read(&mut buffer[..max]).await {
+                Ok(r) if r > 0 => r,
+                _ => {
+                    sender.abort();
+                    return;
+                }
+            };
+            if sender
+                .send_data(Bytes::copy_from_slice(&buffer[..read]))
+                .await
+                .is_err()
+            {
+                return;
+            }
+            remaining -= read as u64;
+        }
+    });
+    *response.body_mut() = body;
+    response
+}
+
+/// resolves the path of a request inside the root, None if it tries to leave it.
+/// segments are decoded before they are checked, so an encoded separator cannot start a new path.
+fn resolve_path(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for segment in request_path.split('/') {
+        let segment = percent_decode(segment)?;
+        match segment.as_str() {
+            "" | "." => continue,
+            ".." => return None,
+            s if s.contains(|c| c == '/' || c == '\\' || c == ':' || c == '\0') => return None,
+            s => {
+                let mut components = Path::new(s).components();
+                match (components.next(), components.next()) {
+                    (Some(Component::Normal(c)), None) => path.push(c),
+                    _ => return None,
+                }
+            }
+        }
+    }
+    if !path.starts_with(root) {
+        return None;
+    }
+    Some(path)
+}
+
+fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// returns true if an If-None-Match header names the etag, weak comparison is used as it is for GET.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|t| t.trim())
+        .any(|t| t == "*" || t.trim_start_matches("W/") == etag)
+}
+
+/// parses a `bytes=` range header against the length of a file into the first and last byte to send.
+/// returns None when the whole file should be sent, which includes multiple ranges as they are not supported,
+/// and an error when the range is past the end of the file.
+fn parse_range(range: &str, len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let spec = match range.trim().strip_prefix("bytes=") {
+        Some(s) if !s.contains(',') => s.trim(),
+        _ => return Ok(None),
+    };
+    let (first, last) = match spec.find('-') {
+        Some(i) => (&spec[..i], &spec[i + 1..]),
+        None => return Ok(None),
+    };
+    if first.is_empty() {
+        // a suffix range asks for the last n bytes.
+        return match last.parse::<u64>() {
+            Ok(n) if n > 0 && len > 0 => Ok(Some((len.saturating_sub(n), len - 1))),
+            Ok(_) => Err(()),
+            Err(_) => Ok(None),
+        };
+    }
+    let start = match first.parse::<u64>() {
+        Ok(s) => s,
+        Err(_) => return Ok(None),
+    };
+    let end = match last {
+        "" => u64::MAX,
+        l => match l.parse::<u64>() {
+            Ok(e) if e >= start => e,
+            _ => return Ok(None),
+        },
+    };
+    if start >= len {
+        return Err(());
+    }
+    Ok(Some((start, std::cmp::min(end, len - 1))))
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => "application/toml",
+        Some("zip") => "application/zip",
+        Some("json") => "application/json",
+        Some("html") => "text/html",
+        Some("exe") => "application/vnd.microsoft.portable-executable",
+        Some("msi") => "application/x-msi",
+        _ => "application/octet-stream",
+    }
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+fn insert_header(response: &mut Response<Body>, name: header::HeaderName, value: &str) {
+    if let Ok(v) = HeaderValue::from_str(value) {
+        response.headers_mut().insert(name, v);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_range, resolve_path};
+    use std::path::Path;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some((0, 99))));
+        assert_eq!(parse_range("bytes=900-", 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range("bytes=500-5000", 1000), Ok(Some((500, 999))));
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(()));
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), Ok(None));
+        assert_eq!(parse_range("bytes=9-5", 1000), Ok(None));
+    }
+
+    #[test]
+    fn traversal() {
+        let root = Path::new("out");
+        assert_eq!(
+            resolve_path(root, "/nightly/2.0.0/package.zip"),
+            Some(root.join("nightly").join("2.0.0").join("package.zip"))
+        );
+        assert_eq!(resolve_path(root, "/nightly/../../secret"), None);
+        assert_eq!(resolve_path(root, "/%2e%2e/secret"), None);
+        assert_eq!(resolve_path(root, "/C:%5Cwindows"), None);
+        assert_eq!(resolve_path(root, "/%2Fetc%2Fpasswd"), None);
+        assert_eq!(resolve_path(root, "/nightly/%2f%2e%2e/secret"), None);
+        assert_eq!(resolve_path(root, "/%2f%2e%2e%2f%2e%2e/secret"), None);
+    }
+}
@@ -11,10 +11,8 @@ pub fn find_cargo_field(key: &'static str) -> &'static str {
 }
 
 fn main() {
-    if !cfg!(target_os = "windows") {
-        panic!("Only Windows builds are supported.");
-    }
-
+    // other hosts build the bakery and run the tests, the Windows only modules are
+    // replaced by `os::unsupported` there so the updater cannot install or update.
     let mut api_origin = "";
 
     let profile = std::env::var("PROFILE").unwrap();
@@ -71,4 +69,21 @@ fn main() {
         "cargo:rustc-env=RAINWAY_KEY={}",
         find_cargo_field("rainway_key")
     );
+    // the keys can also be passed in by the environment, so release builds do not need Cargo.toml edited.
+    println!("cargo:rerun-if-env-changed=RELEASE_PUBLIC_KEYS");
+    let release_public_keys = match std::env::var("RELEASE_PUBLIC_KEYS") {
+        Ok(keys) => keys,
+        Err(_) => find_cargo_field("release_public_keys").to_string(),
+    };
+    // a client without keys rejects every Releases.toml and manifest, so it could never update.
+    if release_public_keys.trim().is_empty() {
+        if profile == "release" {
+            panic!("Set release_public_keys in quiche/Cargo.toml or RELEASE_PUBLIC_KEYS for production releases.");
+        }
+        println!("cargo:warning=no release public keys are set, this build will refuse every release.");
+    }
+    println!(
+        "cargo:rustc-env=RELEASE_PUBLIC_KEYS={}",
+        release_public_keys
+    );
 }
@@ -1,11 +1,16 @@
 use crate::io::ico::IcoError;
-use crate::updater::{ReleaseBranch, UpdateType};
+use crate::updater::{ReleaseBranch, UpdateKind, UpdateType};
+use std::convert::TryFrom;
 use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug)]
 pub enum BootstrapError {
     RecipeBakeFailure(String),
     RecipeStageFailure(String),
+    ReleasePromotionFailure(String),
+    RolloutFailure(String),
+    ReleasesChanged(String),
     ElevationRequired,
     ServiceConnectionFailure,
     ServiceOpenFailure,
@@ -17,16 +22,23 @@ pub enum BootstrapError {
     WindowsVersionUnsupported,
     NeedWindowsMediaPack(String),
     NeedDotNetFramework,
+    SystemRequirementsNotMet(String),
     RegistryKeyNotFound(String),
     RegistryValueNotFound(String),
     HttpFailed(String),
     LocalVersionMissing,
     InstallPathMissing,
     ReleaseLookupFailed(String),
+    InvalidBranchName(String),
+    InvalidInstalledBranch(String),
+    UnknownBranch(String),
+    UnknownComponent(String),
+    UpdateNotDeferrable(String),
     VersionCheckFailed(String, String),
     TomlParseFailure(String, String),
     BootstrapperExist,
     SignatureMismatch,
+    MetadataSignatureInvalid(String),
     RemoteFileMissing(String),
     RemoteFileEmpty(String),
     InstallationFailed(String),
@@ -49,6 +61,9 @@ impl fmt::Display for BootstrapError {
         match *self {
             BootstrapError::RecipeBakeFailure(ref s) => write!(f, "Unable to complete release build due to baking issue: {0}", s),
             BootstrapError::RecipeStageFailure(ref s) => write!(f, "Unable to complete release build due to staging issue: {0}", s),
+            BootstrapError::ReleasePromotionFailure(ref s) => write!(f, "Unable to promote the release: {0}", s),
+            BootstrapError::RolloutFailure(ref s) => write!(f, "Unable to change the rollout: {0}", s),
+            BootstrapError::ReleasesChanged(ref s) => write!(f, "{0} changed after it was read, another publisher may have updated it. Run the command again to build on their changes.", s),
             BootstrapError::ElevationRequired => write!(f, "Please run the Rainway Boostrapper as Administrator."),
             BootstrapError::DismFailed(ref s) => write!(f, "DISM failed to launch: {0}", s),
             BootstrapError::ServiceConnectionFailure => write!(f, "Failed to connect to the system service manager."),
@@ -59,6 +74,7 @@ impl fmt::Display for BootstrapError {
             BootstrapError::ArchitectureUnsupported => write!(f, "Rainway is currently only supported by x64 operating systems."),
             BootstrapError::WindowsVersionUnsupported => write!(f, "Rainway is currently only supported on Windows 10 and Windows Server 2016+."),
             BootstrapError::NeedWindowsMediaPack(ref s) => write!(f, "A required video codec is missing from your system. Please inslaunchtall the Windows Media Pack for {}.\n\nPress \"Ok\" to open the codec download page.", s),
+            BootstrapError::SystemRequirementsNotMet(ref s) => write!(f, "Your computer does not meet the system requirements for this version of Rainway.\n\n{}", s),
             BootstrapError::NeedDotNetFramework => write!(f, ".NET Framework 4.7.2 is missing from your computer and is required to install Rainway.\n\nPress \"Ok\" to open the .NET Framework download page."),
             BootstrapError::RegistryKeyNotFound(ref s) => write!(f, "An error occured accessing Windows Registry key: {}.", s),
             BootstrapError::RegistryValueNotFound(ref s) => write!(f, "An error occured accessing Windows Registry value: {}.", s),
@@ -66,6 +82,7 @@ impl fmt::Display for BootstrapError {
             BootstrapError::VersionCheckFailed(ref rv, ref lv) => write!(f, "Unable to compare remote version ({}) to installed version ({}).", rv, lv),
             BootstrapError::TomlParseFailure(ref s, ref e) => write!(f, "An exception was encountered parsing a remote file located at {} due to {}", s, e),
             BootstrapError::SignatureMismatch => write!(f, "We were unable to validate the updates integrity. Please exit and try again."),
+            BootstrapError::MetadataSignatureInvalid(ref s) => write!(f, "The release information at {} is not signed by a trusted key.", s),
             BootstrapError::RemoteFileMissing(ref s) => write!(f, "The remote file requested ({}) is not present at the address provided.", s),
             BootstrapError::RemoteFileEmpty(ref s) => write!(f, "The remote file requested ({}) is has a zero byte length.", s),
             BootstrapError::InstallationFailed(ref s) => write!(f, "An error occured installing the latest update: {0}", s),
@@ -75,6 +92,11 @@ impl fmt::Display for BootstrapError {
             BootstrapError::WebView(ref e) => write!(f, "An unknown UI issue was encountered: {0}", e),
             BootstrapError::LocalVersionMissing => write!(f, "Unable to locate the version of the currently intalled branch."),
             BootstrapError::InstallPathMissing => write!(f, "Unable to locate the installation path of the currently intalled branch."),
+            BootstrapError::InvalidBranchName(ref s) => write!(f, "\"{}\" is not a valid release branch name. Names can only contain letters, digits, dashes and underscores.", s),
+            BootstrapError::InvalidInstalledBranch(ref s) => write!(f, "The install is on the release branch \"{}\" which is not a valid name, it is updated from the {} branch instead.", s, ReleaseBranch::default()),
+            BootstrapError::UnknownBranch(ref s) => write!(f, "There is no release branch named \"{}\".", s),
+            BootstrapError::UnknownComponent(ref s) => write!(f, "The release has no component named \"{}\".", s),
+            BootstrapError::UpdateNotDeferrable(ref s) => write!(f, "The update to {} can no longer be put off.", s),
             BootstrapError::ReleaseLookupFailed(ref e) => write!(f, "Looks like something went wrong. We were unable to determine the latest Rainway release. Please exit and try again. \n\n {0}", e),
             BootstrapError::ResourceLoadError(ref e) => write!(f, "Failed to load application resource. {0}", e),
             BootstrapError::IcoError(ref e) => write!(f, "{0}", e),
@@ -88,26 +110,55 @@ impl fmt::Display for BootstrapError {
     }
 }
 
-impl From<String> for ReleaseBranch {
-    fn from(branch: String) -> Self {
-        match branch.to_lowercase().trim() {
-            "stable" => ReleaseBranch::Stable,
-            "nightly" => ReleaseBranch::Nightly,
-            "beta" => ReleaseBranch::Beta,
-            _ => ReleaseBranch::Stable,
+impl FromStr for ReleaseBranch {
+    type Err = BootstrapError;
+
+    /// branch names are case insensitive, they are stored in lowercase.
+    fn from_str(branch: &str) -> Result<Self, Self::Err> {
+        let name = branch.trim().to_lowercase();
+        let valid = !name.is_empty()
+            && name.len() <= 64
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !valid {
+            return Err(BootstrapError::InvalidBranchName(branch.to_string()));
+        }
+        Ok(ReleaseBranch(name))
+    }
+}
+
+impl ReleaseBranch {
+    /// reads the branch name stored with an install. installs from before branches were stored
+    /// are on the default branch, a stored name that is not valid is an `InvalidInstalledBranch`.
+    pub fn from_installed(branch: &str) -> Result<ReleaseBranch, BootstrapError> {
+        if branch.is_empty() {
+            return Ok(ReleaseBranch::default());
+        }
+        match branch.parse() {
+            Ok(branch) => Ok(branch),
+            Err(_) => Err(BootstrapError::InvalidInstalledBranch(branch.to_string())),
         }
     }
 }
 
-impl From<&str> for ReleaseBranch {
-    fn from(branch: &str) -> Self {
-        ReleaseBranch::from(branch.to_string())
+impl TryFrom<String> for ReleaseBranch {
+    type Error = BootstrapError;
+
+    fn try_from(branch: String) -> Result<Self, Self::Error> {
+        branch.parse()
+    }
+}
+
+impl From<ReleaseBranch> for String {
+    fn from(branch: ReleaseBranch) -> Self {
+        branch.0
     }
 }
 
 impl Default for ReleaseBranch {
     fn default() -> ReleaseBranch {
-        ReleaseBranch::Stable
+        ReleaseBranch("stable".to_string())
     }
 }
 
@@ -148,15 +199,34 @@ impl fmt::Display for UpdateType {
 }
 impl Copy for UpdateType {}
 
+/// the lowercase name of the update kind that is handed to the UI.
+impl fmt::Display for UpdateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            UpdateKind::Install => write!(f, "install"),
+            UpdateKind::Required => write!(f, "required"),
+            UpdateKind::Critical => write!(f, "critical"),
+            UpdateKind::Optional => write!(f, "optional"),
+        }
+    }
+}
+
 impl Clone for UpdateType {
     fn clone(&self) -> UpdateType {
         *self
     }
 }
 
+/// the name a branch has in the output directory and release URLs. the three original branches
+/// keep the casing they were always published under, so existing hosting and releases still line up.
 impl fmt::Display for ReleaseBranch {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self.0.as_str() {
+            "stable" => write!(f, "Stable"),
+            "beta" => write!(f, "Beta"),
+            "nightly" => write!(f, "Nightly"),
+            name => write!(f, "{}", name),
+        }
     }
 }
 
@@ -164,3 +234,20 @@ impl fmt::Display for ReleaseBranch {
 pub fn is_compiled_for_64_bit() -> bool {
     cfg!(target_pointer_width = "64")
 }
+
+#[cfg(test)]
+mod test {
+    use super::BootstrapError;
+    use crate::updater::ReleaseBranch;
+
+    #[test]
+    fn installed_branches() {
+        assert_eq!(ReleaseBranch::from_installed("").unwrap(), ReleaseBranch::default());
+        assert_eq!(ReleaseBranch::from_installed("Beta").unwrap().as_str(), "beta");
+        // an invalid name is reported rather than quietly moving the install to another branch.
+        match ReleaseBranch::from_installed("beta/../stable") {
+            Err(BootstrapError::InvalidInstalledBranch(name)) => assert_eq!(name, "beta/../stable"),
+            other => panic!("expected an invalid installed branch, got {:?}", other),
+        }
+    }
+}
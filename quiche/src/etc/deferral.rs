@@ -0,0 +1,107 @@
+/// the number of seconds in a day, deferral windows are configured in days.
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// how long an optional update can be put off, taken from the manifest of the release.
+/// a limit of zero is not enforced, an update cannot be deferred at all when both are zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeferralPolicy {
+    /// how many times the update can be deferred.
+    pub max_deferrals: u32,
+    /// how many days after it was first deferred the update can still be deferred.
+    pub max_days: u32,
+}
+
+/// the deferral state of the update a user last postponed, kept in the install state store.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Deferral {
+    /// the version that was deferred.
+    pub version: String,
+    /// how many times it has been deferred.
+    pub count: u32,
+    /// when it was first deferred, in seconds since the unix epoch.
+    pub since: u64,
+}
+
+impl Deferral {
+    /// returns the state for a version, deferrals of any other version no longer count.
+    fn for_version(&self, version: &str, now: u64) -> Deferral {
+        if self.version == version {
+            return self.clone();
+        }
+        Deferral {
+            version: version.to_string(),
+            count: 0,
+            since: now,
+        }
+    }
+
+    /// returns true if the user is still allowed to defer a version under a policy.
+    pub fn can_defer(&self, version: &str, policy: DeferralPolicy, now: u64) -> bool {
+        if policy.max_deferrals == 0 && policy.max_days == 0 {
+            return false;
+        }
+        let current = self.for_version(version, now);
+        if policy.max_deferrals > 0 && current.count >= policy.max_deferrals {
+            return false;
+        }
+        if policy.max_days > 0
+            && now.saturating_sub(current.since) >= policy.max_days as u64 * SECONDS_PER_DAY
+        {
+            return false;
+        }
+        true
+    }
+
+    /// returns how many more times a version can be deferred, None if only days are limited.
+    pub fn remaining(&self, version: &str, policy: DeferralPolicy, now: u64) -> Option<u32> {
+        if policy.max_deferrals == 0 {
+            return None;
+        }
+        let current = self.for_version(version, now);
+        Some(policy.max_deferrals.saturating_sub(current.count))
+    }
+
+    /// returns the state after the user defers a version once more.
+    pub fn record(&self, version: &str, now: u64) -> Deferral {
+        let mut next = self.for_version(version, now);
+        next.count += 1;
+        next
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Deferral, DeferralPolicy, SECONDS_PER_DAY};
+
+    #[test]
+    fn deferral_count() {
+        let policy = DeferralPolicy {
+            max_deferrals: 2,
+            max_days: 0,
+        };
+        let mut deferral = Deferral::default();
+        assert_eq!(deferral.can_defer("2.0.0", policy, 100), true);
+        deferral = deferral.record("2.0.0", 100);
+        deferral = deferral.record("2.0.0", 200);
+        assert_eq!(deferral.can_defer("2.0.0", policy, 300), false);
+        assert_eq!(deferral.remaining("2.0.0", policy, 300), Some(0));
+        // a newer release starts over.
+        assert_eq!(deferral.can_defer("2.1.0", policy, 300), true);
+        assert_eq!(deferral.remaining("2.1.0", policy, 300), Some(2));
+    }
+
+    #[test]
+    fn deferral_window() {
+        let policy = DeferralPolicy {
+            max_deferrals: 0,
+            max_days: 3,
+        };
+        let deferral = Deferral::default().record("2.0.0", 0);
+        assert_eq!(deferral.can_defer("2.0.0", policy, 2 * SECONDS_PER_DAY), true);
+        assert_eq!(deferral.can_defer("2.0.0", policy, 3 * SECONDS_PER_DAY), false);
+        assert_eq!(
+            Deferral::default().can_defer("2.0.0", DeferralPolicy::default(), 0),
+            false
+        );
+    }
+}
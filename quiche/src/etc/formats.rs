@@ -0,0 +1,473 @@
+use crate::updater::{Branch, Manifest, ReleaseBranch};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// the folder of a Squirrel package that holds the files of the app.
+pub const NUPKG_LIB_DIR: &str = "lib/net45";
+
+/// the other formats a release can be described in, next to manifest.toml.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// a Sparkle appcast, appcast.xml.
+    Sparkle,
+    /// a Squirrel RELEASES file and the full nupkg it lists.
+    Squirrel,
+    /// the manifest and branch as release.json.
+    Json,
+}
+
+impl OutputFormat {
+    /// returns the writer of the format.
+    pub fn writer(&self) -> Box<dyn ReleaseFormat> {
+        match self {
+            OutputFormat::Sparkle => Box::new(SparkleAppcast),
+            OutputFormat::Squirrel => Box::new(SquirrelReleases),
+            OutputFormat::Json => Box::new(JsonRelease),
+        }
+    }
+}
+
+/// a file of a baked release, described the ways the formats need it.
+#[derive(Serialize, Default, Clone)]
+pub struct Artifact {
+    pub url: String,
+    /// the SHA256 of the file, as it is in the manifest.
+    pub hash: String,
+    /// the size of the file in bytes.
+    pub size: u64,
+    /// the file name inside of the release directory.
+    #[serde(skip)]
+    pub name: String,
+    /// the SHA1 of the file, which Squirrel identifies packages by.
+    #[serde(skip)]
+    pub sha1: String,
+    /// the base64 Ed25519 signature of the file which Sparkle checks, empty when the release is not signed.
+    #[serde(skip)]
+    pub ed_signature: String,
+}
+
+/// everything a format can describe about a staged release.
+pub struct ReleaseInfo<'a> {
+    pub branch_name: &'a ReleaseBranch,
+    pub branch: &'a Branch,
+    pub manifest: &'a Manifest,
+    pub installer: Artifact,
+    pub package: Artifact,
+    /// the full Squirrel package of the release, only made for the squirrel format.
+    pub nupkg: Option<Artifact>,
+}
+
+/// writes a release in the format another update tool reads.
+pub trait ReleaseFormat {
+    /// the name of the format, as it is listed in a recipe.
+    fn name(&self) -> &'static str;
+    /// the file the format is written to inside of the release directory.
+    fn file_name(&self) -> &'static str;
+    fn render(&self, release: &ReleaseInfo) -> Result<String, String>;
+}
+
+/// a Sparkle appcast with a single item for the release, its enclosure is the full installer.
+pub struct SparkleAppcast;
+
+impl ReleaseFormat for SparkleAppcast {
+    fn name(&self) -> &'static str {
+        "sparkle"
+    }
+
+    fn file_name(&self) -> &'static str {
+        "appcast.xml"
+    }
+
+    fn render(&self, release: &ReleaseInfo) -> Result<String, String> {
+        let manifest = release.manifest;
+        let title = match manifest.title.is_empty() {
+            true => format!("Version {}", manifest.version),
+            false => manifest.title.clone(),
+        };
+        let mut item = vec![
+            format!("<title>{}</title>", escape_xml(&title)),
+            format!(
+                "<sparkle:version>{}</sparkle:version>",
+                escape_xml(&manifest.version)
+            ),
+            format!(
+                "<sparkle:shortVersionString>{}</sparkle:shortVersionString>",
+                escape_xml(&manifest.version)
+            ),
+        ];
+        if let Ok(published) = DateTime::parse_from_rfc3339(&manifest.published) {
+            item.push(format!("<pubDate>{}</pubDate>", published.to_rfc2822()));
+        }
+        if *release.branch_name != ReleaseBranch::default() {
+            item.push(format!(
+                "<sparkle:channel>{}</sparkle:channel>",
+                escape_xml(release.branch_name.as_str())
+            ));
+        }
+        if let Some(os_version) = &manifest.requirements.minimum_os_version {
+            item.push(format!(
+                "<sparkle:minimumSystemVersion>{}</sparkle:minimumSystemVersion>",
+                escape_xml(os_version)
+            ));
+        }
+        // a critical release is critical for everyone, otherwise only for installs below the minimum supported version.
+        if manifest.critical {
+            item.push("<sparkle:criticalUpdate />".to_string());
+        } else if !manifest.minimum_supported_version.is_empty() {
+            item.push(format!(
+                "<sparkle:criticalUpdate sparkle:version=\"{}\" />",
+                escape_xml(&manifest.minimum_supported_version)
+            ));
+        }
+        if !manifest.notes.is_empty() {
+            item.push(format!(
+                "<description><![CDATA[{}]]></description>",
+                manifest.notes.replace("]]>", "]]]]><![CDATA[>")
+            ));
+        }
+        let installer = &release.installer;
+        let mut enclosure = format!(
+            "<enclosure url=\"{}\" length=\"{}\" type=\"application/octet-stream\" sparkle:os=\"windows\"",
+            escape_xml(&installer.url),
+            installer.size
+        );
+        if !installer.ed_signature.is_empty() {
+            enclosure.push_str(&format!(
+                " sparkle:edSignature=\"{}\"",
+                installer.ed_signature
+            ));
+        }
+        enclosure.push_str(" />");
+        item.push(enclosure);
+
+        let mut appcast = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        appcast.push_str("<rss version=\"2.0\" xmlns:sparkle=\"http://www.andymatuschak.org/xml-namespaces/sparkle\">\n");
+        appcast.push_str("  <channel>\n");
+        appcast.push_str(&format!(
+            "    <title>{}</title>\n",
+            escape_xml(release.branch_name.as_str())
+        ));
+        appcast.push_str("    <item>\n");
+        for line in item {
+            appcast.push_str(&format!("      {}\n", line));
+        }
+        appcast.push_str("    </item>\n  </channel>\n</rss>\n");
+        Ok(appcast)
+    }
+}
+
+/// a Squirrel RELEASES file, which lists the full nupkg of the release as `SHA1 name size`.
+/// Squirrel reads the id and version of a release from the `<id>-<version>-full.nupkg` name.
+pub struct SquirrelReleases;
+
+impl ReleaseFormat for SquirrelReleases {
+    fn name(&self) -> &'static str {
+        "squirrel"
+    }
+
+    fn file_name(&self) -> &'static str {
+        "RELEASES"
+    }
+
+    fn render(&self, release: &ReleaseInfo) -> Result<String, String> {
+        let package = match &release.nupkg {
+            Some(p) => p,
+            None => return Err("The release has no Squirrel package to list.".to_string()),
+        };
+        if package.name.contains(char::is_whitespace) {
+            return Err(format!(
+                "{} cannot be listed as it contains a space.",
+                package.name
+            ));
+        }
+        Ok(format!(
+            "{} {} {}\n",
+            package.sha1, package.name, package.size
+        ))
+    }
+}
+
+/// checks that a release can be named as a Squirrel package. the id is a NuGet package id and the
+/// version must be one NuGet 2 accepts, whose pre-release label has no dots and no build metadata.
+pub fn check_squirrel_release(id: &str, version: &str) -> Result<(), String> {
+    let valid_id = !id.is_empty()
+        && id.len() <= 100
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-');
+    if !valid_id {
+        return Err(format!(
+            "\"{}\" is not a valid Squirrel package id, set package_id to letters, digits, dots, dashes and underscores.",
+            id
+        ));
+    }
+    let (release, label) = match version.find('-') {
+        Some(i) => (&version[..i], Some(&version[i + 1..])),
+        None => (version, None),
+    };
+    let valid_release = release.split('.').count() <= 4
+        && release
+            .split('.')
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()));
+    let valid_label = match label {
+        Some(l) => {
+            l.starts_with(|c: char| c.is_ascii_alphabetic())
+                && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }
+        None => true,
+    };
+    if !valid_release || !valid_label {
+        return Err(format!(
+            "Squirrel cannot read the version {}, its pre-release label can only hold letters, digits and dashes.",
+            version
+        ));
+    }
+    Ok(())
+}
+
+/// the name Squirrel reads the id and version of a full package from.
+pub fn nupkg_name(id: &str, version: &str) -> String {
+    format!("{}-{}-full.nupkg", id, version)
+}
+
+/// the name a file of the app is stored under inside of a nupkg. packages are read as Open Packaging
+/// Conventions archives, so anything outside of the characters URIs leave unescaped is percent encoded.
+pub fn nupkg_entry_name(path: &str) -> String {
+    let mut name = format!("{}/", NUPKG_LIB_DIR);
+    for b in path.replace('\\', "/").bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                name.push(b as char)
+            }
+            _ => name.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    name
+}
+
+/// the nuspec, content types and relationships a nupkg needs next to the files of the app,
+/// as the names they are stored under and their contents.
+pub fn nupkg_metadata(id: &str, manifest: &Manifest, entries: &[String]) -> Vec<(String, String)> {
+    let description = match manifest.title.is_empty() {
+        true => format!("Version {}", manifest.version),
+        false => manifest.title.clone(),
+    };
+    let mut metadata = vec![
+        format!("<id>{}</id>", escape_xml(id)),
+        format!("<version>{}</version>", escape_xml(&manifest.version)),
+        format!("<title>{}</title>", escape_xml(&description)),
+        format!("<authors>{}</authors>", escape_xml(id)),
+        format!("<description>{}</description>", escape_xml(&description)),
+    ];
+    if !manifest.notes.is_empty() {
+        metadata.push(format!(
+            "<releaseNotes>{}</releaseNotes>",
+            escape_xml(&manifest.notes)
+        ));
+    }
+    let mut nuspec = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    nuspec.push_str(
+        "<package xmlns=\"http://schemas.microsoft.com/packaging/2010/07/nuspec.xsd\">\n  <metadata>\n",
+    );
+    for line in metadata {
+        nuspec.push_str(&format!("    {}\n", line));
+    }
+    nuspec.push_str("  </metadata>\n</package>\n");
+    let nuspec_name = format!("{}.nuspec", id);
+
+    let rels = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\n  <Relationship Type=\"http://schemas.microsoft.com/packaging/2010/07/manifest\" Target=\"/{}\" Id=\"R0\" />\n</Relationships>\n",
+        escape_xml(&nuspec_name)
+    );
+
+    // every part needs a content type, by its extension or by its name when it has none.
+    let mut extensions: BTreeSet<String> =
+        ["nuspec", "rels"].iter().map(|e| e.to_string()).collect();
+    let mut overrides = Vec::new();
+    for entry in entries {
+        let file_name = entry.rsplit('/').next().unwrap_or_default();
+        match file_name.rfind('.') {
+            Some(i) if i + 1 < file_name.len() => {
+                extensions.insert(file_name[i + 1..].to_lowercase());
+            }
+            _ => overrides.push(entry),
+        }
+    }
+    let mut content_types = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\n");
+    for extension in extensions {
+        let content_type = match extension.as_str() {
+            "rels" => "application/vnd.openxmlformats-package.relationships+xml",
+            _ => "application/octet",
+        };
+        content_types.push_str(&format!(
+            "  <Default Extension=\"{}\" ContentType=\"{}\" />\n",
+            escape_xml(&extension),
+            content_type
+        ));
+    }
+    for entry in overrides {
+        content_types.push_str(&format!(
+            "  <Override PartName=\"/{}\" ContentType=\"application/octet\" />\n",
+            escape_xml(entry)
+        ));
+    }
+    content_types.push_str("</Types>\n");
+
+    vec![
+        (nuspec_name, nuspec),
+        ("_rels/.rels".to_string(), rels),
+        ("[Content_Types].xml".to_string(), content_types),
+    ]
+}
+
+/// the manifest and branch of the release as JSON, along with the sizes of the installer and package.
+pub struct JsonRelease;
+
+#[derive(Serialize)]
+struct JsonDocument<'a> {
+    branch: &'a str,
+    release: &'a Branch,
+    installer: &'a Artifact,
+    package: &'a Artifact,
+    manifest: &'a Manifest,
+}
+
+impl ReleaseFormat for JsonRelease {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn file_name(&self) -> &'static str {
+        "release.json"
+    }
+
+    fn render(&self, release: &ReleaseInfo) -> Result<String, String> {
+        let document = JsonDocument {
+            branch: release.branch_name.as_str(),
+            release: release.branch,
+            installer: &release.installer,
+            package: &release.package,
+            manifest: release.manifest,
+        };
+        serde_json::to_string_pretty(&document).map_err(|e| e.to_string())
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        check_squirrel_release, nupkg_entry_name, nupkg_metadata, nupkg_name, Artifact,
+        OutputFormat, ReleaseInfo,
+    };
+    use crate::updater::{Branch, Manifest, ReleaseBranch};
+
+    #[test]
+    fn formats() {
+        let manifest = Manifest {
+            version: "2.1.1".to_string(),
+            title: "Faster & smaller".to_string(),
+            published: "2020-06-01T12:00:00Z".to_string(),
+            minimum_supported_version: "2.0.0".to_string(),
+            ..Default::default()
+        };
+        let branch_name: ReleaseBranch = "beta".parse().unwrap();
+        let branch = Branch {
+            version: "2.1.1".to_string(),
+            manifest_url: "https://example.com/beta/2.1.1/manifest.toml".to_string(),
+            ..Default::default()
+        };
+        let release = ReleaseInfo {
+            branch_name: &branch_name,
+            branch: &branch,
+            manifest: &manifest,
+            installer: Artifact {
+                url: "https://example.com/beta/2.1.1/installer.exe".to_string(),
+                size: 1024,
+                ed_signature: "c2lnbmF0dXJl".to_string(),
+                ..Default::default()
+            },
+            package: Artifact {
+                name: "package.zip".to_string(),
+                size: 2048,
+                ..Default::default()
+            },
+            nupkg: Some(Artifact {
+                name: nupkg_name("Rainway", "2.1.1"),
+                sha1: "A94A8FE5CCB19BA61C4C0873D391E987982FBBD3".to_string(),
+                size: 4096,
+                ..Default::default()
+            }),
+        };
+
+        let appcast = OutputFormat::Sparkle.writer().render(&release).unwrap();
+        assert!(appcast.contains("<title>Faster &amp; smaller</title>"));
+        assert!(appcast.contains("Jun 2020 12:00:00 +0000</pubDate>"));
+        assert!(appcast.contains("<sparkle:channel>beta</sparkle:channel>"));
+        assert!(appcast.contains("<sparkle:criticalUpdate sparkle:version=\"2.0.0\" />"));
+        assert!(appcast.contains("length=\"1024\""));
+        assert!(appcast.contains("sparkle:edSignature=\"c2lnbmF0dXJl\""));
+
+        let releases = OutputFormat::Squirrel.writer().render(&release).unwrap();
+        assert_eq!(
+            releases,
+            "A94A8FE5CCB19BA61C4C0873D391E987982FBBD3 Rainway-2.1.1-full.nupkg 4096\n"
+        );
+
+        let json = OutputFormat::Json.writer().render(&release).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["branch"], "beta");
+        assert_eq!(value["installer"]["size"], 1024);
+        assert_eq!(value["manifest"]["version"], "2.1.1");
+    }
+
+    #[test]
+    fn squirrel_packages() {
+        assert!(check_squirrel_release("Rainway", "2.1.1").is_ok());
+        assert!(check_squirrel_release("Rainway", "2.1.1-beta2").is_ok());
+        // NuGet 2 versions have no dots in their pre-release label and no build metadata.
+        assert!(check_squirrel_release("Rainway", "2.1.1-beta.2").is_err());
+        assert!(check_squirrel_release("Rainway", "2.1.1+a84c3f16").is_err());
+        assert!(check_squirrel_release("", "2.1.1").is_err());
+        assert!(check_squirrel_release("Rainway App", "2.1.1").is_err());
+
+        assert_eq!(
+            nupkg_entry_name("bin\\Rainway App.exe"),
+            "lib/net45/bin/Rainway%20App.exe"
+        );
+        let manifest = Manifest {
+            version: "2.1.1".to_string(),
+            notes: "- <faster>".to_string(),
+            ..Default::default()
+        };
+        let entries = vec![
+            "lib/net45/Rainway.exe".to_string(),
+            "lib/net45/LICENSE".to_string(),
+        ];
+        let metadata = nupkg_metadata("Rainway", &manifest, &entries);
+        let names: Vec<&str> = metadata.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["Rainway.nuspec", "_rels/.rels", "[Content_Types].xml"]
+        );
+        assert!(metadata[0].1.contains("<id>Rainway</id>"));
+        assert!(metadata[0].1.contains("<version>2.1.1</version>"));
+        assert!(metadata[0]
+            .1
+            .contains("<releaseNotes>- &lt;faster&gt;</releaseNotes>"));
+        assert!(metadata[1].1.contains("Target=\"/Rainway.nuspec\""));
+        assert!(metadata[2].1.contains("<Default Extension=\"exe\""));
+        assert!(metadata[2]
+            .1
+            .contains("<Override PartName=\"/lib/net45/LICENSE\""));
+    }
+}
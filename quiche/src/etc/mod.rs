@@ -1 +1,5 @@
 pub mod constants;
+pub mod deferral;
+pub mod formats;
+pub mod rollout;
+pub mod version;
@@ -0,0 +1,51 @@
+use sha2::{Digest, Sha256};
+
+/// places a setup id into one of 100 buckets for a release version.
+/// the version is hashed in so a different slice of machines goes first on every release.
+pub fn rollout_bucket(setup_id: &str, version: &str) -> u8 {
+    let mut sha256 = Sha256::new();
+    sha256.input(setup_id.trim().to_lowercase().as_bytes());
+    sha256.input(b":");
+    sha256.input(version.trim().as_bytes());
+    let hash = sha256.result();
+    let value = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]);
+    (value % 100) as u8
+}
+
+/// returns true if a setup id falls inside the rollout percentage of a version.
+/// machines without a setup id cannot be bucketed, so they only get a release at 100%.
+pub fn in_rollout(percentage: u8, setup_id: &str, version: &str) -> bool {
+    if percentage >= 100 {
+        return true;
+    }
+    if setup_id.trim().is_empty() {
+        return false;
+    }
+    rollout_bucket(setup_id, version) < percentage
+}
+
+#[cfg(test)]
+mod test {
+    use super::{in_rollout, rollout_bucket};
+
+    const SETUP_ID: &str = "8E0C5C53-4B3A-4C0B-9F6E-2D7A1B3C4D5E";
+
+    #[test]
+    fn stable_bucket() {
+        let bucket = rollout_bucket(SETUP_ID, "2.0.0");
+        assert_eq!(bucket < 100, true);
+        assert_eq!(rollout_bucket(&SETUP_ID.to_lowercase(), "2.0.0"), bucket);
+        assert_eq!(in_rollout(bucket, SETUP_ID, "2.0.0"), false);
+        assert_eq!(in_rollout(bucket + 1, SETUP_ID, "2.0.0"), true);
+    }
+
+    #[test]
+    fn rollout_share() {
+        let ids: Vec<String> = (0..1000).map(|i| format!("setup-{}", i)).collect();
+        let included = ids.iter().filter(|id| in_rollout(10, id, "2.0.0")).count();
+        assert_eq!(included > 50 && included < 150, true);
+        assert_eq!(in_rollout(0, SETUP_ID, "2.0.0"), false);
+        assert_eq!(in_rollout(100, "", "2.0.0"), true);
+        assert_eq!(in_rollout(99, "", "2.0.0"), false);
+    }
+}
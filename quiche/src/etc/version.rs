@@ -0,0 +1,97 @@
+use semver::Version;
+use std::cmp::Ordering;
+
+/// parses a release version as semver, such as 2.1.1 or 2.1.1-a84c3f16+win32.
+/// a leading `v` is allowed.
+pub fn parse_version(version: &str) -> Option<Version> {
+    Version::parse(version.trim().trim_start_matches('v')).ok()
+}
+
+/// compares two release versions by semver precedence, build metadata is ignored.
+/// returns None if either of them is not a valid semver version.
+pub fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    Some(parse_version(a)?.cmp(&parse_version(b)?))
+}
+
+/// splits the version of a binary, such as 1.0.18.0 or "1, 0, 18, 0", into its numbers.
+fn numeric_parts(version: &str) -> Option<Vec<u64>> {
+    let parts: Option<Vec<u64>> = version
+        .trim()
+        .split(&['.', ','][..])
+        .map(|p| p.trim().parse().ok())
+        .collect();
+    match parts {
+        Some(p) if !p.is_empty() && p.len() <= 4 => Some(p),
+        _ => None,
+    }
+}
+
+/// returns true if the version of a binary agrees with a release version.
+/// binaries usually carry four part versions such as 2.1.1.0, their fourth part is ignored.
+/// a semver binary version has to match the major, minor and patch, and the pre-release if it has one.
+pub fn matches_binary_version(version: &str, binary_version: &str) -> bool {
+    let release = match parse_version(version) {
+        Some(v) => v,
+        None => return false,
+    };
+    if let Some(binary) = parse_version(binary_version) {
+        return (binary.major, binary.minor, binary.patch)
+            == (release.major, release.minor, release.patch)
+            && (!binary.is_prerelease() || binary.pre == release.pre);
+    }
+    match numeric_parts(binary_version) {
+        Some(mut parts) => {
+            parts.resize(3, 0);
+            parts == [release.major, release.minor, release.patch]
+        }
+        None => false,
+    }
+}
+
+/// turns the version of a binary into a release version, 1.0.18.0 becomes 1.0.18.
+/// returns None when it has a nonzero fourth part, which semver has no place for.
+pub fn release_version_from_binary(binary_version: &str) -> Option<String> {
+    if let Some(v) = parse_version(binary_version) {
+        return Some(v.to_string());
+    }
+    let mut parts = numeric_parts(binary_version)?;
+    if parts.len() == 4 && parts[3] != 0 {
+        return None;
+    }
+    parts.resize(3, 0);
+    Some(format!("{}.{}.{}", parts[0], parts[1], parts[2]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compare_versions, matches_binary_version, release_version_from_binary};
+    use std::cmp::Ordering;
+
+    #[test]
+    fn precedence() {
+        assert_eq!(compare_versions("2.0.0", "2.1.1-a84c3f16"), Some(Ordering::Less));
+        assert_eq!(compare_versions("2.1.1-a84c3f16", "2.1.1"), Some(Ordering::Less));
+        assert_eq!(compare_versions("2.0.10", "2.0.9"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("v2.0.0+win32", "2.0.0"), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn invalid_version() {
+        assert_eq!(compare_versions("1.0.17.0", "2.0.0"), None);
+        assert_eq!(compare_versions("2.0.0", ""), None);
+    }
+
+    #[test]
+    fn binary_versions() {
+        assert!(matches_binary_version("1.0.18", "1.0.18.0"));
+        assert!(matches_binary_version("1.0.18-a84c3f16", "1, 0, 18, 7"));
+        assert!(matches_binary_version("2.1.0", "2.1"));
+        assert!(matches_binary_version("2.1.1-beta", "2.1.1-beta+win32"));
+        assert!(!matches_binary_version("2.1.1", "2.1.1-beta"));
+        assert!(!matches_binary_version("2.0.0", "1.0.18.0"));
+        assert!(!matches_binary_version("2.0.0", "2.0.0.0.0"));
+        assert_eq!(release_version_from_binary("1.0.18.0"), Some("1.0.18".to_string()));
+        assert_eq!(release_version_from_binary("2.1"), Some("2.1.0".to_string()));
+        assert_eq!(release_version_from_binary("1.1.0.14"), None);
+    }
+}
@@ -0,0 +1,372 @@
+use crate::io::msi::{is_msi, parse_msi_signature};
+use crate::io::pe::{is_pe, parse_security_directory};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs::read;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// the content type of PKCS#7 SignedData, 1.2.840.113549.1.7.2.
+const OID_SIGNED_DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02];
+/// the content type of the Authenticode digest of a file, 1.3.6.1.4.1.311.2.1.4.
+const OID_SPC_INDIRECT_DATA: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x01, 0x04];
+/// SHA-256, 2.16.840.1.101.3.4.2.1.
+const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+/// the common name attribute of a certificate subject, 2.5.4.3.
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_BMP_STRING: u8 = 0x1E;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+/// the context specific tag [0], used for the content of a ContentInfo and the certificates of SignedData.
+const TAG_CONTEXT_0: u8 = 0xA0;
+
+/// what a bake does when a file is unsigned or not signed by one of the recipe signers.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignaturePolicy {
+    /// logs a warning for each file and carries on.
+    Warn,
+    /// fails the bake, listing every file.
+    Fail,
+}
+
+impl Default for SignaturePolicy {
+    fn default() -> SignaturePolicy {
+        SignaturePolicy::Warn
+    }
+}
+
+/// the Authenticode signature of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    /// the common name of the certificate that signed the file, such as `Rainway, Inc.`
+    pub subject: String,
+    /// whether the file still matches the digest that was signed. None when it was not checked,
+    /// which is the case for msi installers and signatures made with a digest other than SHA-256.
+    pub digest_matches: Option<bool>,
+}
+
+/// returns true for the kinds of release files that are expected to be signed, exe and dll files.
+pub fn is_signable(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(e) => e.eq_ignore_ascii_case("exe") || e.eq_ignore_ascii_case("dll"),
+        None => false,
+    }
+}
+
+/// reads the Authenticode signature of a PE file or an msi installer, None if it is not signed.
+/// only who signed the file is read, the certificate chain is not checked.
+pub fn read_signature(path: &Path) -> Result<Option<Signature>, Error> {
+    parse_signature(&read(path)?)
+}
+
+/// parses the Authenticode signature out of the contents of a PE file or an msi installer.
+pub fn parse_signature(data: &[u8]) -> Result<Option<Signature>, Error> {
+    if is_msi(data) {
+        return match parse_msi_signature(data)? {
+            Some(signed_data) => Ok(Some(Signature {
+                subject: parse_signed_data(&signed_data)?.subject,
+                digest_matches: None,
+            })),
+            None => Ok(None),
+        };
+    }
+    if !is_pe(data) {
+        return Err(invalid(
+            "the file is neither a PE file nor an msi installer.",
+        ));
+    }
+    let directory = match parse_security_directory(data)? {
+        Some(d) => d,
+        None => return Ok(None),
+    };
+    let signed_data = match directory.signatures.first() {
+        Some(s) => parse_signed_data(s)?,
+        None => return Ok(None),
+    };
+    let digest_matches = if signed_data.digest_algorithm == OID_SHA256 {
+        let mut sha256 = Sha256::new();
+        for range in &directory.signed_ranges {
+            sha256.input(range);
+        }
+        Some(sha256.result().as_slice() == signed_data.digest)
+    } else {
+        None
+    };
+    Ok(Some(Signature {
+        subject: signed_data.subject,
+        digest_matches,
+    }))
+}
+
+/// the parts of a PKCS#7 SignedData signature the audit needs.
+struct SignedData<'a> {
+    /// the common name of the certificate of the signer.
+    subject: String,
+    /// the algorithm and value of the digest of the file that was signed.
+    digest_algorithm: &'a [u8],
+    digest: &'a [u8],
+}
+
+/// a DER encoded value along with the bytes it was read from.
+#[derive(Clone, Copy)]
+struct Der<'a> {
+    tag: u8,
+    value: &'a [u8],
+    raw: &'a [u8],
+}
+
+fn parse_signed_data(data: &[u8]) -> Result<SignedData<'_>, Error> {
+    let content_info = children(read_der(data)?.0.value)?;
+    let content = match content_info.as_slice() {
+        [content_type, content, ..]
+            if content_type.tag == TAG_OID && content_type.value == OID_SIGNED_DATA =>
+        {
+            field(&children(content.value)?, 0, TAG_SEQUENCE)?
+        }
+        _ => return Err(invalid("the signature is not PKCS#7 SignedData.")),
+    };
+    // version, digest algorithms, the signed content, optional certificates and crls and then the signers.
+    let fields = children(content.value)?;
+    let signed_content = children(field(&fields, 2, TAG_SEQUENCE)?.value)?;
+    if field(&signed_content, 0, TAG_OID)?.value != OID_SPC_INDIRECT_DATA {
+        return Err(invalid(
+            "the signature does not hold an Authenticode digest.",
+        ));
+    }
+    // SpcIndirectDataContent ends with the DigestInfo of the file.
+    let indirect_data = field(
+        &children(field(&signed_content, 1, TAG_CONTEXT_0)?.value)?,
+        0,
+        TAG_SEQUENCE,
+    )?;
+    let digest_info = children(field(&children(indirect_data.value)?, 1, TAG_SEQUENCE)?.value)?;
+    let digest_algorithm = field(
+        &children(field(&digest_info, 0, TAG_SEQUENCE)?.value)?,
+        0,
+        TAG_OID,
+    )?
+    .value;
+    let digest = field(&digest_info, 1, TAG_OCTET_STRING)?.value;
+
+    let signer_infos = match fields.last() {
+        Some(f) if f.tag == TAG_SET => children(f.value)?,
+        _ => return Err(invalid("the signature has no signers.")),
+    };
+    let signer = children(field(&signer_infos, 0, TAG_SEQUENCE)?.value)?;
+    // the signer names its certificate by the issuer and serial number.
+    let id = children(field(&signer, 1, TAG_SEQUENCE)?.value)?;
+    let (issuer, serial) = (field(&id, 0, TAG_SEQUENCE)?, field(&id, 1, TAG_INTEGER)?);
+    let certificates = match fields.iter().find(|f| f.tag == TAG_CONTEXT_0) {
+        Some(f) => children(f.value)?,
+        None => Vec::new(),
+    };
+    for certificate in certificates {
+        let tbs = children(field(&children(certificate.value)?, 0, TAG_SEQUENCE)?.value)?;
+        // the version is optional, it is followed by the serial, algorithm, issuer, validity and subject.
+        let skip = match tbs.first() {
+            Some(v) if v.tag == TAG_CONTEXT_0 => 1,
+            _ => 0,
+        };
+        if field(&tbs, skip, TAG_INTEGER)?.raw == serial.raw
+            && field(&tbs, skip + 2, TAG_SEQUENCE)?.raw == issuer.raw
+        {
+            return Ok(SignedData {
+                subject: common_name(field(&tbs, skip + 4, TAG_SEQUENCE)?)?,
+                digest_algorithm,
+                digest,
+            });
+        }
+    }
+    Err(invalid(
+        "the certificate of the signer is not in the signature.",
+    ))
+}
+
+/// reads the common name out of an X.501 name, a sequence of sets of attributes.
+fn common_name(name: Der<'_>) -> Result<String, Error> {
+    for set in children(name.value)? {
+        for attribute in children(set.value)? {
+            let parts = children(attribute.value)?;
+            if field(&parts, 0, TAG_OID)?.value != OID_COMMON_NAME {
+                continue;
+            }
+            return match parts.get(1) {
+                Some(v) if v.tag == TAG_BMP_STRING => {
+                    let units: Vec<u16> = v
+                        .value
+                        .chunks_exact(2)
+                        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                        .collect();
+                    Ok(String::from_utf16_lossy(&units))
+                }
+                Some(v) => Ok(String::from_utf8_lossy(v.value).to_string()),
+                None => Err(invalid("the common name of the signer is empty.")),
+            };
+        }
+    }
+    Err(invalid("the certificate of the signer has no common name."))
+}
+
+/// returns a value of a sequence, failing if it is missing or has another tag.
+fn field<'a>(values: &[Der<'a>], index: usize, tag: u8) -> Result<Der<'a>, Error> {
+    match values.get(index) {
+        Some(v) if v.tag == tag => Ok(*v),
+        _ => Err(invalid("the signature is malformed.")),
+    }
+}
+
+/// reads the DER value at the start of a buffer, returning it and the rest of the buffer.
+fn read_der(data: &[u8]) -> Result<(Der<'_>, &[u8]), Error> {
+    let (tag, first) = match data {
+        [tag, first, ..] => (*tag, *first),
+        _ => return Err(invalid("the signature is truncated.")),
+    };
+    if tag & 0x1F == 0x1F {
+        return Err(invalid(
+            "the signature uses a tag number that is not supported.",
+        ));
+    }
+    let (length, header) = if first < 0x80 {
+        (first as usize, 2)
+    } else {
+        let count = (first & 0x7F) as usize;
+        if count == 0 || count > 4 || data.len() < 2 + count {
+            return Err(invalid("the signature has a length that is not valid DER."));
+        }
+        let length = data[2..2 + count]
+            .iter()
+            .fold(0usize, |length, &b| (length << 8) | b as usize);
+        (length, 2 + count)
+    };
+    if data.len() - header < length {
+        return Err(invalid("the signature is truncated."));
+    }
+    let end = header + length;
+    Ok((
+        Der {
+            tag,
+            value: &data[header..end],
+            raw: &data[..end],
+        },
+        &data[end..],
+    ))
+}
+
+/// reads every value inside of a constructed value.
+fn children(mut data: &[u8]) -> Result<Vec<Der<'_>>, Error> {
+    let mut values = Vec::new();
+    while !data.is_empty() {
+        let (value, rest) = read_der(data)?;
+        values.push(value);
+        data = rest;
+    }
+    Ok(values)
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        is_signable, parse_signed_data, OID_COMMON_NAME, OID_SHA256, OID_SIGNED_DATA,
+        OID_SPC_INDIRECT_DATA,
+    };
+    use std::path::Path;
+
+    fn der(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+        let value = parts.concat();
+        let mut encoded = vec![tag];
+        if value.len() < 0x80 {
+            encoded.push(value.len() as u8);
+        } else {
+            encoded.extend(&[0x82, (value.len() >> 8) as u8, value.len() as u8]);
+        }
+        encoded.extend(value);
+        encoded
+    }
+
+    fn name(common_name: &str) -> Vec<u8> {
+        let attribute = der(
+            0x30,
+            &[
+                &der(0x06, &[OID_COMMON_NAME]),
+                &der(0x0C, &[common_name.as_bytes()]),
+            ],
+        );
+        der(0x30, &[&der(0x31, &[&attribute])])
+    }
+
+    fn certificate(serial: u8, issuer: &str, subject: &str) -> Vec<u8> {
+        let tbs = der(
+            0x30,
+            &[
+                &der(0xA0, &[&der(0x02, &[&[2]])]),
+                &der(0x02, &[&[serial]]),
+                &der(0x30, &[]),
+                &name(issuer),
+                &der(0x30, &[]),
+                &name(subject),
+            ],
+        );
+        der(0x30, &[&tbs, &der(0x30, &[]), &der(0x03, &[&[0]])])
+    }
+
+    #[test]
+    fn signer_subject() {
+        let digest = [0xAB; 32];
+        let digest_info = der(
+            0x30,
+            &[
+                &der(0x30, &[&der(0x06, &[OID_SHA256])]),
+                &der(0x04, &[&digest]),
+            ],
+        );
+        let indirect_data = der(0x30, &[&der(0x30, &[]), &digest_info]);
+        let signed_content = der(
+            0x30,
+            &[
+                &der(0x06, &[OID_SPC_INDIRECT_DATA]),
+                &der(0xA0, &[&indirect_data]),
+            ],
+        );
+        // the signer is the second certificate, the first is its issuer.
+        let certificates = der(
+            0xA0,
+            &[
+                &certificate(1, "Test Root", "Test Root"),
+                &certificate(7, "Test Root", "Rainway, Inc."),
+            ],
+        );
+        let signer_id = der(0x30, &[&name("Test Root"), &der(0x02, &[&[7]])]);
+        let signer = der(0x30, &[&der(0x02, &[&[1]]), &signer_id]);
+        let signed_data = der(
+            0x30,
+            &[
+                &der(0x02, &[&[1]]),
+                &der(0x31, &[]),
+                &signed_content,
+                &certificates,
+                &der(0x31, &[&signer]),
+            ],
+        );
+        let content_info = der(
+            0x30,
+            &[&der(0x06, &[OID_SIGNED_DATA]), &der(0xA0, &[&signed_data])],
+        );
+
+        let signed = parse_signed_data(&content_info).unwrap();
+        assert_eq!(signed.subject, "Rainway, Inc.");
+        assert_eq!(signed.digest_algorithm, OID_SHA256);
+        assert_eq!(signed.digest, &digest[..]);
+        assert!(parse_signed_data(&content_info[..content_info.len() - 1]).is_err());
+        assert!(is_signable(Path::new("bin/Rainway.DLL")));
+        assert!(!is_signable(Path::new("bin/Rainway.pdb")));
+    }
+}
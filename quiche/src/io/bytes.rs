@@ -0,0 +1,42 @@
+use std::io::{Error, ErrorKind};
+
+/// reads a little endian u16 at an offset of a buffer.
+pub fn le_u16(data: &[u8], offset: usize) -> Result<u16, Error> {
+    match data.get(offset..offset.wrapping_add(2)) {
+        Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+        None => Err(truncated(offset)),
+    }
+}
+
+/// reads a little endian u32 at an offset of a buffer.
+pub fn le_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+    match data.get(offset..offset.wrapping_add(4)) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(truncated(offset)),
+    }
+}
+
+/// returns `length` bytes of a buffer from an offset.
+pub fn slice(data: &[u8], offset: usize, length: usize) -> Result<&[u8], Error> {
+    match data.get(offset..offset.saturating_add(length)) {
+        Some(b) => Ok(b),
+        None => Err(truncated(offset)),
+    }
+}
+
+/// decodes UTF-16 text up to the first null, or the end of the buffer if there is none.
+pub fn utf16_until_nul(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&c| c != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn truncated(offset: usize) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("the file is truncated, nothing could be read at offset {}.", offset),
+    )
+}
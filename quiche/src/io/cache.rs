@@ -0,0 +1,242 @@
+use crate::io::hash::{sha_256_bytes, sha_256_with_size};
+use crate::io::lock::OutputLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_file, rename, write};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// the file the cache records the hashes of source files in, it also marks a directory as a cache.
+const INDEX_FILE: &str = "index.toml";
+/// the folder compressed entries are kept in, nothing outside of it is ever deleted.
+const ENTRIES_DIR: &str = "entries";
+/// a file changed this soon before it was hashed could change again within the same mtime tick,
+/// so its hash is not trusted on the next bake and it is read again.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// what the cache last saw of a source file.
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedFile {
+    size: u64,
+    /// the modified time of the file in nanoseconds since the unix epoch.
+    modified: u64,
+    /// when the file was hashed in nanoseconds since the unix epoch.
+    hashed: u64,
+    hash: String,
+}
+
+impl CachedFile {
+    /// true if a file with this size and modified time can be trusted to still have the cached hash.
+    fn matches(&self, size: u64, modified: u64) -> bool {
+        let settled = modified + (RACY_WINDOW.as_nanos() as u64) < self.hashed;
+        self.size == size && self.modified == modified && settled
+    }
+}
+
+/// a local cache of the hashes of source files and their compressed zip entries,
+/// so a repeat bake only hashes and compresses the files that changed.
+/// the cache directory is locked while it is open.
+pub struct BakeCache {
+    dir: PathBuf,
+    files: Mutex<BTreeMap<String, CachedFile>>,
+    /// the entries used since the cache was opened, every other entry is deleted on save.
+    used: Mutex<HashSet<String>>,
+    _lock: OutputLock,
+}
+
+impl BakeCache {
+    /// opens the cache in a directory, creating it if needed. a directory that is not empty
+    /// is only used if it is already a cache, so pointing a recipe at the wrong folder never prunes it.
+    pub fn open(dir: &Path) -> Result<BakeCache, Error> {
+        let index = dir.join(INDEX_FILE);
+        if dir.is_dir() && !index.exists() && read_dir(dir)?.next().is_some() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!(
+                    "{} is not empty and is not a bake cache, choose an empty or new directory.",
+                    dir.display()
+                ),
+            ));
+        }
+        create_dir_all(dir.join(ENTRIES_DIR))?;
+        let lock = OutputLock::acquire(dir)?;
+        if !index.exists() {
+            write(&index, "")?;
+        }
+        let files = match read_to_string(&index) {
+            Ok(contents) => match toml::from_str(&contents) {
+                Ok(f) => f,
+                Err(e) => {
+                    log::warn!(
+                        "ignoring the unreadable bake cache index {}. {}",
+                        index.display(),
+                        e
+                    );
+                    BTreeMap::new()
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(BakeCache {
+            dir: dir.to_path_buf(),
+            files: Mutex::new(files),
+            used: Mutex::new(HashSet::new()),
+            _lock: lock,
+        })
+    }
+
+    /// returns the SHA256 and size of a file, only reading it if its size or modified time
+    /// changed since it was last hashed.
+    pub fn hash_file(&self, path: &Path) -> Result<(String, u64), Error> {
+        let metadata = path.metadata()?;
+        let modified = to_nanos(metadata.modified()?);
+        let key = path.to_string_lossy().to_string();
+        if let Some(cached) = self.files.lock().unwrap().get(&key) {
+            if cached.matches(metadata.len(), modified) {
+                return Ok((cached.hash.clone(), cached.size));
+            }
+        }
+        let (hash, size) = match sha_256_with_size(&path.to_path_buf()) {
+            Some(h) => h,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("Unable to hash {}.", path.display()),
+                ))
+            }
+        };
+        self.files.lock().unwrap().insert(
+            key,
+            CachedFile {
+                size,
+                modified,
+                hashed: to_nanos(SystemTime::now()),
+                hash: hash.clone(),
+            },
+        );
+        Ok((hash, size))
+    }
+
+    /// returns where the compressed entry of a file is kept. an entry is only shared by files
+    /// stored under the same name with the same contents and compression settings.
+    pub fn entry_path(&self, name: &str, hash: &str, settings: &str) -> PathBuf {
+        let entry = format!(
+            "{}.zip",
+            sha_256_bytes(format!("{}\n{}\n{}", name, hash, settings).as_bytes())
+        );
+        let path = self.dir.join(ENTRIES_DIR).join(&entry);
+        self.used.lock().unwrap().insert(entry);
+        path
+    }
+
+    /// writes the index and deletes the entries that were not used since the cache was opened,
+    /// such as those of files that changed or were removed.
+    pub fn save(&self) -> Result<(), Error> {
+        let used = self.used.lock().unwrap();
+        let mut removed = 0;
+        let entries_dir = self.dir.join(ENTRIES_DIR);
+        for file in read_dir(&entries_dir)? {
+            let name = file?.file_name().to_string_lossy().to_string();
+            if !used.contains(&name) {
+                remove_file(entries_dir.join(&name))?;
+                removed += 1;
+            }
+        }
+        let index = match toml::to_string(&*self.files.lock().unwrap()) {
+            Ok(i) => i,
+            Err(e) => return Err(Error::new(ErrorKind::Other, e.to_string())),
+        };
+        let index_path = self.dir.join(INDEX_FILE);
+        let partial_path = self.dir.join(format!("{}.partial", INDEX_FILE));
+        write(&partial_path, index)?;
+        rename(&partial_path, &index_path)?;
+        log::info!(
+            "saved the bake cache in {}, {} entries are cached and {} were removed.",
+            self.dir.display(),
+            used.len(),
+            removed
+        );
+        Ok(())
+    }
+}
+
+fn to_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BakeCache, CachedFile};
+    use crate::io::hash::sha_256_bytes;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::path::PathBuf;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let mut dir = temp_dir();
+        dir.push(format!("Quiche_{}_{}", name, std::process::id()));
+        if dir.exists() {
+            remove_dir_all(&dir).unwrap();
+        }
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn settled_files() {
+        let cached = CachedFile {
+            size: 3,
+            modified: 1_000_000_000_000,
+            hashed: 1_000_000_000_000 + 60_000_000_000,
+            hash: "A".to_string(),
+        };
+        assert!(cached.matches(3, 1_000_000_000_000));
+        assert!(!cached.matches(4, 1_000_000_000_000));
+        assert!(!cached.matches(3, 1_000_000_000_001));
+        // a file hashed within the same second it was written could still change unnoticed.
+        let racy = CachedFile {
+            hashed: 1_000_000_000_000 + 1_000_000_000,
+            ..cached
+        };
+        assert!(!racy.matches(3, 1_000_000_000_000));
+    }
+
+    #[test]
+    fn cache_dir() {
+        let dir = test_dir("Cache_Test");
+        let file = dir.join("Rainway.exe");
+        write(&file, b"exe").unwrap();
+        let foreign = dir.join("package.zip");
+        write(&foreign, b"").unwrap();
+        // a folder holding anything other than a cache is refused.
+        assert!(BakeCache::open(&dir).is_err());
+
+        let cache_dir = dir.join("cache");
+        let cache = BakeCache::open(&cache_dir).unwrap();
+        assert!(BakeCache::open(&cache_dir).is_err());
+        let (hash, size) = cache.hash_file(&file).unwrap();
+        assert_eq!((hash.as_str(), size), (sha_256_bytes(b"exe").as_str(), 3));
+        write(&file, b"exe2").unwrap();
+        assert_eq!(cache.hash_file(&file).unwrap().0, sha_256_bytes(b"exe2"));
+
+        let used = cache.entry_path("Rainway.exe", &hash, "zstd");
+        let unused = used.with_file_name("unused.zip");
+        write(&used, b"").unwrap();
+        write(&unused, b"").unwrap();
+        write(cache_dir.join("notes.zip"), b"").unwrap();
+        cache.save().unwrap();
+        assert!(used.exists());
+        assert!(!unused.exists());
+        assert!(cache_dir.join("notes.zip").exists());
+        drop(cache);
+        // once saved, the folder opens again as a cache.
+        assert!(BakeCache::open(&cache_dir).is_ok());
+        assert!(foreign.exists());
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,368 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{create_dir_all, rename, File};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// no chunk is cut before it is this long, unless the file ends first.
+pub const MIN_CHUNK_SIZE: usize = 16 * 1024;
+/// the size chunks average out to, it must be a power of two.
+pub const AVG_CHUNK_SIZE: usize = 64 * 1024;
+/// a chunk is always cut once it reaches this length.
+pub const MAX_CHUNK_SIZE: usize = 256 * 1024;
+
+/// the extension chunks are stored with inside of a chunk store.
+const CHUNK_EXTENSION: &str = "chunk";
+
+/// the chunk index of a release, it lists the chunks every package file is made from.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct ChunkIndex {
+    #[serde(default)]
+    pub files: Vec<ChunkedFile>,
+}
+
+/// a package file and the chunks it is made from, in order.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct ChunkedFile {
+    pub path: String,
+    pub hash: String,
+    pub size: u64,
+    #[serde(default)]
+    pub chunks: Vec<ChunkRef>,
+}
+
+/// a single chunk by the SHA256 of its contents.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct ChunkRef {
+    pub hash: String,
+    pub size: u64,
+}
+
+/// where a chunk can be read from a local file.
+#[derive(Clone, Debug)]
+pub struct LocalChunk {
+    pub path: PathBuf,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// how many chunks a file was split into and how many of them were not in the store yet.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct ChunkStats {
+    pub chunks: usize,
+    pub new_chunks: usize,
+    pub bytes: u64,
+    pub new_bytes: u64,
+}
+
+impl ChunkStats {
+    pub fn add(&mut self, other: ChunkStats) {
+        self.chunks += other.chunks;
+        self.new_chunks += other.new_chunks;
+        self.bytes += other.bytes;
+        self.new_bytes += other.new_bytes;
+    }
+}
+
+/// the gear table maps every byte to a pseudo random value for the rolling hash.
+/// it is generated from a fixed seed so every build cuts chunks in the same places.
+fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x5155_4943_4845_4344;
+    for entry in table.iter_mut() {
+        // splitmix64
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        *entry = z ^ (z >> 31);
+    }
+    table
+}
+
+/// returns the length of the next chunk at the start of `data`.
+/// a boundary is placed where the rolling hash of the bytes before it matches the mask,
+/// so an insertion only changes the chunks around it instead of every chunk after it.
+fn cut_point(data: &[u8], gear: &[u64; 256]) -> usize {
+    if data.len() <= MIN_CHUNK_SIZE {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK_SIZE);
+    let mask = (AVG_CHUNK_SIZE - 1) as u64;
+    let mut hash: u64 = 0;
+    for (i, byte) in data[MIN_CHUNK_SIZE..end].iter().enumerate() {
+        hash = (hash << 1).wrapping_add(gear[*byte as usize]);
+        if hash & mask == 0 {
+            return MIN_CHUNK_SIZE + i + 1;
+        }
+    }
+    end
+}
+
+/// splits everything read from a reader into content-defined chunks and calls `f` with each of them.
+pub fn for_each_chunk<R, F>(mut reader: R, mut f: F) -> Result<(), Error>
+where
+    R: Read,
+    F: FnMut(&[u8]) -> Result<(), Error>,
+{
+    let gear = gear_table();
+    let mut buffer: Vec<u8> = Vec::with_capacity(MAX_CHUNK_SIZE * 2);
+    let mut eof = false;
+    loop {
+        while !eof && buffer.len() < MAX_CHUNK_SIZE {
+            let start = buffer.len();
+            buffer.resize(MAX_CHUNK_SIZE * 2, 0);
+            let read = reader.read(&mut buffer[start..])?;
+            buffer.truncate(start + read);
+            eof = read == 0;
+        }
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        let length = cut_point(&buffer, &gear);
+        f(&buffer[..length])?;
+        buffer.drain(..length);
+    }
+}
+
+/// hashes a chunk using SHA256 and returns the formatted `{:X}` String.
+pub fn chunk_hash(data: &[u8]) -> String {
+    let mut sha256 = Sha256::new();
+    sha256.input(data);
+    format!("{:X}", sha256.result())
+}
+
+/// returns where a chunk is kept inside of a chunk store.
+/// chunks are spread over folders named after the first four characters of their hash.
+pub fn chunk_path(store: &Path, hash: &str) -> PathBuf {
+    let mut path = store.to_path_buf();
+    path.push(&hash[..hash.len().min(4)]);
+    path.push(format!("{}.{}", hash, CHUNK_EXTENSION));
+    path
+}
+
+/// returns the URL of a chunk inside of a remote chunk store.
+pub fn chunk_url(store_url: &str, hash: &str) -> String {
+    format!(
+        "{}/{}/{}.{}",
+        store_url.trim_end_matches('/'),
+        &hash[..hash.len().min(4)],
+        hash,
+        CHUNK_EXTENSION
+    )
+}
+
+/// splits a file into chunks and writes the ones the store does not have yet.
+/// returns the chunks the file is made of, the hash of the whole file and the chunk stats.
+pub fn store_file(path: &Path, store: &Path) -> Result<(Vec<ChunkRef>, String, ChunkStats), Error> {
+    let mut chunks = Vec::new();
+    let mut stats = ChunkStats::default();
+    let mut sha256 = Sha256::new();
+    for_each_chunk(File::open(path)?, |data| {
+        sha256.input(data);
+        let hash = chunk_hash(data);
+        let stored = chunk_path(store, &hash);
+        stats.chunks += 1;
+        stats.bytes += data.len() as u64;
+        if !stored.exists() {
+            write_chunk(&stored, data)?;
+            stats.new_chunks += 1;
+            stats.new_bytes += data.len() as u64;
+        }
+        chunks.push(ChunkRef {
+            hash,
+            size: data.len() as u64,
+        });
+        Ok(())
+    })?;
+    Ok((chunks, format!("{:X}", sha256.result()), stats))
+}
+
+/// writes a chunk through a temporary file so a partially written chunk is never picked up.
+pub fn write_chunk(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    let partial = path.with_extension("partial");
+    File::create(&partial)?.write_all(data)?;
+    rename(&partial, path)
+}
+
+/// chunks every file that exists under a root folder so their chunks can be reused.
+/// files which cannot be read are skipped, they only mean more chunks are downloaded.
+pub fn scan_local_chunks(root: &Path, files: &[String]) -> HashMap<String, LocalChunk> {
+    let mut chunks = HashMap::new();
+    for file in files {
+        let mut path = root.to_path_buf();
+        path.push(file);
+        let reader = match File::open(&path) {
+            Ok(f) => f,
+            Err(_e) => continue,
+        };
+        let mut offset = 0u64;
+        let result = for_each_chunk(reader, |data| {
+            chunks.entry(chunk_hash(data)).or_insert(LocalChunk {
+                path: path.clone(),
+                offset,
+                size: data.len() as u64,
+            });
+            offset += data.len() as u64;
+            Ok(())
+        });
+        if let Err(e) = result {
+            log::warn!("unable to chunk {}: {}", path.display(), e);
+        }
+    }
+    chunks
+}
+
+/// reads a chunk back out of a local file.
+pub fn read_local_chunk(chunk: &LocalChunk) -> Result<Vec<u8>, Error> {
+    let mut file = File::open(&chunk.path)?;
+    file.seek(SeekFrom::Start(chunk.offset))?;
+    let mut data = vec![0u8; chunk.size as usize];
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// rebuilds a file from its chunks. `fetch` returns the contents of a chunk from wherever it is found,
+/// every chunk is checked against its hash and the finished file against the hash of the whole file.
+pub fn assemble_file<F>(file: &ChunkedFile, output: &Path, mut fetch: F) -> Result<(), Error>
+where
+    F: FnMut(&ChunkRef) -> Result<Vec<u8>, Error>,
+{
+    if let Some(parent) = output.parent() {
+        create_dir_all(parent)?;
+    }
+    let mut writer = File::create(output)?;
+    let mut sha256 = Sha256::new();
+    let mut size = 0u64;
+    for chunk in &file.chunks {
+        let data = fetch(chunk)?;
+        if !chunk_hash(&data).eq_ignore_ascii_case(&chunk.hash) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "chunk {} of {} does not match its hash.",
+                    chunk.hash, file.path
+                ),
+            ));
+        }
+        sha256.input(&data);
+        writer.write_all(&data)?;
+        size += data.len() as u64;
+    }
+    writer.flush()?;
+    let hash = format!("{:X}", sha256.result());
+    if size != file.size || !hash.eq_ignore_ascii_case(&file.hash) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} does not match the chunk index after assembling.",
+                file.path
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        assemble_file, chunk_path, for_each_chunk, read_local_chunk, scan_local_chunks, store_file,
+        ChunkedFile, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE,
+    };
+    use std::collections::HashSet;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, read, remove_dir_all, write};
+    use std::io::Error;
+    use std::path::PathBuf;
+
+    fn random_bytes(length: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn chunk_hashes(data: &[u8]) -> Vec<String> {
+        let mut hashes = Vec::new();
+        for_each_chunk(data, |chunk| {
+            assert_eq!(chunk.len() <= MAX_CHUNK_SIZE, true);
+            hashes.push(super::chunk_hash(chunk));
+            Ok(())
+        })
+        .unwrap();
+        hashes
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let mut dir = temp_dir();
+        dir.push(format!("quiche_chunk_{}_{}", name, std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn boundaries_survive_insertion() {
+        let original = random_bytes(2 * 1024 * 1024, 7);
+        let mut edited = original[..100_000].to_vec();
+        edited.extend_from_slice(b"a few inserted bytes");
+        edited.extend_from_slice(&original[100_000..]);
+        let before: HashSet<String> = chunk_hashes(&original).into_iter().collect();
+        let after = chunk_hashes(&edited);
+        let reused = after.iter().filter(|h| before.contains(*h)).count();
+        assert_eq!(after.len() > 8, true);
+        assert_eq!(reused >= after.len() - 2, true);
+    }
+
+    #[test]
+    fn small_file_single_chunk() {
+        assert_eq!(chunk_hashes(&random_bytes(MIN_CHUNK_SIZE - 1, 3)).len(), 1);
+        assert_eq!(chunk_hashes(&[]).len(), 0);
+    }
+
+    #[test]
+    fn store_and_assemble() {
+        let dir = test_dir("assemble");
+        let store = dir.join("store");
+        let source = dir.join("source.bin");
+        let data = random_bytes(700_000, 11);
+        write(&source, &data).unwrap();
+        let (chunks, hash, stats) = store_file(&source, &store).unwrap();
+        assert_eq!(stats.new_chunks, stats.chunks);
+        let (_, _, again) = store_file(&source, &store).unwrap();
+        assert_eq!(again.new_chunks, 0);
+
+        let file = ChunkedFile {
+            path: "source.bin".to_string(),
+            hash,
+            size: data.len() as u64,
+            chunks,
+        };
+        // the first chunk comes from a local copy, the rest from the store.
+        let local = scan_local_chunks(&dir, &["source.bin".to_string()]);
+        let output = dir.join("out").join("source.bin");
+        assemble_file(&file, &output, |chunk| match local.get(&chunk.hash) {
+            Some(l) if chunk == &file.chunks[0] => read_local_chunk(l),
+            _ => read(chunk_path(&store, &chunk.hash)),
+        })
+        .unwrap();
+        assert_eq!(read(&output).unwrap(), data);
+
+        let corrupt = assemble_file(&file, &output, |chunk| {
+            let mut data = read(chunk_path(&store, &chunk.hash))?;
+            data[0] ^= 1;
+            Ok::<Vec<u8>, Error>(data)
+        });
+        assert_eq!(corrupt.is_err(), true);
+        remove_dir_all(&dir).unwrap();
+    }
+}
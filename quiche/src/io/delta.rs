@@ -0,0 +1,511 @@
+use bzip2::read::BzDecoder;
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use std::fs::{read, write};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::Path;
+
+/// every patch starts with the same magic bytes as the original bsdiff,
+/// so patches can also be inspected or applied by the reference bspatch.
+const MAGIC: &[u8; 8] = b"BSDIFF40";
+/// the magic bytes, followed by the compressed control and diff block lengths and the new size.
+const HEADER_SIZE: usize = 32;
+
+/// creates a bsdiff patch which turns the `old` file into the `new` file.
+/// both files are read into memory and the suffix array of `old` takes about 16 times its size on top.
+pub fn diff_files(old: &Path, new: &Path, output: &Path) -> Result<(), Error> {
+    let old_data = read(old)?;
+    let new_data = read(new)?;
+    write(output, diff(&old_data, &new_data)?)
+}
+
+/// applies a bsdiff patch to the `old` file and writes the rebuilt file to `output`.
+pub fn patch_file(old: &Path, patch_path: &Path, output: &Path) -> Result<(), Error> {
+    let old_data = read(old)?;
+    let patch_data = read(patch_path)?;
+    write(output, patch(&old_data, &patch_data)?)
+}
+
+/// creates a patch in the BSDIFF40 format which turns `old` into `new`.
+/// the patch is made up of three bzip2 blocks: the control tuples, the bytewise
+/// differences of approximate matches, and the extra bytes that have no match at all.
+pub fn diff(old: &[u8], new: &[u8]) -> Result<Vec<u8>, Error> {
+    let suffixes = suffix_sort(old);
+    let old_size = old.len() as isize;
+    let new_size = new.len() as isize;
+
+    let mut ctrl_block = BzEncoder::new(Vec::new(), Compression::Best);
+    let mut diff_block: Vec<u8> = Vec::with_capacity(new.len());
+    let mut extra_block: Vec<u8> = Vec::new();
+
+    let mut scan: isize = 0;
+    let mut len: isize = 0;
+    let mut pos: isize = 0;
+    let mut last_scan: isize = 0;
+    let mut last_pos: isize = 0;
+    let mut last_offset: isize = 0;
+
+    while scan < new_size {
+        let mut old_score: isize = 0;
+        scan += len;
+        let mut scsc = scan;
+        while scan < new_size {
+            len = search(&suffixes, old, &new[scan as usize..], 0, old_size, &mut pos);
+            while scsc < scan + len {
+                if scsc + last_offset < old_size
+                    && old[(scsc + last_offset) as usize] == new[scsc as usize]
+                {
+                    old_score += 1;
+                }
+                scsc += 1;
+            }
+            if (len == old_score && len != 0) || len > old_score + 8 {
+                break;
+            }
+            if scan + last_offset < old_size
+                && old[(scan + last_offset) as usize] == new[scan as usize]
+            {
+                old_score -= 1;
+            }
+            scan += 1;
+        }
+
+        if len != old_score || scan == new_size {
+            // extend the previous match forwards as far as it remains mostly equal.
+            let mut s: isize = 0;
+            let mut sf: isize = 0;
+            let mut lenf: isize = 0;
+            let mut i: isize = 0;
+            while last_scan + i < scan && last_pos + i < old_size {
+                if old[(last_pos + i) as usize] == new[(last_scan + i) as usize] {
+                    s += 1;
+                }
+                i += 1;
+                if s * 2 - i > sf * 2 - lenf {
+                    sf = s;
+                    lenf = i;
+                }
+            }
+
+            // and extend the current match backwards the same way.
+            let mut lenb: isize = 0;
+            if scan < new_size {
+                let mut s: isize = 0;
+                let mut sb: isize = 0;
+                let mut i: isize = 1;
+                while scan >= last_scan + i && pos >= i {
+                    if old[(pos - i) as usize] == new[(scan - i) as usize] {
+                        s += 1;
+                    }
+                    if s * 2 - i > sb * 2 - lenb {
+                        sb = s;
+                        lenb = i;
+                    }
+                    i += 1;
+                }
+            }
+
+            // if both extensions overlap, find the best place to split them.
+            if last_scan + lenf > scan - lenb {
+                let overlap = (last_scan + lenf) - (scan - lenb);
+                let mut s: isize = 0;
+                let mut ss: isize = 0;
+                let mut lens: isize = 0;
+                for i in 0..overlap {
+                    if new[(last_scan + lenf - overlap + i) as usize]
+                        == old[(last_pos + lenf - overlap + i) as usize]
+                    {
+                        s += 1;
+                    }
+                    if new[(scan - lenb + i) as usize] == old[(pos - lenb + i) as usize] {
+                        s -= 1;
+                    }
+                    if s > ss {
+                        ss = s;
+                        lens = i + 1;
+                    }
+                }
+                lenf += lens - overlap;
+                lenb -= lens;
+            }
+
+            for i in 0..lenf {
+                diff_block.push(
+                    new[(last_scan + i) as usize].wrapping_sub(old[(last_pos + i) as usize]),
+                );
+            }
+            let extra_len = (scan - lenb) - (last_scan + lenf);
+            extra_block.extend_from_slice(
+                &new[(last_scan + lenf) as usize..(last_scan + lenf + extra_len) as usize],
+            );
+
+            ctrl_block.write_all(&encode_offset(lenf as i64))?;
+            ctrl_block.write_all(&encode_offset(extra_len as i64))?;
+            ctrl_block.write_all(&encode_offset(((pos - lenb) - (last_pos + lenf)) as i64))?;
+
+            last_scan = scan - lenb;
+            last_pos = pos - lenb;
+            last_offset = pos - scan;
+        }
+    }
+
+    let ctrl_block = ctrl_block.finish()?;
+    let diff_block = compress(&diff_block)?;
+    let extra_block = compress(&extra_block)?;
+
+    let mut output =
+        Vec::with_capacity(HEADER_SIZE + ctrl_block.len() + diff_block.len() + extra_block.len());
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&encode_offset(ctrl_block.len() as i64));
+    output.extend_from_slice(&encode_offset(diff_block.len() as i64));
+    output.extend_from_slice(&encode_offset(new_size as i64));
+    output.extend_from_slice(&ctrl_block);
+    output.extend_from_slice(&diff_block);
+    output.extend_from_slice(&extra_block);
+    Ok(output)
+}
+
+/// rebuilds a new file from the `old` file and a patch created by `diff`.
+pub fn patch(old: &[u8], patch: &[u8]) -> Result<Vec<u8>, Error> {
+    if patch.len() < HEADER_SIZE || &patch[0..8] != MAGIC {
+        return Err(corrupt_patch("missing bsdiff header"));
+    }
+    let ctrl_len = decode_offset(&patch[8..16]);
+    let diff_len = decode_offset(&patch[16..24]);
+    let new_size = decode_offset(&patch[24..32]);
+    if ctrl_len < 0 || diff_len < 0 || new_size < 0 {
+        return Err(corrupt_patch("negative block length"));
+    }
+    let ctrl_end = HEADER_SIZE as i64 + ctrl_len;
+    let diff_end = ctrl_end + diff_len;
+    if diff_end > patch.len() as i64 {
+        return Err(corrupt_patch("block lengths exceed the patch size"));
+    }
+
+    let mut ctrl_block = BzDecoder::new(&patch[HEADER_SIZE..ctrl_end as usize]);
+    let mut diff_block = BzDecoder::new(&patch[ctrl_end as usize..diff_end as usize]);
+    let mut extra_block = BzDecoder::new(&patch[diff_end as usize..]);
+
+    let old_size = old.len() as i64;
+    let mut new = vec![0u8; new_size as usize];
+    let mut old_pos: i64 = 0;
+    let mut new_pos: i64 = 0;
+    let mut ctrl = [0u8; 24];
+
+    while new_pos < new_size {
+        ctrl_block.read_exact(&mut ctrl)?;
+        let diff_count = decode_offset(&ctrl[0..8]);
+        let extra_count = decode_offset(&ctrl[8..16]);
+        let seek = decode_offset(&ctrl[16..24]);
+
+        if diff_count < 0 || new_pos + diff_count > new_size {
+            return Err(corrupt_patch("diff block runs past the new file"));
+        }
+        let start = new_pos as usize;
+        let end = (new_pos + diff_count) as usize;
+        diff_block.read_exact(&mut new[start..end])?;
+        for i in 0..diff_count {
+            let old_index = old_pos + i;
+            if old_index >= 0 && old_index < old_size {
+                let new_index = (new_pos + i) as usize;
+                new[new_index] = new[new_index].wrapping_add(old[old_index as usize]);
+            }
+        }
+        new_pos += diff_count;
+        old_pos += diff_count;
+
+        if extra_count < 0 || new_pos + extra_count > new_size {
+            return Err(corrupt_patch("extra block runs past the new file"));
+        }
+        let start = new_pos as usize;
+        let end = (new_pos + extra_count) as usize;
+        extra_block.read_exact(&mut new[start..end])?;
+        new_pos += extra_count;
+        old_pos += seek;
+    }
+    Ok(new)
+}
+
+fn corrupt_patch(reason: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("The delta patch is corrupt: {}", reason),
+    )
+}
+
+fn compress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut encoder = BzEncoder::new(Vec::new(), Compression::Best);
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// bsdiff stores offsets as a little-endian magnitude with the sign in the highest bit.
+fn encode_offset(value: i64) -> [u8; 8] {
+    let mut buffer = value.wrapping_abs().to_le_bytes();
+    if value < 0 {
+        buffer[7] |= 0x80;
+    }
+    buffer
+}
+
+fn decode_offset(buffer: &[u8]) -> i64 {
+    let mut magnitude = [0u8; 8];
+    magnitude.copy_from_slice(&buffer[0..8]);
+    magnitude[7] &= 0x7F;
+    let value = i64::from_le_bytes(magnitude);
+    if buffer[7] & 0x80 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// returns the length of the common prefix of two byte slices.
+fn match_len(old: &[u8], new: &[u8]) -> isize {
+    old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count() as isize
+}
+
+/// binary searches the suffix array for the longest match of `new` inside of `old`.
+fn search(
+    suffixes: &[isize],
+    old: &[u8],
+    new: &[u8],
+    start: isize,
+    end: isize,
+    pos: &mut isize,
+) -> isize {
+    if end - start < 2 {
+        let x = match_len(&old[suffixes[start as usize] as usize..], new);
+        let y = match_len(&old[suffixes[end as usize] as usize..], new);
+        if x > y {
+            *pos = suffixes[start as usize];
+            x
+        } else {
+            *pos = suffixes[end as usize];
+            y
+        }
+    } else {
+        let middle = start + (end - start) / 2;
+        let suffix = &old[suffixes[middle as usize] as usize..];
+        let length = suffix.len().min(new.len());
+        if suffix[..length] < new[..length] {
+            search(suffixes, old, new, middle, end, pos)
+        } else {
+            search(suffixes, old, new, start, middle, pos)
+        }
+    }
+}
+
+/// builds the suffix array of `old` using the Larsson-Sadakane qsufsort, the same as bsdiff.
+/// the array has one more entry than `old` as the empty suffix is included.
+/// it and the ranks used to build it are an isize per byte of `old`, about 16 times its size on 64-bit.
+fn suffix_sort(old: &[u8]) -> Vec<isize> {
+    let old_size = old.len();
+    let mut suffixes = vec![0isize; old_size + 1];
+    let mut ranks = vec![0isize; old_size + 1];
+
+    let mut buckets = [0isize; 256];
+    for &byte in old {
+        buckets[byte as usize] += 1;
+    }
+    for i in 1..256 {
+        buckets[i] += buckets[i - 1];
+    }
+    for i in (1..256).rev() {
+        buckets[i] = buckets[i - 1];
+    }
+    buckets[0] = 0;
+
+    for (i, &byte) in old.iter().enumerate() {
+        buckets[byte as usize] += 1;
+        suffixes[buckets[byte as usize] as usize] = i as isize;
+    }
+    suffixes[0] = old_size as isize;
+    for (i, &byte) in old.iter().enumerate() {
+        ranks[i] = buckets[byte as usize];
+    }
+    ranks[old_size] = 0;
+    for i in 1..256 {
+        if buckets[i] == buckets[i - 1] + 1 {
+            suffixes[buckets[i] as usize] = -1;
+        }
+    }
+    suffixes[0] = -1;
+
+    let mut h = 1;
+    while suffixes[0] != -(old_size as isize + 1) {
+        let mut len: isize = 0;
+        let mut i: isize = 0;
+        while i < old_size as isize + 1 {
+            if suffixes[i as usize] < 0 {
+                len -= suffixes[i as usize];
+                i -= suffixes[i as usize];
+            } else {
+                if len != 0 {
+                    suffixes[(i - len) as usize] = -len;
+                }
+                len = ranks[suffixes[i as usize] as usize] + 1 - i;
+                split(&mut suffixes, &mut ranks, i, len, h);
+                i += len;
+                len = 0;
+            }
+        }
+        if len != 0 {
+            suffixes[(i - len) as usize] = -len;
+        }
+        h += h;
+    }
+
+    for i in 0..old_size + 1 {
+        suffixes[ranks[i] as usize] = i as isize;
+    }
+    suffixes
+}
+
+/// ternary quicksort of a group of suffixes by the rank `h` bytes ahead.
+fn split(suffixes: &mut [isize], ranks: &mut [isize], start: isize, len: isize, h: isize) {
+    let rank_at = |ranks: &[isize], suffix: isize| ranks[(suffix + h) as usize];
+
+    if len < 16 {
+        let mut k = start;
+        while k < start + len {
+            let mut j: isize = 1;
+            let mut x = rank_at(ranks, suffixes[k as usize]);
+            let mut i: isize = 1;
+            while k + i < start + len {
+                let rank = rank_at(ranks, suffixes[(k + i) as usize]);
+                if rank < x {
+                    x = rank;
+                    j = 0;
+                }
+                if rank == x {
+                    suffixes.swap((k + j) as usize, (k + i) as usize);
+                    j += 1;
+                }
+                i += 1;
+            }
+            for i in 0..j {
+                ranks[suffixes[(k + i) as usize] as usize] = k + j - 1;
+            }
+            if j == 1 {
+                suffixes[k as usize] = -1;
+            }
+            k += j;
+        }
+        return;
+    }
+
+    let x = rank_at(ranks, suffixes[(start + len / 2) as usize]);
+    let mut jj: isize = 0;
+    let mut kk: isize = 0;
+    for i in start..start + len {
+        let rank = rank_at(ranks, suffixes[i as usize]);
+        if rank < x {
+            jj += 1;
+        }
+        if rank == x {
+            kk += 1;
+        }
+    }
+    jj += start;
+    kk += jj;
+
+    let mut i = start;
+    let mut j: isize = 0;
+    let mut k: isize = 0;
+    while i < jj {
+        let rank = rank_at(ranks, suffixes[i as usize]);
+        if rank < x {
+            i += 1;
+        } else if rank == x {
+            suffixes.swap(i as usize, (jj + j) as usize);
+            j += 1;
+        } else {
+            suffixes.swap(i as usize, (kk + k) as usize);
+            k += 1;
+        }
+    }
+    while jj + j < kk {
+        if rank_at(ranks, suffixes[(jj + j) as usize]) == x {
+            j += 1;
+        } else {
+            suffixes.swap((jj + j) as usize, (kk + k) as usize);
+            k += 1;
+        }
+    }
+
+    if jj > start {
+        split(suffixes, ranks, start, jj - start, h);
+    }
+    for i in 0..kk - jj {
+        ranks[suffixes[(jj + i) as usize] as usize] = kk - 1;
+    }
+    if jj == kk - 1 {
+        suffixes[jj as usize] = -1;
+    }
+    if start + len > kk {
+        split(suffixes, ranks, kk, start + len - kk, h);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff, patch};
+
+    #[test]
+    fn round_trip() {
+        let old = b"the quick brown fox jumps over the lazy dog. the quick brown fox.".to_vec();
+        let new = b"the quick red fox jumped over the lazy dogs! the quick brown fox?".to_vec();
+        let delta = diff(&old, &new).unwrap();
+        assert_eq!(patch(&old, &delta).unwrap(), new);
+    }
+
+    #[test]
+    fn from_empty() {
+        let new = b"Pork Chop Sandwiches".to_vec();
+        let delta = diff(&[], &new).unwrap();
+        assert_eq!(patch(&[], &delta).unwrap(), new);
+    }
+
+    #[test]
+    fn to_empty() {
+        let old = b"Pork Chop Sandwiches".to_vec();
+        assert_eq!(patch(&old, &diff(&old, &[]).unwrap()).unwrap(), Vec::<u8>::new());
+        assert_eq!(patch(&[], &diff(&[], &[]).unwrap()).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn completely_changed() {
+        let old = vec![0u8; 4096];
+        let new: Vec<u8> = (0..8192).map(|i| (i % 251 + 1) as u8).collect();
+        let delta = diff(&old, &new).unwrap();
+        assert_eq!(patch(&old, &delta).unwrap(), new);
+    }
+
+    #[test]
+    fn large_file() {
+        // pseudo random data, with a few edits, an insertion and a removal, spans many control tuples.
+        let mut seed: u32 = 0x5EED;
+        let old: Vec<u8> = (0..256 * 1024)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let mut new = old.clone();
+        for i in (0..new.len()).step_by(4099) {
+            new[i] = new[i].wrapping_add(1);
+        }
+        new.splice(1000..1000, b"inserted".iter().cloned());
+        new.drain(100_000..100_500);
+        new.extend_from_slice(&old[..2048]);
+        let delta = diff(&old, &new).unwrap();
+        assert!(delta.len() < new.len() / 4);
+        assert_eq!(patch(&old, &delta).unwrap(), new);
+    }
+
+    #[test]
+    fn corrupt_header() {
+        assert_eq!(patch(b"old", b"BSDIFF39").is_err(), true);
+    }
+}
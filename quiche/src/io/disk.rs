@@ -95,6 +95,7 @@ pub fn to_slash(buf: &Path) -> PathBuf {
 
 /// Deletes all the files in a directory
 /// Allows you to supply a vector of files you'd like to exclude for deletion
+/// Folders left empty are removed deepest first, the directory itself is kept
 pub fn delete_dir_contents(path: &Path, ignored: &Vec<String>) -> Result<(), Error> {
     for entry in WalkDir::new(&path).into_iter().filter_map(|e| e.ok()) {
         if entry.path().exists() && entry.path().is_file() {
@@ -107,7 +108,12 @@ pub fn delete_dir_contents(path: &Path, ignored: &Vec<String>) -> Result<(), Err
             fs::remove_file(entry.path())?;
         }
     }
-    for entry in WalkDir::new(&path).into_iter().filter_map(|e| e.ok()) {
+    for entry in WalkDir::new(&path)
+        .min_depth(1)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
         if entry.path().exists() && entry.path().is_dir() {
             let is_empty = entry
                 .path()
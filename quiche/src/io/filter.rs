@@ -0,0 +1,115 @@
+use glob::{MatchOptions, Pattern, PatternError};
+use std::fmt;
+
+/// the reason a file was left out of a release.
+#[derive(Debug, PartialEq)]
+pub enum FilterReason {
+    /// the file matched the given exclude rule.
+    Excluded(String),
+    /// include rules were given and the file matched none of them.
+    NotIncluded,
+}
+
+impl fmt::Display for FilterReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            FilterReason::Excluded(ref rule) => write!(f, "excluded by \"{}\"", rule),
+            FilterReason::NotIncluded => write!(f, "not matched by any include rule"),
+        }
+    }
+}
+
+/// include and exclude glob rules which are matched against paths relative to a directory.
+/// when no include rules are given every file is included, exclude rules always win.
+pub struct FileFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl FileFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<FileFilter, PatternError> {
+        Ok(FileFilter {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// checks if a relative path passes the filter, returning the reason it was filtered if not.
+    pub fn check(&self, path: &str) -> Result<(), FilterReason> {
+        // rules are always written with forward slashes.
+        let path = path.replace('\\', "/");
+        let options = MatchOptions {
+            case_sensitive: false,
+            require_literal_separator: false,
+            require_literal_leading_dot: false,
+        };
+        if !self.include.is_empty()
+            && !self
+                .include
+                .iter()
+                .any(|rule| rule.matches_with(&path, options))
+        {
+            return Err(FilterReason::NotIncluded);
+        }
+        match self
+            .exclude
+            .iter()
+            .find(|rule| rule.matches_with(&path, options))
+        {
+            Some(rule) => Err(FilterReason::Excluded(rule.as_str().to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// splits a list of relative paths into the files that pass and the files that were filtered out.
+    pub fn apply(&self, files: Vec<String>) -> (Vec<String>, Vec<(String, FilterReason)>) {
+        let mut passed = Vec::with_capacity(files.len());
+        let mut filtered = Vec::new();
+        for file in files {
+            match self.check(&file) {
+                Ok(_) => passed.push(file),
+                Err(reason) => filtered.push((file, reason)),
+            }
+        }
+        (passed, filtered)
+    }
+}
+
+fn compile(rules: &[String]) -> Result<Vec<Pattern>, PatternError> {
+    rules.iter().map(|rule| Pattern::new(rule)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FileFilter, FilterReason};
+
+    fn filter(include: &[&str], exclude: &[&str]) -> FileFilter {
+        let to_vec = |rules: &[&str]| rules.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+        FileFilter::new(&to_vec(include), &to_vec(exclude)).unwrap()
+    }
+
+    #[test]
+    fn exclude_rule() {
+        let filter = filter(&[], &["*.pdb", "logs/**"]);
+        assert_eq!(filter.check("Rainway.exe"), Ok(()));
+        assert_eq!(
+            filter.check("bin\\Rainway.PDB"),
+            Err(FilterReason::Excluded("*.pdb".to_string()))
+        );
+        assert_eq!(
+            filter.check("logs/today.log"),
+            Err(FilterReason::Excluded("logs/**".to_string()))
+        );
+    }
+
+    #[test]
+    fn include_rule() {
+        let filter = filter(&["*.exe", "*.dll"], &["test*"]);
+        assert_eq!(filter.check("Rainway.dll"), Ok(()));
+        assert_eq!(filter.check("readme.txt"), Err(FilterReason::NotIncluded));
+        assert_eq!(
+            filter.check("tests.dll"),
+            Err(FilterReason::Excluded("test*".to_string()))
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use sha1::Sha1;
 use sha2::{Digest, Sha256};
 use std::io::Seek;
 use std::path::PathBuf;
@@ -5,11 +6,28 @@ use std::{fs, io};
 
 /// hashes a file using SHA256 and returns the formatted `{:X}` String.
 pub fn sha_256(path: &PathBuf) -> Option<String> {
+    sha_256_with_size(path).map(|(hash, _size)| hash)
+}
+
+/// hashes a buffer using SHA256 and returns the formatted `{:X}` String.
+pub fn sha_256_bytes(data: &[u8]) -> String {
+    format!("{:X}", Sha256::digest(data))
+}
+
+/// hashes a buffer using SHA1 and returns the formatted `{:X}` String.
+/// only for formats that identify files by SHA1, such as Squirrel's RELEASES.
+pub fn sha_1_bytes(data: &[u8]) -> String {
+    format!("{:X}", Sha1::digest(data))
+}
+
+/// hashes a file using SHA256 and returns the formatted `{:X}` String
+/// along with the number of bytes that were read from it.
+pub fn sha_256_with_size(path: &PathBuf) -> Option<(String, u64)> {
     if let Ok(mut file) = fs::File::open(path) {
         &file.seek(std::io::SeekFrom::Start(0));
         let mut sha256 = Sha256::new();
-        io::copy(&mut file, &mut sha256).unwrap_or(0);
-        return Some(format!("{:X}", sha256.result()));
+        let size = io::copy(&mut file, &mut sha256).ok()?;
+        return Some((format!("{:X}", sha256.result()), size));
     };
     None
 }
@@ -0,0 +1,172 @@
+use crate::os::actions::Action;
+use serde::{Deserialize, Serialize};
+use std::fs::{read_to_string, remove_file, rename, File};
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// the steps of applying an update, in the order they happen.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApplyStep {
+    /// the install is being backed up.
+    Started,
+    /// the install is backed up and the update is being extracted.
+    BackedUp,
+    /// the installed files are being replaced by the update.
+    Replacing,
+    /// the files of the update are in place and its post-install actions are running.
+    Replaced,
+    /// the post-install actions completed, only recording the new version is left.
+    /// it is journaled right after the last action, so an apply that stopped on `Replaced`
+    /// may have run some or all of its actions.
+    Applied,
+}
+
+/// what is done with an apply that was interrupted.
+#[derive(Debug, PartialEq)]
+pub enum Recovery {
+    /// the install was not changed yet, the journal is dropped.
+    Discard,
+    /// the install was partly replaced, it is restored from the backup.
+    RollBack,
+    /// the files of the update are in place but its post-install actions may not all have run.
+    /// they are run again and the update is finished, or it is rolled back if one fails.
+    RunActions,
+    /// the update is in place, it is finished by recording the new version.
+    Finish,
+}
+
+/// a record of an update being applied, written before each step so a process that dies
+/// part way through can be recovered the next time it starts.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApplyJournal {
+    /// the version being applied.
+    pub version: String,
+    pub install_path: PathBuf,
+    /// a full copy of the install made before it was changed.
+    pub backup_path: PathBuf,
+    pub staging_path: PathBuf,
+    /// files inside of the install that are never replaced, such as the running exe.
+    pub ignored_files: Vec<String>,
+    pub step: ApplyStep,
+    /// the post-install actions of the update, so they can be run again on recovery.
+    #[serde(default)]
+    pub post_install: Vec<Action>,
+}
+
+impl ApplyJournal {
+    /// reads the journal at a path, None if no apply is in progress.
+    pub fn read(path: &Path) -> Result<Option<ApplyJournal>, Error> {
+        let contents = match read_to_string(path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        match toml::from_str(&contents) {
+            Ok(j) => Ok(Some(j)),
+            Err(e) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("The update journal {} is unreadable: {}", path.display(), e),
+            )),
+        }
+    }
+
+    /// moves the journal on to a step. it is written aside, flushed to disk and renamed over
+    /// the old journal, so it always holds either the previous step or the new one.
+    pub fn record(&mut self, path: &Path, step: ApplyStep) -> Result<(), Error> {
+        self.step = step;
+        let contents = match toml::to_string(&self) {
+            Ok(c) => c,
+            Err(e) => return Err(Error::new(ErrorKind::Other, e.to_string())),
+        };
+        let mut partial_path = path.to_path_buf();
+        partial_path.set_extension("partial");
+        let mut file = File::create(&partial_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        rename(&partial_path, path)?;
+        log::debug!("journaled the update to {} as {:?}", self.version, step);
+        Ok(())
+    }
+
+    /// deletes the journal once the apply it records has completed or been undone.
+    pub fn remove(path: &Path) -> Result<(), Error> {
+        match remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// decides how an apply that stopped at the journaled step is recovered.
+    pub fn recovery(&self) -> Recovery {
+        match self.step {
+            ApplyStep::Started | ApplyStep::BackedUp => Recovery::Discard,
+            ApplyStep::Replacing => Recovery::RollBack,
+            // restoring the old files would leave the old version with whatever the actions
+            // already changed outside of the install, such as services and registry values.
+            ApplyStep::Replaced => Recovery::RunActions,
+            ApplyStep::Applied => Recovery::Finish,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ApplyJournal, ApplyStep, Recovery};
+    use crate::os::actions::{Action, RegistryHive};
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all};
+    use std::path::PathBuf;
+
+    #[test]
+    fn journal_steps() {
+        let mut dir = temp_dir();
+        dir.push(format!("Quiche_Journal_Test_{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("Rainway_Apply.toml");
+        ApplyJournal::remove(&path).unwrap();
+        assert!(ApplyJournal::read(&path).unwrap().is_none());
+
+        let mut journal = ApplyJournal {
+            version: "2.1.1".to_string(),
+            install_path: PathBuf::from("C:/Program Files/Rainway"),
+            backup_path: PathBuf::from("C:/Temp/Rainway_Backup_2.1.1"),
+            staging_path: PathBuf::from("C:/Temp/Rainway_Stage_2.1.1"),
+            ignored_files: vec!["bootstrapper.exe".to_string()],
+            step: ApplyStep::Started,
+            post_install: vec![
+                Action::SetRegistryValue {
+                    hive: RegistryHive::LocalMachine,
+                    key: "SOFTWARE\\Rainway".to_string(),
+                    name: "Version".to_string(),
+                    value: "{version}".to_string(),
+                },
+                Action::RegisterService {
+                    name: "Rainway".to_string(),
+                    display_name: "Rainway Service".to_string(),
+                    path: "Rainway.Service.exe".to_string(),
+                    args: vec![],
+                },
+            ],
+        };
+        let expected = vec![
+            (ApplyStep::Started, Recovery::Discard),
+            (ApplyStep::BackedUp, Recovery::Discard),
+            (ApplyStep::Replacing, Recovery::RollBack),
+            (ApplyStep::Replaced, Recovery::RunActions),
+            (ApplyStep::Applied, Recovery::Finish),
+        ];
+        for (step, recovery) in expected {
+            journal.record(&path, step).unwrap();
+            let read = ApplyJournal::read(&path).unwrap().unwrap();
+            assert_eq!(read.step, step);
+            assert_eq!(read.backup_path, journal.backup_path);
+            assert_eq!(read.post_install, journal.post_install);
+            assert_eq!(read.recovery(), recovery);
+        }
+        ApplyJournal::remove(&path).unwrap();
+        assert!(ApplyJournal::read(&path).unwrap().is_none());
+        remove_dir_all(&dir).unwrap();
+    }
+}
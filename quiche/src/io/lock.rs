@@ -0,0 +1,73 @@
+use chrono::{SecondsFormat, Utc};
+use std::fs::{read_to_string, remove_file, OpenOptions};
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// the file that marks an output directory as in use by a bake, promotion or ramp.
+pub const LOCK_FILE: &str = ".quiche.lock";
+
+/// an exclusive lock on an output directory, it is released when dropped.
+pub struct OutputLock {
+    path: PathBuf,
+}
+
+impl OutputLock {
+    /// takes the lock on a directory, failing if another process already holds it.
+    /// the lock file records who took it and when, a lock left behind by a crash has to be deleted by hand.
+    pub fn acquire(dir: &Path) -> Result<OutputLock, Error> {
+        let path = dir.join(LOCK_FILE);
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                let owner = read_to_string(&path).unwrap_or_default();
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!(
+                        "{} is locked by another process ({}). delete {} if it is no longer running.",
+                        dir.display(),
+                        owner.trim().replace('\n', ", "),
+                        path.display()
+                    ),
+                ));
+            }
+            Err(e) => return Err(e),
+        };
+        let lock = OutputLock { path };
+        write!(
+            file,
+            "pid = {}\nsince = {}\n",
+            std::process::id(),
+            Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+        )?;
+        log::debug!("locked {}", dir.display());
+        Ok(lock)
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        if let Err(e) = remove_file(&self.path) {
+            log::warn!("unable to release the lock {}. {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OutputLock, LOCK_FILE};
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    #[test]
+    fn exclusive() {
+        let mut dir = temp_dir();
+        dir.push(format!("Quiche_Lock_Test_{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let lock = OutputLock::acquire(&dir).unwrap();
+        assert!(OutputLock::acquire(&dir).is_err());
+        drop(lock);
+        assert!(!dir.join(LOCK_FILE).exists());
+        assert!(OutputLock::acquire(&dir).is_ok());
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,4 +1,15 @@
+pub mod authenticode;
+pub mod bytes;
+pub mod cache;
+pub mod chunk;
+pub mod delta;
 pub mod disk;
+pub mod filter;
 pub mod hash;
 pub mod ico;
+pub mod journal;
+pub mod lock;
+pub mod msi;
+pub mod pe;
+pub mod sign;
 pub mod zip;
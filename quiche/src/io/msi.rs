@@ -0,0 +1,482 @@
+use crate::io::bytes::{le_u16, le_u32, slice};
+use std::collections::HashMap;
+use std::fs::read;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// msi installers are compound files, which start with this signature.
+const CFB_SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+/// marks the last sector of a chain.
+const END_OF_CHAIN: u32 = 0xFFFF_FFFE;
+/// marks an unused entry of the DIFAT.
+const FREE_SECTOR: u32 = 0xFFFF_FFFF;
+/// the header holds the first 109 sectors of the FAT itself.
+const HEADER_DIFAT_ENTRIES: usize = 109;
+/// the size of every entry of the directory.
+const DIRECTORY_ENTRY_SIZE: usize = 128;
+/// the directory entry type of a stream.
+const STREAM_ENTRY: u8 = 2;
+/// the stream names of msi tables start with this character.
+const TABLE_PREFIX: u16 = 0x4840;
+/// the stream a signed msi keeps its Authenticode signature in.
+const DIGITAL_SIGNATURE_STREAM: &str = "\u{5}DigitalSignature";
+
+/// the properties of an msi installer that identify the product it installs.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MsiInfo {
+    /// the version of the product, such as 2.1.1.
+    pub product_version: String,
+    /// the GUID of the product, such as {E312ACD2-6504-4A9E-A968-B12F0242385D}.
+    pub product_code: String,
+}
+
+/// returns true if a file starts with the signature of a compound file, which msi installers are.
+pub fn is_msi(data: &[u8]) -> bool {
+    data.starts_with(&CFB_SIGNATURE)
+}
+
+/// reads the ProductVersion and ProductCode properties of an msi installer.
+pub fn read_msi_info(path: &Path) -> Result<MsiInfo, Error> {
+    parse_msi_info(&read(path)?)
+}
+
+/// parses the ProductVersion and ProductCode properties out of the contents of an msi installer.
+pub fn parse_msi_info(data: &[u8]) -> Result<MsiInfo, Error> {
+    let file = CompoundFile::parse(data)?;
+    let pool = file.read_stream(&table_stream_name("_StringPool"))?;
+    let string_data = file.read_stream(&table_stream_name("_StringData"))?;
+    let (strings, reference_size) = read_string_pool(&pool, &string_data)?;
+    let table = file.read_stream(&table_stream_name("Property"))?;
+    let properties = read_property_table(&table, &strings, reference_size)?;
+    let product_version = match properties.get("ProductVersion") {
+        Some(v) => v.to_string(),
+        None => return Err(invalid("the installer has no ProductVersion property.")),
+    };
+    Ok(MsiInfo {
+        product_version,
+        product_code: properties.get("ProductCode").cloned().unwrap_or_default(),
+    })
+}
+
+/// reads the PKCS#7 SignedData of the Authenticode signature of an msi installer, None if it is not signed.
+pub fn parse_msi_signature(data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    let file = CompoundFile::parse(data)?;
+    let name: Vec<u16> = DIGITAL_SIGNATURE_STREAM.encode_utf16().collect();
+    match file.find_stream(&name) {
+        Some(_) => file.read_stream(&name).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// a stream inside of a compound file.
+struct DirectoryEntry {
+    name: Vec<u16>,
+    kind: u8,
+    start: u32,
+    size: u64,
+}
+
+/// the parts of a compound file needed to read the streams inside of it.
+struct CompoundFile<'a> {
+    data: &'a [u8],
+    sector_size: usize,
+    mini_sector_size: usize,
+    mini_stream_cutoff: u64,
+    fat: Vec<u32>,
+    mini_fat: Vec<u32>,
+    entries: Vec<DirectoryEntry>,
+    mini_stream: Vec<u8>,
+}
+
+impl<'a> CompoundFile<'a> {
+    fn parse(data: &'a [u8]) -> Result<CompoundFile<'a>, Error> {
+        if !is_msi(data) {
+            return Err(invalid("the file is not an msi installer."));
+        }
+        let major_version = le_u16(data, 0x1A)?;
+        let sector_size = match le_u16(data, 0x1E)? {
+            9 => 512,
+            12 => 4096,
+            shift => return Err(invalid(&format!("unknown sector shift {}.", shift))),
+        };
+        let mut file = CompoundFile {
+            data,
+            sector_size,
+            mini_sector_size: 1 << le_u16(data, 0x20)?,
+            mini_stream_cutoff: le_u32(data, 0x38)? as u64,
+            fat: Vec::new(),
+            mini_fat: Vec::new(),
+            entries: Vec::new(),
+            mini_stream: Vec::new(),
+        };
+
+        // the DIFAT lists the sectors the FAT is stored in, it starts in the header
+        // and continues in a chain of sectors which each end with the next one.
+        let fat_sector_count = le_u32(data, 0x2C)? as usize;
+        let mut fat_sectors = Vec::with_capacity(fat_sector_count);
+        for i in 0..HEADER_DIFAT_ENTRIES {
+            fat_sectors.push(le_u32(data, 0x4C + i * 4)?);
+        }
+        let mut difat_sector = le_u32(data, 0x44)?;
+        for _ in 0..le_u32(data, 0x48)? {
+            let sector = file.sector(difat_sector)?;
+            let entries = sector_size / 4 - 1;
+            for i in 0..entries {
+                fat_sectors.push(le_u32(sector, i * 4)?);
+            }
+            difat_sector = le_u32(sector, entries * 4)?;
+        }
+        fat_sectors.retain(|&s| s != FREE_SECTOR);
+        fat_sectors.truncate(fat_sector_count);
+        for sector in fat_sectors {
+            file.fat.extend(to_u32s(file.sector(sector)?));
+        }
+
+        let directory = file.read_chain(le_u32(data, 0x30)?, None)?;
+        for entry in directory.chunks_exact(DIRECTORY_ENTRY_SIZE) {
+            let name_length = (le_u16(entry, 64)? as usize).min(64);
+            let name = entry[..name_length]
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .take_while(|&c| c != 0)
+                .collect();
+            // version 3 files only use the low half of the size.
+            let mut size = le_u32(entry, 120)? as u64;
+            if major_version >= 4 {
+                size |= (le_u32(entry, 124)? as u64) << 32;
+            }
+            file.entries.push(DirectoryEntry {
+                name,
+                kind: entry[66],
+                start: le_u32(entry, 116)?,
+                size,
+            });
+        }
+
+        // small streams are stored in the mini stream, which is the stream of the root entry.
+        let (root_start, root_size) = match file.entries.first() {
+            Some(root) => (root.start, root.size),
+            None => return Err(invalid("the installer has an empty directory.")),
+        };
+        file.mini_stream = file.read_chain(root_start, Some(root_size))?;
+        let mini_fat_start = le_u32(data, 0x3C)?;
+        if mini_fat_start != END_OF_CHAIN {
+            file.mini_fat = to_u32s(&file.read_chain(mini_fat_start, None)?);
+        }
+        Ok(file)
+    }
+
+    fn sector(&self, sector: u32) -> Result<&'a [u8], Error> {
+        let start = (sector as usize + 1) * self.sector_size;
+        if start >= self.data.len() {
+            return Err(invalid(&format!(
+                "the sector {} is past the end of the installer.",
+                sector
+            )));
+        }
+        let end = (start + self.sector_size).min(self.data.len());
+        Ok(&self.data[start..end])
+    }
+
+    /// reads a chain of sectors, cutting it to `size` when the length of the stream is known.
+    fn read_chain(&self, start: u32, size: Option<u64>) -> Result<Vec<u8>, Error> {
+        let mut buffer = Vec::new();
+        for sector in chain(&self.fat, start)? {
+            buffer.extend_from_slice(self.sector(sector)?);
+        }
+        if let Some(size) = size {
+            buffer.truncate(size as usize);
+        }
+        Ok(buffer)
+    }
+
+    /// reads a chain of the mini stream.
+    fn read_mini_chain(&self, start: u32, size: u64) -> Result<Vec<u8>, Error> {
+        let mut buffer = Vec::new();
+        for sector in chain(&self.mini_fat, start)? {
+            let offset = sector as usize * self.mini_sector_size;
+            buffer.extend_from_slice(slice(&self.mini_stream, offset, self.mini_sector_size)?);
+        }
+        buffer.truncate(size as usize);
+        Ok(buffer)
+    }
+
+    fn find_stream(&self, name: &[u16]) -> Option<&DirectoryEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.kind == STREAM_ENTRY && e.name == name)
+    }
+
+    fn read_stream(&self, name: &[u16]) -> Result<Vec<u8>, Error> {
+        let entry = match self.find_stream(name) {
+            Some(e) => e,
+            None => {
+                return Err(invalid(&format!(
+                    "the installer has no {} stream.",
+                    String::from_utf16_lossy(name)
+                )))
+            }
+        };
+        if entry.size < self.mini_stream_cutoff {
+            self.read_mini_chain(entry.start, entry.size)
+        } else {
+            self.read_chain(entry.start, Some(entry.size))
+        }
+    }
+}
+
+/// follows a chain through an allocation table, a chain that loops or leaves the table is an error.
+fn chain(table: &[u32], start: u32) -> Result<Vec<u32>, Error> {
+    let mut sectors = Vec::new();
+    let mut next = start;
+    while next != END_OF_CHAIN {
+        if next as usize >= table.len() || sectors.len() >= table.len() {
+            return Err(invalid("a sector chain of the installer is broken."));
+        }
+        sectors.push(next);
+        next = table[next as usize];
+    }
+    Ok(sectors)
+}
+
+fn to_u32s(data: &[u8]) -> Vec<u32> {
+    data.chunks_exact(4)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+/// msi packs two characters of a stream name into one, using a 64 character alphabet.
+fn encode_name_char(c: u8) -> Option<u16> {
+    match c {
+        b'0'..=b'9' => Some((c - b'0') as u16),
+        b'A'..=b'Z' => Some((c - b'A') as u16 + 10),
+        b'a'..=b'z' => Some((c - b'a') as u16 + 36),
+        b'.' => Some(62),
+        b'_' => Some(63),
+        _ => None,
+    }
+}
+
+/// the name of the stream an msi table is stored in.
+fn table_stream_name(table: &str) -> Vec<u16> {
+    let bytes = table.as_bytes();
+    let mut name = vec![TABLE_PREFIX];
+    let mut i = 0;
+    while i < bytes.len() {
+        let next = bytes.get(i + 1).and_then(|&c| encode_name_char(c));
+        match (encode_name_char(bytes[i]), next) {
+            (Some(first), Some(second)) => {
+                name.push(0x3800 + first + (second << 6));
+                i += 2;
+            }
+            (Some(first), None) => {
+                name.push(0x4800 + first);
+                i += 1;
+            }
+            (None, _) => {
+                name.push(bytes[i] as u16);
+                i += 1;
+            }
+        }
+    }
+    name
+}
+
+/// reads the shared strings of an msi, tables refer to them by id starting at one.
+/// returns them along with how many bytes a reference to a string takes up in a table.
+fn read_string_pool(pool: &[u8], data: &[u8]) -> Result<(Vec<String>, usize), Error> {
+    // the pool starts with the codepage, its top bit is set when references are three bytes.
+    let reference_size = if le_u16(pool, 2)? & 0x8000 != 0 { 3 } else { 2 };
+    let mut strings = vec![String::new()];
+    let mut offset = 0;
+    let mut i = 1;
+    let count = pool.len() / 4;
+    while i < count {
+        let length = le_u16(pool, i * 4)? as usize;
+        let references = le_u16(pool, i * 4 + 2)? as usize;
+        let length = match (length, references) {
+            (0, 0) => {
+                strings.push(String::new());
+                i += 1;
+                continue;
+            }
+            // a string over 64k is preceded by an empty entry holding the high half of its length.
+            (0, high) => {
+                i += 2;
+                (high << 16) + le_u16(pool, i * 4 - 4)? as usize
+            }
+            (length, _) => {
+                i += 1;
+                length
+            }
+        };
+        strings.push(String::from_utf8_lossy(slice(data, offset, length)?).to_string());
+        offset += length;
+    }
+    Ok((strings, reference_size))
+}
+
+/// reads the Property table, which is stored a column at a time: every name and then every value.
+fn read_property_table(
+    table: &[u8],
+    strings: &[String],
+    reference_size: usize,
+) -> Result<HashMap<String, String>, Error> {
+    let rows = table.len() / (reference_size * 2);
+    let reference = |index: usize| -> Result<&str, Error> {
+        let bytes = slice(table, index * reference_size, reference_size)?;
+        let id = bytes
+            .iter()
+            .rev()
+            .fold(0usize, |id, &b| (id << 8) | b as usize);
+        match strings.get(id) {
+            Some(s) => Ok(s.as_str()),
+            None => Err(invalid(&format!(
+                "the string {} is not in the string pool.",
+                id
+            ))),
+        }
+    };
+    let mut properties = HashMap::with_capacity(rows);
+    for row in 0..rows {
+        properties.insert(
+            reference(row)?.to_string(),
+            reference(rows + row)?.to_string(),
+        );
+    }
+    Ok(properties)
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_msi_info, table_stream_name, MsiInfo, END_OF_CHAIN, FREE_SECTOR};
+
+    fn put_u16(data: &mut [u8], offset: usize, value: u16) {
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// builds a version 3 compound file with every stream stored in the mini stream.
+    /// sector 0 holds the FAT, 1 the directory, 2 the mini FAT and the mini stream follows.
+    fn compound_file(streams: &[(Vec<u16>, Vec<u8>)]) -> Vec<u8> {
+        let mut mini_stream = Vec::new();
+        let mut mini_fat = Vec::new();
+        let mut starts = Vec::new();
+        for (_, data) in streams {
+            let start = mini_fat.len() as u32;
+            let sectors = (data.len() + 63) / 64;
+            for i in 0..sectors as u32 {
+                mini_fat.push(if i + 1 == sectors as u32 {
+                    END_OF_CHAIN
+                } else {
+                    start + i + 1
+                });
+            }
+            starts.push(start);
+            mini_stream.extend(data);
+            mini_stream.resize(mini_fat.len() * 64, 0);
+        }
+        let mini_stream_sectors = (mini_stream.len() + 511) / 512;
+
+        let mut file = vec![0u8; 512 * (4 + mini_stream_sectors)];
+        file[..8].copy_from_slice(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]);
+        put_u16(&mut file, 0x18, 0x3E);
+        put_u16(&mut file, 0x1A, 3);
+        put_u16(&mut file, 0x1C, 0xFFFE);
+        put_u16(&mut file, 0x1E, 9);
+        put_u16(&mut file, 0x20, 6);
+        put_u32(&mut file, 0x2C, 1);
+        put_u32(&mut file, 0x30, 1);
+        put_u32(&mut file, 0x38, 4096);
+        put_u32(&mut file, 0x3C, 2);
+        put_u32(&mut file, 0x40, 1);
+        put_u32(&mut file, 0x44, END_OF_CHAIN);
+        for i in 0..109 {
+            put_u32(
+                &mut file,
+                0x4C + i * 4,
+                if i == 0 { 0 } else { FREE_SECTOR },
+            );
+        }
+
+        let mut fat = vec![0xFFFF_FFFD, END_OF_CHAIN, END_OF_CHAIN];
+        for i in 0..mini_stream_sectors as u32 {
+            fat.push(if i + 1 == mini_stream_sectors as u32 {
+                END_OF_CHAIN
+            } else {
+                4 + i
+            });
+        }
+        fat.resize(128, FREE_SECTOR);
+        for (i, value) in fat.iter().enumerate() {
+            put_u32(&mut file, 512 + i * 4, *value);
+        }
+
+        let root: Vec<u16> = "Root Entry".encode_utf16().collect();
+        let mut entries = vec![(root, 5u8, 3u32, mini_stream.len())];
+        for ((name, data), start) in streams.iter().zip(starts) {
+            entries.push((name.clone(), 2, start, data.len()));
+        }
+        for (i, (name, kind, start, size)) in entries.iter().enumerate() {
+            let entry = 1024 + i * 128;
+            for (j, c) in name.iter().enumerate() {
+                put_u16(&mut file, entry + j * 2, *c);
+            }
+            put_u16(&mut file, entry + 64, (name.len() as u16 + 1) * 2);
+            file[entry + 66] = *kind;
+            put_u32(&mut file, entry + 68, FREE_SECTOR);
+            put_u32(&mut file, entry + 72, FREE_SECTOR);
+            put_u32(&mut file, entry + 76, FREE_SECTOR);
+            put_u32(&mut file, entry + 116, *start);
+            put_u32(&mut file, entry + 120, *size as u32);
+        }
+
+        for (i, value) in mini_fat.iter().enumerate() {
+            put_u32(&mut file, 1536 + i * 4, *value);
+        }
+        file[2048..2048 + mini_stream.len()].copy_from_slice(&mini_stream);
+        file
+    }
+
+    #[test]
+    fn product_properties() {
+        let strings = [
+            "ProductVersion",
+            "2.1.1",
+            "ProductCode",
+            "{E312ACD2-6504-4A9E-A968-B12F0242385D}",
+        ];
+        let mut pool = vec![0xE4, 0x04, 0, 0];
+        let mut data = Vec::new();
+        for s in &strings {
+            pool.extend(&(s.len() as u16).to_le_bytes());
+            pool.extend(&1u16.to_le_bytes());
+            data.extend(s.as_bytes());
+        }
+        // two rows, the name column and then the value column.
+        let mut property = Vec::new();
+        for id in &[1u16, 3, 2, 4] {
+            property.extend(&id.to_le_bytes());
+        }
+        let file = compound_file(&[
+            (table_stream_name("_StringPool"), pool),
+            (table_stream_name("_StringData"), data),
+            (table_stream_name("Property"), property),
+        ]);
+        assert_eq!(
+            parse_msi_info(&file).unwrap(),
+            MsiInfo {
+                product_version: "2.1.1".to_string(),
+                product_code: "{E312ACD2-6504-4A9E-A968-B12F0242385D}".to_string(),
+            }
+        );
+        assert!(parse_msi_info(b"not an msi").is_err());
+    }
+}
@@ -0,0 +1,403 @@
+use crate::io::bytes::{le_u16, le_u32, slice, utf16_until_nul};
+use std::fs::read;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// the resource type id of a VERSIONINFO resource.
+const RT_VERSION: u32 = 16;
+/// the index of the resource table in the data directories of the optional header.
+const RESOURCE_DIRECTORY: usize = 2;
+/// the index of the certificate table, which holds the Authenticode signatures of the file.
+const SECURITY_DIRECTORY: usize = 4;
+/// the WIN_CERTIFICATE type of a PKCS#7 SignedData signature.
+const WIN_CERT_TYPE_PKCS_SIGNED_DATA: u16 = 2;
+/// VS_FIXEDFILEINFO starts with this signature.
+const FIXED_FILE_INFO_SIGNATURE: u32 = 0xFEEF_04BD;
+/// the high bit of a resource directory entry marks a subdirectory or a named entry.
+const HIGH_BIT: u32 = 0x8000_0000;
+
+/// the VERSIONINFO resource of a PE file such as an exe or dll.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PeVersion {
+    /// the numeric file version of VS_FIXEDFILEINFO, such as 2.1.1.0.
+    pub file_version: String,
+    /// the numeric product version of VS_FIXEDFILEINFO.
+    pub product_version: String,
+    /// the ProductVersion text of the StringFileInfo block, which can be anything such as 2.1.1-a84c3f16.
+    pub product_version_text: Option<String>,
+}
+
+impl PeVersion {
+    /// the version the product describes itself with, the text is preferred as it can carry a pre-release.
+    pub fn version(&self) -> &str {
+        match &self.product_version_text {
+            Some(v) if !v.trim().is_empty() => v.trim(),
+            _ => &self.product_version,
+        }
+    }
+}
+
+/// the Authenticode signatures of a PE file and the parts of the file they are a digest of.
+pub struct SecurityDirectory<'a> {
+    /// the PKCS#7 SignedData of each signature in the certificate table.
+    pub signatures: Vec<&'a [u8]>,
+    /// the file with its checksum, the certificate table entry and the certificate table left out.
+    pub signed_ranges: Vec<&'a [u8]>,
+}
+
+/// the parts of the headers of a PE file needed to find its data directories and sections.
+struct Headers {
+    /// the offset of the CheckSum field of the optional header.
+    checksum: usize,
+    directories: usize,
+    directory_count: usize,
+    sections: usize,
+    section_count: usize,
+}
+
+impl Headers {
+    /// returns the address and size of a data directory, None if the file does not have it.
+    fn directory(&self, data: &[u8], index: usize) -> Result<Option<(u32, u32)>, Error> {
+        if index >= self.directory_count {
+            return Ok(None);
+        }
+        let address = le_u32(data, self.directories + index * 8)?;
+        let size = le_u32(data, self.directories + index * 8 + 4)?;
+        if address == 0 || size == 0 {
+            return Ok(None);
+        }
+        Ok(Some((address, size)))
+    }
+}
+
+/// returns true if a file starts with the MZ header of a PE file.
+pub fn is_pe(data: &[u8]) -> bool {
+    data.starts_with(b"MZ")
+}
+
+/// reads the VERSIONINFO resource of a PE file, None if it does not have one.
+pub fn read_pe_version(path: &Path) -> Result<Option<PeVersion>, Error> {
+    parse_pe_version(&read(path)?)
+}
+
+/// parses the VERSIONINFO resource out of the contents of a PE file, None if it does not have one.
+pub fn parse_pe_version(data: &[u8]) -> Result<Option<PeVersion>, Error> {
+    let headers = read_headers(data)?;
+    let resource_rva = match headers.directory(data, RESOURCE_DIRECTORY)? {
+        Some((address, _)) => address,
+        None => return Ok(None),
+    };
+
+    let mut sections = Vec::with_capacity(headers.section_count);
+    for i in 0..headers.section_count {
+        let header = headers.sections + i * 40;
+        let virtual_size = le_u32(data, header + 8)?;
+        let virtual_address = le_u32(data, header + 12)?;
+        let raw_size = le_u32(data, header + 16)?;
+        let raw_pointer = le_u32(data, header + 20)?;
+        sections.push((virtual_address, virtual_size.max(raw_size), raw_pointer));
+    }
+    let to_offset = |rva: u32| -> Result<usize, Error> {
+        for (address, size, pointer) in &sections {
+            if rva >= *address && rva - address < *size {
+                return Ok((rva - address + pointer) as usize);
+            }
+        }
+        Err(invalid(&format!("the address {:#x} is not inside any section.", rva)))
+    };
+
+    // the resource tree is three levels deep: type, name and then language.
+    let resources = to_offset(resource_rva)?;
+    let names = match find_entry(data, resources, 0, Some(RT_VERSION))? {
+        Some(e) if e & HIGH_BIT != 0 => (e & !HIGH_BIT) as usize,
+        _ => return Ok(None),
+    };
+    let languages = match find_entry(data, resources, names, None)? {
+        Some(e) if e & HIGH_BIT != 0 => (e & !HIGH_BIT) as usize,
+        _ => return Ok(None),
+    };
+    let data_entry = match find_entry(data, resources, languages, None)? {
+        Some(e) if e & HIGH_BIT == 0 => resources + e as usize,
+        _ => return Ok(None),
+    };
+    let version_offset = to_offset(le_u32(data, data_entry)?)?;
+    let version_size = le_u32(data, data_entry + 4)? as usize;
+    parse_version_info(slice(data, version_offset, version_size)?).map(Some)
+}
+
+/// reads the certificate table of a PE file, None if the file is not signed.
+pub fn parse_security_directory(data: &[u8]) -> Result<Option<SecurityDirectory<'_>>, Error> {
+    let headers = read_headers(data)?;
+    // unlike the other data directories the certificate table is found by file offset rather than address.
+    let (offset, size) = match headers.directory(data, SECURITY_DIRECTORY)? {
+        Some((offset, size)) => (offset as usize, size as usize),
+        None => return Ok(None),
+    };
+    let entry = headers.directories + SECURITY_DIRECTORY * 8;
+    if offset < entry + 8 {
+        return Err(invalid("the certificate table overlaps the headers."));
+    }
+    let table = slice(data, offset, size)?;
+    let mut signatures = Vec::new();
+    let mut position = 0;
+    while position + 8 <= table.len() {
+        let length = le_u32(table, position)? as usize;
+        if length < 8 {
+            return Err(invalid("the certificate table has an entry shorter than its header."));
+        }
+        if le_u16(table, position + 6)? == WIN_CERT_TYPE_PKCS_SIGNED_DATA {
+            signatures.push(slice(table, position + 8, length - 8)?);
+        }
+        // entries are aligned to 8 bytes.
+        position += (length + 7) & !7;
+    }
+    Ok(Some(SecurityDirectory {
+        signatures,
+        signed_ranges: vec![
+            &data[..headers.checksum],
+            &data[headers.checksum + 4..entry],
+            &data[entry + 8..offset],
+            &data[offset + size..],
+        ],
+    }))
+}
+
+fn read_headers(data: &[u8]) -> Result<Headers, Error> {
+    if !is_pe(data) {
+        return Err(invalid("the file is not a PE file."));
+    }
+    let pe_offset = le_u32(data, 0x3C)? as usize;
+    if slice(data, pe_offset, 4)? != b"PE\0\0" {
+        return Err(invalid("the file has no PE signature."));
+    }
+    let coff = pe_offset + 4;
+    let optional = coff + 20;
+    let directories = match le_u16(data, optional)? {
+        0x10B => optional + 96,
+        0x20B => optional + 112,
+        magic => return Err(invalid(&format!("unknown optional header magic {:#x}.", magic))),
+    };
+    Ok(Headers {
+        checksum: optional + 64,
+        directories,
+        directory_count: le_u32(data, directories - 4)? as usize,
+        sections: optional + le_u16(data, coff + 16)? as usize,
+        section_count: le_u16(data, coff + 2)? as usize,
+    })
+}
+
+/// returns the data of the entry of a resource directory with the given id, or its first entry.
+fn find_entry(
+    data: &[u8],
+    resources: usize,
+    directory: usize,
+    id: Option<u32>,
+) -> Result<Option<u32>, Error> {
+    let directory = resources + directory;
+    let count = le_u16(data, directory + 12)? as usize + le_u16(data, directory + 14)? as usize;
+    for i in 0..count {
+        let entry = directory + 16 + i * 8;
+        let name = le_u32(data, entry)?;
+        if id.is_none() || id == Some(name) {
+            return Ok(Some(le_u32(data, entry + 4)?));
+        }
+    }
+    Ok(None)
+}
+
+/// a node of a VERSIONINFO resource: a key, a value and the nodes nested inside of it.
+struct Block<'a> {
+    key: String,
+    value: &'a [u8],
+    children: &'a [u8],
+}
+
+/// reads the block at the start of a buffer, returning it along with the length it takes up.
+fn read_block(data: &[u8]) -> Result<(Block<'_>, usize), Error> {
+    let length = le_u16(data, 0)? as usize;
+    let value_length = le_u16(data, 2)? as usize;
+    let text = le_u16(data, 4)? == 1;
+    if length < 6 || length > data.len() {
+        return Err(invalid("a VERSIONINFO block has an invalid length."));
+    }
+    let data = &data[..length];
+    let key = utf16_until_nul(&data[6..]);
+    let value_start = align(6 + (key.encode_utf16().count() + 1) * 2).min(length);
+    // text values are measured in characters, which some compilers get wrong,
+    // so a string runs to the end of its block as it never has children.
+    let value_end = match text {
+        true if value_length > 0 => length,
+        _ => (value_start + value_length).min(length),
+    };
+    let children_start = align(value_end).min(length);
+    Ok((
+        Block {
+            key,
+            value: &data[value_start..value_end],
+            children: &data[children_start..],
+        },
+        align(length),
+    ))
+}
+
+/// reads every block of a buffer that holds a list of them.
+fn read_children(mut data: &[u8]) -> Result<Vec<Block<'_>>, Error> {
+    let mut blocks = Vec::new();
+    while data.len() >= 6 {
+        let (block, length) = read_block(data)?;
+        blocks.push(block);
+        data = &data[length.min(data.len())..];
+    }
+    Ok(blocks)
+}
+
+fn parse_version_info(data: &[u8]) -> Result<PeVersion, Error> {
+    let (root, _) = read_block(data)?;
+    if root.key != "VS_VERSION_INFO" {
+        return Err(invalid("the version resource is not a VS_VERSION_INFO block."));
+    }
+    if le_u32(root.value, 0)? != FIXED_FILE_INFO_SIGNATURE {
+        return Err(invalid("the version resource has no VS_FIXEDFILEINFO."));
+    }
+    let numeric = |offset: usize| -> Result<String, Error> {
+        let most = le_u32(root.value, offset)?;
+        let least = le_u32(root.value, offset + 4)?;
+        Ok(format!(
+            "{}.{}.{}.{}",
+            most >> 16,
+            most & 0xFFFF,
+            least >> 16,
+            least & 0xFFFF
+        ))
+    };
+    let mut version = PeVersion {
+        file_version: numeric(8)?,
+        product_version: numeric(16)?,
+        product_version_text: None,
+    };
+    for info in read_children(root.children)? {
+        if info.key != "StringFileInfo" {
+            continue;
+        }
+        for table in read_children(info.children)? {
+            for string in read_children(table.children)? {
+                if string.key == "ProductVersion" && version.product_version_text.is_none() {
+                    version.product_version_text = Some(utf16_until_nul(string.value));
+                }
+            }
+        }
+    }
+    Ok(version)
+}
+
+/// blocks and values of a VERSIONINFO resource start on 32-bit boundaries.
+fn align(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_pe_version, PeVersion};
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .chain(Some(0))
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    /// builds a VERSIONINFO block, `text` values are measured in characters.
+    fn block(key: &str, value: &[u8], text: bool, children: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0u8; 6];
+        data.extend(utf16(key));
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        data.extend_from_slice(value);
+        for child in children {
+            while data.len() % 4 != 0 {
+                data.push(0);
+            }
+            data.extend(child);
+        }
+        let value_length = if text { value.len() / 2 } else { value.len() };
+        let length = data.len() as u16;
+        data[0..2].copy_from_slice(&length.to_le_bytes());
+        data[2..4].copy_from_slice(&(value_length as u16).to_le_bytes());
+        data[4..6].copy_from_slice(&(text as u16).to_le_bytes());
+        data
+    }
+
+    fn version_resource() -> Vec<u8> {
+        let mut fixed = Vec::new();
+        for value in &[0xFEEF_04BDu32, 0x10000, 0x2_0001, 0x1_0000, 0x2_0001, 0x1_0000] {
+            fixed.extend(&value.to_le_bytes());
+        }
+        fixed.resize(52, 0);
+        let product = block("ProductVersion", &utf16("2.1.1-a84c3f16"), true, &[]);
+        let file = block("FileVersion", &utf16("2.1.1.0"), true, &[]);
+        let table = block("040904b0", &[], true, &[file, product]);
+        let strings = block("StringFileInfo", &[], true, &[table]);
+        block("VS_VERSION_INFO", &fixed, false, &[strings])
+    }
+
+    /// builds a 32-bit PE file with a single .rsrc section holding a version resource.
+    fn pe_file() -> Vec<u8> {
+        let version = version_resource();
+        let mut data = vec![0u8; 0x200];
+        data[0..2].copy_from_slice(b"MZ");
+        data[0x3C..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        data[0x40..0x44].copy_from_slice(b"PE\0\0");
+        let coff = 0x44;
+        data[coff + 2..coff + 4].copy_from_slice(&1u16.to_le_bytes());
+        data[coff + 16..coff + 18].copy_from_slice(&224u16.to_le_bytes());
+        let optional = coff + 20;
+        data[optional..optional + 2].copy_from_slice(&0x10Bu16.to_le_bytes());
+        data[optional + 92..optional + 96].copy_from_slice(&16u32.to_le_bytes());
+        let raw_size = 0x100 + version.len() as u32;
+        data[optional + 96 + 16..optional + 96 + 20].copy_from_slice(&0x1000u32.to_le_bytes());
+        data[optional + 96 + 20..optional + 96 + 24].copy_from_slice(&raw_size.to_le_bytes());
+        let section = optional + 224;
+        data[section..section + 5].copy_from_slice(b".rsrc");
+        data[section + 8..section + 12].copy_from_slice(&raw_size.to_le_bytes());
+        data[section + 12..section + 16].copy_from_slice(&0x1000u32.to_le_bytes());
+        data[section + 16..section + 20].copy_from_slice(&raw_size.to_le_bytes());
+        data[section + 20..section + 24].copy_from_slice(&0x200u32.to_le_bytes());
+
+        let mut rsrc = vec![0u8; 0x100];
+        // type directory, name directory and language directory, each with one id entry.
+        for (i, (id, target)) in [(16u32, 0x8000_0018u32), (1, 0x8000_0030), (1033, 0x48)]
+            .iter()
+            .enumerate()
+        {
+            let directory = i * 0x18;
+            rsrc[directory + 14..directory + 16].copy_from_slice(&1u16.to_le_bytes());
+            rsrc[directory + 16..directory + 20].copy_from_slice(&id.to_le_bytes());
+            rsrc[directory + 20..directory + 24].copy_from_slice(&target.to_le_bytes());
+        }
+        rsrc[0x48..0x4C].copy_from_slice(&0x1100u32.to_le_bytes());
+        rsrc[0x4C..0x50].copy_from_slice(&(version.len() as u32).to_le_bytes());
+        rsrc.extend(version);
+        data.extend(rsrc);
+        data
+    }
+
+    #[test]
+    fn version_info() {
+        let version = parse_pe_version(&pe_file()).unwrap().unwrap();
+        assert_eq!(
+            version,
+            PeVersion {
+                file_version: "2.1.1.0".to_string(),
+                product_version: "2.1.1.0".to_string(),
+                product_version_text: Some("2.1.1-a84c3f16".to_string()),
+            }
+        );
+        assert_eq!(version.version(), "2.1.1-a84c3f16");
+        assert!(parse_pe_version(b"not a pe file").is_err());
+    }
+}
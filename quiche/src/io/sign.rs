@@ -0,0 +1,132 @@
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use std::convert::TryFrom;
+use std::fs::{read_to_string, write};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// detached signatures are stored next to the file they sign with this extension appended.
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// returns the path or URL of the detached signature for a given file.
+pub fn signature_path(path: &str) -> String {
+    format!("{}.{}", path, SIGNATURE_EXTENSION)
+}
+
+/// generates a new Ed25519 signing key and writes its secret half as hex to `path`.
+/// the key should be kept offline, only the returned public key is built into clients.
+pub fn generate_signing_key(path: &Path) -> Result<String, Error> {
+    if path.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("Refusing to overwrite the signing key at {}", path.display()),
+        ));
+    }
+    let secret = SecretKey::generate(&mut rand::rngs::OsRng);
+    let public = PublicKey::from(&secret);
+    write(path, hex::encode(secret.as_bytes()))?;
+    Ok(hex::encode(public.as_bytes()))
+}
+
+/// reads a hex encoded Ed25519 secret key created by `generate_signing_key`.
+pub fn load_signing_key(path: &Path) -> Result<Keypair, Error> {
+    let contents = read_to_string(path)?;
+    let secret = hex::decode(contents.trim())
+        .ok()
+        .and_then(|bytes| SecretKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is not a valid Ed25519 signing key.", path.display()),
+            )
+        })?;
+    Ok(Keypair {
+        public: PublicKey::from(&secret),
+        secret,
+    })
+}
+
+/// signs the contents of a file and writes the hex encoded detached signature next to it.
+pub fn sign_file(key: &Keypair, path: &Path) -> Result<PathBuf, Error> {
+    let data = std::fs::read(path)?;
+    let signature_file = PathBuf::from(signature_path(&path.to_string_lossy()));
+    write(&signature_file, sign(key, &data))?;
+    Ok(signature_file)
+}
+
+/// signs data and returns the hex encoded signature.
+pub fn sign(key: &Keypair, data: &[u8]) -> String {
+    hex::encode(key.sign(data).to_bytes().as_ref())
+}
+
+/// signs data and returns the base64 encoded signature, which is how Sparkle expects it.
+pub fn sign_base64(key: &Keypair, data: &[u8]) -> String {
+    base64::encode(key.sign(data).to_bytes().as_ref())
+}
+
+/// checks a hex encoded detached signature against a list of hex encoded public keys.
+/// returns true if any of the keys produced the signature.
+pub fn verify_signature(data: &[u8], signature: &str, public_keys: &[&str]) -> bool {
+    let signature = match hex::decode(signature.trim())
+        .ok()
+        .and_then(|bytes| Signature::try_from(&bytes[..]).ok())
+    {
+        Some(s) => s,
+        None => return false,
+    };
+    public_keys
+        .iter()
+        .filter_map(|key| hex::decode(key.trim()).ok())
+        .filter_map(|bytes| PublicKey::from_bytes(&bytes).ok())
+        .any(|key| key.verify(data, &signature).is_ok())
+}
+
+/// the public keys release metadata must be signed with, built into the client at compile time.
+pub fn get_release_public_keys() -> Vec<&'static str> {
+    env!("RELEASE_PUBLIC_KEYS")
+        .split(',')
+        .map(|key| key.trim())
+        .filter(|key| !key.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sign, verify_signature};
+    use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+
+    fn keypair() -> Keypair {
+        let secret = SecretKey::from_bytes(&[7u8; 32]).unwrap();
+        Keypair {
+            public: PublicKey::from(&secret),
+            secret,
+        }
+    }
+
+    #[test]
+    fn trusted_signature() {
+        let key = keypair();
+        let public = hex::encode(key.public.as_bytes());
+        let signature = sign(&key, b"version = \"2.0.0\"");
+        assert_eq!(
+            verify_signature(b"version = \"2.0.0\"", &signature, &[&public]),
+            true
+        );
+    }
+
+    #[test]
+    fn tampered_data() {
+        let key = keypair();
+        let public = hex::encode(key.public.as_bytes());
+        let signature = sign(&key, b"version = \"2.0.0\"");
+        assert_eq!(
+            verify_signature(b"version = \"6.6.6\"", &signature, &[&public]),
+            false
+        );
+    }
+
+    #[test]
+    fn untrusted_key() {
+        let signature = sign(&keypair(), b"version = \"2.0.0\"");
+        assert_eq!(verify_signature(b"version = \"2.0.0\"", &signature, &[]), false);
+    }
+}
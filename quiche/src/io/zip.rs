@@ -1,14 +1,120 @@
-use std::fs::{create_dir_all, File};
-use std::io::{copy, Error};
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::fs::{create_dir_all, remove_dir_all, rename, File};
+use std::io::{copy, Error, ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::io::cache::BakeCache;
 use crate::io::disk::get_dir_files;
-use std::io::prelude::*;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use zip::result::ZipError;
 use zip::write::FileOptions;
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+/// files larger than this need zip64 headers.
+const ZIP64_THRESHOLD: u64 = 0xFFFF_FFFF;
+
+/// the codec used to compress the files inside of a zip.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    Store,
+    Deflate,
+    Bzip2,
+    Zstd,
+}
+
+impl Default for Codec {
+    fn default() -> Codec {
+        Codec::Bzip2
+    }
+}
+
+impl Codec {
+    /// checks that a compression level is one the codec accepts, store takes no level at all.
+    pub fn check_level(&self, level: Option<i32>) -> Result<(), String> {
+        let level = match level {
+            Some(l) => l,
+            None => return Ok(()),
+        };
+        let (min, max) = match self {
+            Codec::Store => {
+                return Err(
+                    "The store codec does not compress, so it takes no compression level."
+                        .to_string(),
+                )
+            }
+            Codec::Deflate | Codec::Bzip2 => (0, 9),
+            Codec::Zstd => (-7, 22),
+        };
+        if level < min || level > max {
+            return Err(format!(
+                "The compression level {} is outside of {} to {} for the {:?} codec.",
+                level, min, max, self
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl From<Codec> for CompressionMethod {
+    fn from(codec: Codec) -> Self {
+        match codec {
+            Codec::Store => CompressionMethod::Stored,
+            Codec::Deflate => CompressionMethod::Deflated,
+            Codec::Bzip2 => CompressionMethod::Bzip2,
+            Codec::Zstd => CompressionMethod::Zstd,
+        }
+    }
+}
+
+/// settings for how a zip file is written.
+#[derive(Clone, Copy, Default)]
+pub struct ZipOptions {
+    /// writes entries in a sorted order with a fixed timestamp and permissions,
+    /// so the same files always produce a byte identical archive.
+    pub reproducible: bool,
+    /// the codec files are compressed with.
+    pub codec: Codec,
+    /// the compression level of the codec, its default level is used when empty.
+    pub level: Option<i32>,
+    /// how many files are compressed at once, zero uses every core.
+    pub threads: usize,
+}
 
 /// creates a zip file from a given directory
-pub fn zip_with_progress<F>(input: &PathBuf, output: &PathBuf, callback: F) -> Result<(), Error>
+pub fn zip_with_progress<F>(
+    input: &PathBuf,
+    output: &PathBuf,
+    options: ZipOptions,
+    callback: F,
+) -> Result<(), Error>
+where
+    F: Fn(String),
+{
+    if !&input.is_dir() {
+        return Err(Error::from(ZipError::FileNotFound));
+    }
+    let files = get_dir_files(&input)?;
+    zip_files_with_progress(input, &files, output, options, None, callback)
+}
+
+/// creates a zip file from a list of files relative to a given directory.
+/// files are compressed in parallel into single entry archives which are then
+/// copied into the output in order without being compressed again.
+/// with a cache those archives are kept, and a file that has not changed since it was
+/// last compressed with the same options reuses its archive.
+pub fn zip_files_with_progress<F>(
+    input: &PathBuf,
+    files: &Vec<String>,
+    output: &PathBuf,
+    options: ZipOptions,
+    cache: Option<&BakeCache>,
+    callback: F,
+) -> Result<(), Error>
 where
     F: Fn(String),
 {
@@ -16,27 +122,246 @@ where
         return Err(Error::from(ZipError::FileNotFound));
     }
 
+    let mut files = files.clone();
+    let mut file_options = FileOptions::default()
+        .compression_method(options.codec.into())
+        .compression_level(options.level);
+    if options.reproducible {
+        // entries are sorted by the name they will have inside the archive
+        // and every timestamp is pinned to the zip epoch of 1980-01-01.
+        files.sort_by_key(|f| f.replace('\\', "/"));
+        file_options = file_options
+            .last_modified_time(DateTime::default())
+            .unix_permissions(0o644);
+    }
+
+    let pool = match ThreadPoolBuilder::new().num_threads(options.threads).build() {
+        Ok(p) => p,
+        Err(e) => return Err(Error::new(ErrorKind::Other, e.to_string())),
+    };
+    let mut parts_dir = output.clone();
+    parts_dir.set_extension("parts");
+    if parts_dir.exists() {
+        remove_dir_all(&parts_dir)?;
+    }
+    create_dir_all(&parts_dir)?;
+
+    // cached archives hold their entry as it was compressed, so they are only reused with the same settings.
+    let settings = format!(
+        "{:?} {:?} {}",
+        options.codec, options.level, options.reproducible
+    );
+    let reused = AtomicUsize::new(0);
+
     let output_file = File::create(&output)?;
-    let files = get_dir_files(&input)?;
-    let mut zip = zip::ZipWriter::new(output_file);
-    let options = FileOptions::default().compression_method(zip::CompressionMethod::Bzip2);
-    let mut buffer = Vec::new();
-
-    for entry in files {
-        callback(entry.clone());
-        let mut file_path = PathBuf::new();
-        file_path.push(&input);
-        file_path.push(&entry);
-        zip.start_file_from_path(Path::new(&entry), options)?;
-        let mut f = File::open(&file_path)?;
-        f.read_to_end(&mut buffer)?;
-        zip.write_all(&*buffer)?;
-        buffer.clear();
+    let mut zip = ZipWriter::new(output_file);
+    // compressed parts are merged a batch at a time so they never take up
+    // more than a few files worth of extra disk space.
+    let batch_size = pool.current_num_threads() * 4;
+    for (batch, entries) in files.chunks(batch_size).enumerate() {
+        let parts = pool.install(|| {
+            entries
+                .par_iter()
+                .enumerate()
+                .map(|(i, entry)| {
+                    let cache = match cache {
+                        Some(c) => c,
+                        None => {
+                            let mut part_path = parts_dir.clone();
+                            part_path.push(format!("{}.zip", batch * batch_size + i));
+                            compress_part(input, entry, &part_path, file_options)?;
+                            return Ok(part_path);
+                        }
+                    };
+                    let mut file_path = input.clone();
+                    file_path.push(&entry);
+                    let (hash, _) = cache.hash_file(&file_path)?;
+                    let part_path = cache.entry_path(&entry.replace('\\', "/"), &hash, &settings);
+                    if part_path.exists() {
+                        reused.fetch_add(1, Ordering::Relaxed);
+                    } else {
+                        // compressed next to the entry first so an interrupted bake never leaves a partial entry behind.
+                        let partial_path = part_path.with_extension("tmp");
+                        compress_part(input, entry, &partial_path, file_options)?;
+                        rename(&partial_path, &part_path)?;
+                    }
+                    Ok(part_path)
+                })
+                .collect::<Result<Vec<PathBuf>, Error>>()
+        });
+        let parts = match parts {
+            Ok(p) => p,
+            Err(e) => {
+                remove_dir_all(&parts_dir)?;
+                return Err(e);
+            }
+        };
+        for (entry, part_path) in entries.iter().zip(parts) {
+            callback(entry.clone());
+            let mut part = ZipArchive::new(File::open(&part_path)?)?;
+            zip.raw_copy_file(part.by_index_raw(0)?)?;
+            if cache.is_none() {
+                std::fs::remove_file(&part_path)?;
+            }
+        }
     }
     zip.finish()?;
+    remove_dir_all(&parts_dir)?;
+    if cache.is_some() {
+        log::info!(
+            "reused {} of {} compressed files from the bake cache.",
+            reused.into_inner(),
+            files.len()
+        );
+    }
     Ok(())
 }
 
+/// the contents of an entry written by `zip_entries`.
+pub enum EntrySource {
+    File(PathBuf),
+    Text(String),
+}
+
+/// writes an archive one entry at a time, each stored under the name it is given.
+/// this is for archives laid out differently from the package, such as a nupkg.
+pub fn zip_entries(
+    entries: &mut [(String, EntrySource)],
+    output: &PathBuf,
+    options: ZipOptions,
+) -> Result<(), Error> {
+    let mut file_options = FileOptions::default()
+        .compression_method(options.codec.into())
+        .compression_level(options.level);
+    if options.reproducible {
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        file_options = file_options
+            .last_modified_time(DateTime::default())
+            .unix_permissions(0o644);
+    }
+    let mut zip = ZipWriter::new(File::create(output)?);
+    for (name, source) in entries.iter() {
+        match source {
+            EntrySource::File(path) => {
+                let mut file = File::open(path)?;
+                let large_file = file.metadata()?.len() >= ZIP64_THRESHOLD;
+                zip.start_file(name.as_str(), file_options.large_file(large_file))?;
+                copy(&mut file, &mut zip)?;
+            }
+            EntrySource::Text(text) => {
+                zip.start_file(name.as_str(), file_options)?;
+                zip.write_all(text.as_bytes())?;
+            }
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// streams a single file into its own archive.
+fn compress_part(
+    input: &PathBuf,
+    entry: &str,
+    part_path: &PathBuf,
+    options: FileOptions,
+) -> Result<(), Error> {
+    let mut file_path = input.clone();
+    file_path.push(&entry);
+    let mut file = File::open(&file_path)?;
+    let large_file = file.metadata()?.len() >= ZIP64_THRESHOLD;
+    let mut part = ZipWriter::new(File::create(&part_path)?);
+    part.start_file(entry.replace('\\', "/"), options.large_file(large_file))?;
+    copy(&mut file, &mut part)?;
+    part.finish()?;
+    Ok(())
+}
+
+/// an entry of an archive along with the hash of its contents once decompressed.
+pub struct ArchiveEntry {
+    /// the name the entry is stored under.
+    pub name: String,
+    /// where `unzip` extracts the entry relative to its output directory, None if it refuses to.
+    pub path: Option<PathBuf>,
+    /// the SHA256 of the decompressed contents, formatted the same as `sha_256`.
+    pub hash: String,
+    /// true for directory entries, which `unzip` creates as empty folders.
+    pub is_dir: bool,
+}
+
+/// reads and hashes every entry of an archive.
+/// reading an entry to the end also checks its CRC, so a corrupt entry is an error.
+pub fn read_entries(input: &PathBuf) -> Result<Vec<ArchiveEntry>, Error> {
+    let mut archive = ZipArchive::new(File::open(&input)?)?;
+    let mut entries = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let mut sha256 = Sha256::new();
+        copy(&mut file, &mut sha256)?;
+        entries.push(ArchiveEntry {
+            name: file.name().to_string(),
+            path: file.enclosed_name().map(|p| p.to_path_buf()),
+            hash: format!("{:X}", sha256.result()),
+            is_dir: file.is_dir(),
+        });
+    }
+    Ok(entries)
+}
+
+/// checks that the entries of an archive are exactly the given files, each a path relative to
+/// the directory it was zipped from along with its SHA256. every entry has to extract to the same
+/// relative path with `unzip`, so the archive unpacks to the same tree it was made from.
+/// returns a description of the first file that does not match.
+pub fn compare_entries(entries: &[ArchiveEntry], files: &[(&str, &str)]) -> Result<(), String> {
+    let mut expected: HashMap<PathBuf, (&str, &str)> = files
+        .iter()
+        .map(|(path, hash)| (PathBuf::from(path), (*path, *hash)))
+        .collect();
+    let mut seen: HashMap<PathBuf, &str> = HashMap::with_capacity(entries.len());
+    for entry in entries {
+        let path = match &entry.path {
+            Some(p) => p,
+            None => {
+                return Err(format!(
+                    "{} has a path unzip would refuse to extract.",
+                    entry.name
+                ))
+            }
+        };
+        if entry.is_dir {
+            return Err(format!(
+                "{} is a directory entry which is not part of the release.",
+                entry.name
+            ));
+        }
+        if let Some(first) = seen.insert(path.clone(), &entry.name) {
+            return Err(format!(
+                "{} is stored twice, as {} and {}.",
+                path.display(),
+                first,
+                entry.name
+            ));
+        }
+        let (file, hash) = match expected.remove(path) {
+            Some(f) => f,
+            None => {
+                return Err(format!(
+                    "{} is in the archive but is not a release file.",
+                    entry.name
+                ))
+            }
+        };
+        if !entry.hash.eq_ignore_ascii_case(hash) {
+            return Err(format!("{} does not match its source file.", file));
+        }
+    }
+    let mut missing: Vec<&str> = expected.values().map(|(path, _)| *path).collect();
+    missing.sort();
+    match missing.first() {
+        Some(file) => Err(format!("{} is missing from the archive.", file)),
+        None => Ok(()),
+    }
+}
+
 /// unzips an archive to a target directory,
 /// returning false if any files false.
 pub fn unzip(input: &PathBuf, output: &PathBuf) -> Result<(), Error> {
@@ -45,7 +370,15 @@ pub fn unzip(input: &PathBuf, output: &PathBuf) -> Result<(), Error> {
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)?;
         let mut outpath = output.clone();
-        outpath.push(file.sanitized_name());
+        match file.enclosed_name() {
+            Some(name) => outpath.push(name),
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} contains an unsafe path: {}", input.display(), file.name()),
+                ))
+            }
+        }
         if (&*file.name()).ends_with('/') {
             log::debug!(
                 "File {} extracted to \"{}\"",
@@ -71,3 +404,116 @@ pub fn unzip(input: &PathBuf, output: &PathBuf) -> Result<(), Error> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{compare_entries, read_entries, zip_files_with_progress, Codec, ZipOptions};
+    use crate::io::cache::BakeCache;
+    use crate::io::hash::sha_256;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, read, remove_dir_all, write};
+    use std::path::PathBuf;
+
+    /// a directory only this test process uses, so parallel runs never remove each other's files.
+    fn test_dir(name: &str) -> PathBuf {
+        let mut dir = temp_dir();
+        dir.push(format!("Quiche_{}_{}", name, std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn reproducible_archive() {
+        let dir = test_dir("Reproducible_Test");
+        let input = dir.join("input");
+        let mut nested = input.clone();
+        nested.push("bin");
+        create_dir_all(&nested).unwrap();
+        nested.push("Rainway.dll");
+        write(&nested, b"dll").unwrap();
+        let mut exe = input.clone();
+        exe.push("Rainway.exe");
+        write(&exe, b"exe").unwrap();
+
+        let options = ZipOptions {
+            reproducible: true,
+            threads: 2,
+            ..Default::default()
+        };
+        let first = dir.join("first.zip");
+        let second = dir.join("second.zip");
+        let files = vec!["Rainway.exe".to_string(), "bin/Rainway.dll".to_string()];
+        let reversed = files.iter().rev().cloned().collect();
+        zip_files_with_progress(&input, &files, &first, options, None, |_| {}).unwrap();
+        zip_files_with_progress(&input, &reversed, &second, options, None, |_| {}).unwrap();
+        assert_eq!(read(&first).unwrap(), read(&second).unwrap());
+
+        // the archive is the same whether its entries are compressed or taken from the cache.
+        let cache_dir = dir.join("cache");
+        let cache = BakeCache::open(&cache_dir).unwrap();
+        for _ in 0..2 {
+            zip_files_with_progress(&input, &files, &second, options, Some(&cache), |_| {})
+                .unwrap();
+            assert_eq!(read(&first).unwrap(), read(&second).unwrap());
+        }
+        drop(cache);
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compression_levels() {
+        assert!(Codec::Store.check_level(None).is_ok());
+        assert!(Codec::Store.check_level(Some(1)).is_err());
+        assert!(Codec::Deflate.check_level(Some(9)).is_ok());
+        assert!(Codec::Bzip2.check_level(Some(10)).is_err());
+        assert!(Codec::Zstd.check_level(Some(-7)).is_ok());
+        assert!(Codec::Zstd.check_level(Some(23)).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = test_dir("Round_Trip_Test");
+        let input = dir.join("input");
+        let mut nested = input.clone();
+        nested.push("bin");
+        create_dir_all(&nested).unwrap();
+        nested.push("Rainway.dll");
+        write(&nested, b"dll").unwrap();
+        let mut exe = input.clone();
+        exe.push("Rainway.exe");
+        write(&exe, b"exe").unwrap();
+        let dll_hash = sha_256(&nested).unwrap();
+        let exe_hash = sha_256(&exe).unwrap();
+
+        let output = dir.join("package.zip");
+        let files = vec!["Rainway.exe".to_string(), "bin/Rainway.dll".to_string()];
+        zip_files_with_progress(&input, &files, &output, ZipOptions::default(), None, |_| {}).unwrap();
+        let entries = read_entries(&output).unwrap();
+        remove_dir_all(&dir).unwrap();
+
+        let expected = [
+            ("Rainway.exe", exe_hash.as_str()),
+            ("bin/Rainway.dll", dll_hash.as_str()),
+        ];
+        assert_eq!(compare_entries(&entries, &expected), Ok(()));
+        let changed = [
+            ("Rainway.exe", dll_hash.as_str()),
+            ("bin/Rainway.dll", dll_hash.as_str()),
+        ];
+        assert_eq!(
+            compare_entries(&entries, &changed),
+            Err("Rainway.exe does not match its source file.".to_string())
+        );
+        assert_eq!(
+            compare_entries(&entries, &expected[..1]),
+            Err("bin/Rainway.dll is in the archive but is not a release file.".to_string())
+        );
+        let mut more = expected.to_vec();
+        more.push(("Rainway.pdb", exe_hash.as_str()));
+        assert_eq!(
+            compare_entries(&entries, &more),
+            Err("Rainway.pdb is missing from the archive.".to_string())
+        );
+    }
+}
@@ -6,35 +6,183 @@ pub mod os;
 pub mod bakery {
 
     use crate::etc::constants::BootstrapError;
-    use crate::io::disk::{copy_file, delete_dir_contents, get_dir_files, to_slash};
-    use crate::io::hash::sha_256;
-    use crate::io::zip::zip_with_progress;
+    use crate::etc::formats::{
+        check_squirrel_release, nupkg_entry_name, nupkg_metadata, nupkg_name, Artifact, OutputFormat,
+        ReleaseInfo,
+    };
+    use crate::etc::version::{
+        compare_versions, matches_binary_version, parse_version, release_version_from_binary,
+    };
+    use crate::io::authenticode::{is_signable, read_signature, SignaturePolicy};
+    use crate::io::chunk::{store_file, ChunkIndex, ChunkStats, ChunkedFile};
+    use crate::io::delta::diff_files;
+    use crate::io::disk::{copy_file, get_dir_files, to_slash};
+    use crate::io::filter::FileFilter;
+    use crate::io::hash::{sha_1_bytes, sha_256, sha_256_bytes, sha_256_with_size};
+    use crate::io::cache::BakeCache;
+    use crate::io::lock::OutputLock;
+    use crate::io::msi::{is_msi, parse_msi_info};
+    use crate::io::pe::{is_pe, parse_pe_version, read_pe_version};
+    use crate::io::sign::{
+        get_release_public_keys, load_signing_key, sign_base64, sign_file, signature_path,
+        verify_signature,
+    };
+    use crate::io::zip::{
+        compare_entries, read_entries, unzip, zip_entries, zip_files_with_progress, zip_with_progress,
+        Codec, EntrySource, ZipOptions,
+    };
+    use crate::os::actions::{validate_actions, Action};
+    use crate::os::requirements::Requirements;
     use crate::updater::{
-        get_base_release_url, get_releases, Branch, Installer, Manifest, Package, ReleaseBranch,
-        Releases,
+        get_base_release_url, get_releases, Branch, Chunks, Component, Delta, Fallback, Installer,
+        Manifest, Package, PackageFile, ReleaseBranch, Releases,
     };
-    use serde::Deserialize;
+    use chrono::{SecondsFormat, Utc};
+    use ed25519_dalek::Keypair;
+    use serde::de::DeserializeOwned;
+    use serde::{Deserialize, Serialize};
+    use std::cmp::Ordering;
+    use std::collections::HashMap;
     use std::io::{Error, ErrorKind};
     use std::{
-        fs::{create_dir_all, read_to_string, write},
+        fs::{copy, create_dir_all, read, read_to_string, remove_dir_all, remove_file, rename, write},
         path::{Path, PathBuf},
     };
 
+    /// the folder of the output directory that the chunks of every release are stored in.
+    pub const CHUNK_STORE: &str = "package.chunks";
+
     /// A recipe is used to craft a release from a given build.
     #[derive(Deserialize)]
     pub struct Recipe {
-        /// the version of the release
+        /// the version of the release, read from main_executable or the installer when it is left empty.
+        #[serde(default)]
         pub version: String,
         /// the full path to the MSI or EXE installer for the parent application.
         pub installer_path: PathBuf,
         /// the directory path to the files that makeup the release version.
         /// usually this will be digtally signed artifacts.
         pub package_source: PathBuf,
+        /// the path of the app executable relative to package_source, such as `bin/App.exe`.
+        /// the version in its VERSIONINFO resource must agree with the release version.
+        pub main_executable: Option<PathBuf>,
+        /// what a bake does when the installer or an exe or dll in the release is unsigned,
+        /// signed by a subject missing from signers or changed since it was signed: warn or fail.
+        #[serde(default)]
+        pub signature_policy: SignaturePolicy,
+        /// the common names of the certificates release files are signed with, such as `Rainway, Inc.`
+        /// any signer is accepted when this is empty.
+        #[serde(default)]
+        pub signers: Vec<String>,
+        /// other formats the release is also written in for other update tools: sparkle, squirrel or json.
+        #[serde(default)]
+        pub formats: Vec<OutputFormat>,
+        /// the id the app is known by in formats that name its package, such as `Rainway` for
+        /// `Rainway-2.1.0-full.nupkg`. the squirrel format needs it.
+        #[serde(default)]
+        pub package_id: String,
         /// the destination branch the release will be under
         pub branch: ReleaseBranch,
         /// the directory baked files will be written too. You should keep this the same between
         /// branches and versions. do not include the version number or branch name.
         pub output_dir: PathBuf,
+        /// a short headline for the release shown to users while it downloads.
+        #[serde(default)]
+        pub title: String,
+        /// markdown release notes describing what changed in this version.
+        pub notes: Option<String>,
+        /// a markdown file to read the release notes from instead of writing them inline.
+        pub notes_path: Option<PathBuf>,
+        /// the offline Ed25519 key used to sign manifest.toml and Releases.toml.
+        /// clients refuse release metadata which is not signed by a key built into them.
+        pub signing_key: Option<PathBuf>,
+        /// glob rules for the files under package_source that make up the release,
+        /// such as `*.exe` or `bin/**`. every file is included when this is empty.
+        #[serde(default)]
+        pub include: Vec<String>,
+        /// glob rules for files under package_source that are left out of the release,
+        /// such as `*.pdb` or `logs/**`. these take priority over include rules.
+        #[serde(default)]
+        pub exclude: Vec<String>,
+        /// produces byte identical packages when baking the same files again,
+        /// so a rebuild can be checked against a published hash.
+        #[serde(default)]
+        pub reproducible: bool,
+        /// the codec package files are compressed with: store, deflate, bzip2 or zstd.
+        #[serde(default)]
+        pub compression: Codec,
+        /// the compression level of the codec, its default is used when this is not set.
+        /// deflate and bzip2 take 0 to 9, zstd -7 to 22 and store no level at all.
+        pub compression_level: Option<i32>,
+        /// how many files are compressed at once, zero uses every core.
+        #[serde(default)]
+        pub threads: usize,
+        /// a local directory where the hashes and compressed entries of release files are kept between bakes,
+        /// so a repeat bake only hashes and compresses the files that changed. it cannot be inside of
+        /// package_source or output_dir.
+        pub cache_dir: Option<PathBuf>,
+        /// previous versions on the same branch that delta patches will be made from.
+        /// each of them must have already been baked into the same output directory.
+        #[serde(default)]
+        pub delta_versions: Vec<String>,
+        /// also splits the package files into content-defined chunks, stored by hash in a chunk store
+        /// shared by every release in the output directory. clients then only download the chunks they are missing.
+        #[serde(default)]
+        pub chunked: bool,
+        /// the percentage of machines, from 0 to 100, that are offered the release at first.
+        /// the rest stay on the release the branch pointed at before, use the ramp command to raise it.
+        pub rollout: Option<u8>,
+        /// allows staging a version that is not newer than the one the branch is on.
+        /// the manifest is marked as a rollback so clients will downgrade to it.
+        #[serde(default)]
+        pub rollback: bool,
+        /// installs the release before the app starts, it can never be deferred.
+        #[serde(default)]
+        pub critical: bool,
+        /// installs older than this version must update before the app starts.
+        #[serde(default)]
+        pub minimum_supported_version: String,
+        /// how many times users can defer an optional update, zero does not limit it by count.
+        #[serde(default)]
+        pub max_deferrals: u32,
+        /// how many days users can defer an optional update for, zero does not limit it by days.
+        /// the update cannot be deferred at all when both limits are zero.
+        #[serde(default)]
+        pub max_deferral_days: u32,
+        /// the system requirements written into the manifest, such as the minimum OS version.
+        #[serde(default)]
+        pub requirements: Requirements,
+        /// the ordered steps clients run after applying the release, such as registering a service.
+        #[serde(default)]
+        pub post_install: Vec<Action>,
+        /// optional parts of the release, such as language packs, each baked into a package of its own.
+        /// the files a component matches are left out of the main package.
+        #[serde(default)]
+        pub components: Vec<ComponentRecipe>,
+        /// where the release is written while it is baked, it is renamed to output_dir once it is staged.
+        #[serde(skip)]
+        staging_dir: PathBuf,
+        /// the lock on the output directory, held from prepare until the recipe is dropped.
+        #[serde(skip)]
+        lock: Option<OutputLock>,
+        /// the bake cache, opened in prepare when cache_dir is set.
+        #[serde(skip)]
+        cache: Option<BakeCache>,
+    }
+
+    /// a named set of files that is packaged separately so users can choose to leave it out.
+    #[derive(Deserialize, Clone)]
+    pub struct ComponentRecipe {
+        /// the name the component is selected by, made of letters, digits, dashes and underscores.
+        pub name: String,
+        /// a readable name for the component.
+        #[serde(default)]
+        pub title: String,
+        /// glob rules for the files under package_source that make up the component.
+        pub include: Vec<String>,
+        /// installs the component for every user instead of only those who selected it.
+        #[serde(default)]
+        pub required: bool,
     }
 
     pub struct Dinner {
@@ -50,12 +198,6 @@ pub mod bakery {
         /// prepares a given recipe by checking that the provided flags are valid.
         /// it will also fix the slashes of paths, adding trailing slashes if needed.
         pub fn prepare(&mut self) -> Result<(), Error> {
-            if self.version.is_empty() {
-                return Err(Error::new(
-                    ErrorKind::NotFound,
-                    "The recipe version flag cannot be empty.",
-                ));
-            }
             self.installer_path = to_slash(&self.installer_path);
             if !self.installer_path.is_file() || !self.installer_path.exists() {
                 return Err(Error::new(
@@ -70,19 +212,141 @@ pub mod bakery {
                     "The recipe package source does not exist.",
                 ));
             }
+            let binary_versions = self.read_binary_versions()?;
+            if self.version.is_empty() {
+                if let Some((path, binary_version)) = binary_versions.first() {
+                    self.version = match release_version_from_binary(binary_version) {
+                        Some(v) => v,
+                        None => {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "The version {} of {} cannot be used as a release version, set the recipe version instead.",
+                                    binary_version,
+                                    path.display()
+                                ),
+                            ))
+                        }
+                    };
+                    log::info!("read the release version {} from {}", self.version, path.display());
+                }
+            }
+            if self.version.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    "The recipe version flag cannot be empty when neither the main executable nor the installer has a version.",
+                ));
+            }
+            self.get_filter()?;
+            if parse_version(&self.version).is_none() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "The recipe version {} is not a semver version such as 2.1.0 or 2.1.0-beta.1.",
+                        self.version
+                    ),
+                ));
+            }
+            for (path, binary_version) in &binary_versions {
+                if !matches_binary_version(&self.version, binary_version) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "{} has the version {} but the recipe version is {}.",
+                            path.display(),
+                            binary_version,
+                            self.version
+                        ),
+                    ));
+                }
+                log::info!("{} matches the release version", path.display());
+            }
+            if !self.minimum_supported_version.is_empty()
+                && parse_version(&self.minimum_supported_version).is_none()
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "The minimum supported version {} is not a semver version.",
+                        self.minimum_supported_version
+                    ),
+                ));
+            }
+            if let Err(e) = self.compression.check_level(self.compression_level) {
+                return Err(Error::new(ErrorKind::InvalidInput, e));
+            }
+            if self.formats.contains(&OutputFormat::Squirrel) {
+                if let Err(e) = check_squirrel_release(&self.package_id, &self.version) {
+                    return Err(Error::new(ErrorKind::InvalidInput, e));
+                }
+            }
+            if self.rollout.map_or(false, |r| r > 100) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "The recipe rollout must be a percentage between 0 and 100.",
+                ));
+            }
+            if let Err(e) = self.requirements.validate() {
+                return Err(Error::new(ErrorKind::InvalidInput, e));
+            }
+            if let Err(e) = validate_actions(&self.post_install) {
+                return Err(Error::new(ErrorKind::InvalidInput, e));
+            }
+            self.validate_components()?;
+            if let Some(notes_path) = &self.notes_path {
+                if self.notes.is_some() {
+                    log::warn!("the recipe has inline notes and a notes_path, the file will be used.");
+                }
+                let notes_path = to_slash(notes_path);
+                self.notes = match read_to_string(&notes_path) {
+                    Ok(n) => Some(n),
+                    Err(e) => {
+                        return Err(Error::new(
+                            e.kind(),
+                            format!(
+                                "Unable to read the release notes at {}: {}",
+                                notes_path.display(),
+                                e
+                            ),
+                        ))
+                    }
+                };
+            }
+            self.output_dir = to_slash(&self.output_dir);
+            create_dir_all(&self.output_dir)?;
+            self.lock = Some(OutputLock::acquire(&self.output_dir)?);
+            if let Some(cache_dir) = &self.cache_dir {
+                let cache_dir = to_slash(cache_dir);
+                create_dir_all(&cache_dir)?;
+                let cache_root = cache_dir.canonicalize()?;
+                for dir in &[&self.package_source, &self.output_dir] {
+                    if cache_root.starts_with(dir.canonicalize()?) {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            format!(
+                                "The cache directory {} cannot be inside of {}.",
+                                cache_dir.display(),
+                                dir.display()
+                            ),
+                        ));
+                    }
+                }
+                self.cache = Some(BakeCache::open(&cache_dir)?);
+                log::info!("using the bake cache inside {}", cache_dir.display());
+            }
             self.output_dir.push(&self.branch.to_string());
             self.output_dir.push(&self.version);
-            self.output_dir = to_slash(&self.output_dir);
-            if !self.output_dir.exists() {
-                create_dir_all(&self.output_dir)?;
-                log::info!("created directory {}", &self.output_dir.display());
-            } else {
-                delete_dir_contents(&self.output_dir, &vec![])?;
+            // the release is baked next to where it will end up, so committing it is a rename.
+            self.staging_dir = get_sibling_dir(&self.output_dir, "staging");
+            if self.staging_dir.exists() {
+                remove_dir_all(&self.staging_dir)?;
                 log::info!(
-                    "deleted previous release found inside {}",
-                    &self.output_dir.display()
+                    "deleted an unfinished release found inside {}",
+                    &self.staging_dir.display()
                 );
             }
+            create_dir_all(&self.staging_dir)?;
+            log::info!("staging the release inside {}", &self.staging_dir.display());
             Ok(())
         }
 
@@ -96,12 +360,7 @@ pub mod bakery {
                 self.branch
             );
 
-            let branch_url = format!(
-                "{}/{}/{}",
-                get_base_release_url(),
-                self.branch.to_string(),
-                self.version
-            );
+            let branch_url = self.get_release_url();
 
             log::debug!("branch_url == {}", branch_url);
 
@@ -124,16 +383,43 @@ pub mod bakery {
                 installer_hash
             );
 
+            let files = self.get_package_files()?;
+
+            if files.len() == 0 {
+                return Err(BootstrapError::RecipeBakeFailure(format!(
+                    "The package source {} contains zero files or we were unable to access the directory.",  
+                    &self.package_source.display()
+                )));
+            }
+
+            log::info!(
+                "found {} files which will be included in this release.",
+                files.len()
+            );
+
+            log::info!("auditing the signatures of the release...");
+
+            self.audit_signatures(&files)?;
+
+            let (files, component_files) = self.split_components(files)?;
+
             log::info!("packaging the release files...");
 
             let func_test = |file: String| {
                 log::info!("[DONE] {}", file);
             };
 
-            let mut package_path = self.output_dir.clone();
+            let mut package_path = self.staging_dir.clone();
             package_path.push("package.zip");
 
-            if let Err(e) = zip_with_progress(&self.package_source, &package_path, func_test) {
+            if let Err(e) = zip_files_with_progress(
+                &self.package_source,
+                &files,
+                &package_path,
+                self.get_zip_options(),
+                self.cache.as_ref(),
+                func_test,
+            ) {
                 return Err(BootstrapError::RecipeBakeFailure(format!(
                     "Issue packaging {} due to unknown exception: {}",
                     &self.package_source.display(),
@@ -158,30 +444,66 @@ pub mod bakery {
                 package_hash
             );
 
-            let files = get_dir_files(&self.package_source)?;
+            log::info!("hashing the release files...");
 
-            if files.len() == 0 {
-                return Err(BootstrapError::RecipeBakeFailure(format!(
-                    "The package source {} contains zero files or we were unable to access the directory.",  
-                    &self.package_source.display()
-                )));
+            let package_files = self.hash_files(files)?;
+
+            log::info!("verifying the release package...");
+
+            self.verify_package(&package_path, &package_files)?;
+
+            let mut deltas = Vec::with_capacity(self.delta_versions.len());
+            for from in &self.delta_versions {
+                log::info!("creating delta patches from {}...", from);
+                deltas.push(self.bake_delta(from, &package_files, &branch_url)?);
+            }
+
+            let chunks = match self.chunked {
+                true => {
+                    log::info!("splitting the release files into chunks...");
+                    Some(self.bake_chunks(&package_files, &branch_url)?)
+                }
+                false => None,
+            };
+
+            let mut components = Vec::with_capacity(self.components.len());
+            for (component, files) in self.components.iter().zip(component_files) {
+                log::info!("packaging the {} component...", component.name);
+                components.push(self.bake_component(component, files, &branch_url)?);
+            }
+
+            // a cache that cannot be saved only costs the next bake its time.
+            if let Some(cache) = &self.cache {
+                if let Err(e) = cache.save() {
+                    log::warn!("unable to save the bake cache. {}", e);
+                }
             }
 
-            log::info!(
-                "found {} files which will be included in this release.",
-                files.len()
-            );
             Ok(Dinner {
                 branch: Branch {
                     manifest_url: format!("{}/manifest.toml", branch_url),
                     version: self.version.clone(),
+                    ..Branch::default()
                 },
                 manifest: Manifest {
                     version: self.version.clone(),
+                    title: self.title.clone(),
+                    notes: self.notes.clone().unwrap_or_default(),
+                    published: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                    rollback: self.rollback,
+                    critical: self.critical,
+                    minimum_supported_version: self.minimum_supported_version.clone(),
+                    max_deferrals: self.max_deferrals,
+                    max_deferral_days: self.max_deferral_days,
+                    requirements: self.requirements.clone(),
+                    post_install: self.post_install.clone(),
+                    components,
                     package: Package {
-                        files: files,
+                        files: package_files,
                         hash: package_hash,
                         url: package_url,
+                        deltas,
+                        chunks,
                     },
                     installer: Installer {
                         url: installer_url,
@@ -191,16 +513,557 @@ pub mod bakery {
             })
         }
 
+        fn get_zip_options(&self) -> ZipOptions {
+            ZipOptions {
+                reproducible: self.reproducible,
+                codec: self.compression,
+                level: self.compression_level,
+                threads: self.threads,
+            }
+        }
+
+        /// builds the include and exclude filter of the recipe.
+        fn get_filter(&self) -> Result<FileFilter, Error> {
+            match FileFilter::new(&self.include, &self.exclude) {
+                Ok(f) => Ok(f),
+                Err(e) => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("The recipe contains an invalid glob rule: {}", e),
+                )),
+            }
+        }
+
+        /// lists the files under the package source which pass the include and exclude rules.
+        /// every file that is left out is logged along with the rule responsible.
+        fn get_package_files(&self) -> Result<Vec<String>, BootstrapError> {
+            let filter = self.get_filter()?;
+            let (files, filtered) = filter.apply(get_dir_files(&self.package_source)?);
+            for (file, reason) in &filtered {
+                log::info!("[FILTERED] {} ({})", file, reason);
+            }
+            if !filtered.is_empty() {
+                log::info!("{} files were filtered out of the release.", filtered.len());
+            }
+            Ok(files)
+        }
+
+        /// hashes every file of a package, recording its size along with it.
+        fn hash_files(&self, files: Vec<String>) -> Result<Vec<PackageFile>, BootstrapError> {
+            let mut package_files = Vec::with_capacity(files.len());
+            for file in files {
+                let mut file_path = self.package_source.clone();
+                file_path.push(&file);
+                let hashed = match &self.cache {
+                    Some(cache) => cache.hash_file(&file_path).ok(),
+                    None => sha_256_with_size(&file_path),
+                };
+                let (hash, size) = match hashed {
+                    Some(h) => h,
+                    None => {
+                        return Err(BootstrapError::RecipeBakeFailure(format!(
+                            "Unable to hash the release file {}.",
+                            file_path.display()
+                        )))
+                    }
+                };
+                log::debug!("{} == {} ({} bytes)", file, hash, size);
+                package_files.push(PackageFile {
+                    path: file,
+                    hash,
+                    size,
+                });
+            }
+            Ok(package_files)
+        }
+
+        /// checks the Authenticode signatures of the installer and every exe and dll in the release.
+        /// the problems found are logged, or fail the bake when the signature policy is fail.
+        fn audit_signatures(&self, files: &[String]) -> Result<(), BootstrapError> {
+            let mut paths = vec![self.installer_path.clone()];
+            for file in files.iter().filter(|f| is_signable(Path::new(f))) {
+                let mut file_path = self.package_source.clone();
+                file_path.push(file);
+                paths.push(file_path);
+            }
+            let mut problems = Vec::new();
+            for path in &paths {
+                let problem = match read_signature(path) {
+                    Ok(None) => "is not signed".to_string(),
+                    Ok(Some(s)) if s.digest_matches == Some(false) => {
+                        format!("was changed after it was signed by {}", s.subject)
+                    }
+                    Ok(Some(s))
+                        if !self.signers.is_empty()
+                            && !self.signers.iter().any(|n| n.eq_ignore_ascii_case(&s.subject)) =>
+                    {
+                        format!("is signed by {} which is not one of the recipe signers", s.subject)
+                    }
+                    Ok(Some(s)) => {
+                        log::debug!("{} is signed by {}", path.display(), s.subject);
+                        continue;
+                    }
+                    Err(e) => format!("has a signature that could not be read. {}", e),
+                };
+                problems.push(format!("{} {}", path.display(), problem));
+            }
+            if problems.is_empty() {
+                log::info!("all {} files passed the signature audit.", paths.len());
+                return Ok(());
+            }
+            match self.signature_policy {
+                SignaturePolicy::Warn => {
+                    for problem in &problems {
+                        log::warn!("{}", problem);
+                    }
+                    Ok(())
+                }
+                SignaturePolicy::Fail => Err(BootstrapError::RecipeBakeFailure(format!(
+                    "{} files failed the signature audit: {}",
+                    problems.len(),
+                    problems.join("; ")
+                ))),
+            }
+        }
+
+        /// opens a package that was just written and checks it holds exactly the release files,
+        /// each with the same contents as its source and extracting to the same path with `unzip`.
+        /// the bake cache is never trusted here, with one the source files are read again so a file
+        /// that changed without its size or modified time changing cannot pass under its cached hash.
+        fn verify_package(
+            &self,
+            package_path: &PathBuf,
+            files: &Vec<PackageFile>,
+        ) -> Result<(), BootstrapError> {
+            if self.cache.is_some() {
+                for file in files {
+                    let file_path = self.package_source.join(&file.path);
+                    let fresh = sha_256_with_size(&file_path);
+                    if !fresh.map_or(false, |(hash, _)| hash.eq_ignore_ascii_case(&file.hash)) {
+                        return Err(BootstrapError::RecipeBakeFailure(format!(
+                            "{} does not match its hash in the bake cache, it changed without its size or modified time changing. delete the cache and bake again.",
+                            file_path.display()
+                        )));
+                    }
+                }
+            }
+            let entries = match read_entries(package_path) {
+                Ok(e) => e,
+                Err(e) => {
+                    return Err(BootstrapError::RecipeBakeFailure(format!(
+                        "Unable to read back {}: {}",
+                        package_path.display(),
+                        e
+                    )))
+                }
+            };
+            let expected: Vec<(&str, &str)> = files
+                .iter()
+                .map(|f| (f.path.as_str(), f.hash.as_str()))
+                .collect();
+            match compare_entries(&entries, &expected) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(BootstrapError::RecipeBakeFailure(format!(
+                    "{} did not verify. {}",
+                    package_path.display(),
+                    e
+                ))),
+            }
+        }
+
+        /// reads the versions embedded in main_executable and the installer, in that order.
+        /// main_executable must have a VERSIONINFO resource, an installer without a version is only a warning.
+        fn read_binary_versions(&self) -> Result<Vec<(PathBuf, String)>, Error> {
+            let mut versions = Vec::new();
+            if let Some(main_executable) = &self.main_executable {
+                let path = to_slash(&self.package_source.join(main_executable));
+                match read_pe_version(&path) {
+                    Ok(Some(v)) => versions.push((path, v.version().to_string())),
+                    Ok(None) => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!("The main executable {} has no VERSIONINFO resource.", path.display()),
+                        ))
+                    }
+                    Err(e) => {
+                        return Err(Error::new(
+                            e.kind(),
+                            format!(
+                                "Unable to read the version of the main executable {}. {}",
+                                path.display(),
+                                e
+                            ),
+                        ))
+                    }
+                };
+            }
+            let installer = read(&self.installer_path)?;
+            let installer_version = if is_msi(&installer) {
+                match parse_msi_info(&installer) {
+                    Ok(info) => {
+                        log::info!("the installer is an msi with the ProductCode {}", info.product_code);
+                        Ok(Some(info.product_version))
+                    }
+                    Err(e) => Err(e),
+                }
+            } else if is_pe(&installer) {
+                parse_pe_version(&installer).map(|v| v.map(|v| v.version().to_string()))
+            } else {
+                Ok(None)
+            };
+            match installer_version {
+                Ok(Some(v)) => versions.push((self.installer_path.clone(), v)),
+                Ok(None) => log::warn!(
+                    "the installer {} has no version to check the release against.",
+                    self.installer_path.display()
+                ),
+                Err(e) => log::warn!(
+                    "unable to read the version of the installer {}. {}",
+                    self.installer_path.display(),
+                    e
+                ),
+            }
+            Ok(versions)
+        }
+
+        /// checks that every component has a unique, valid name and at least one include rule.
+        fn validate_components(&self) -> Result<(), Error> {
+            for (i, component) in self.components.iter().enumerate() {
+                let valid_name = !component.name.is_empty()
+                    && component
+                        .name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                if !valid_name {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "\"{}\" is not a valid component name. Names can only contain letters, digits, dashes and underscores.",
+                            component.name
+                        ),
+                    ));
+                }
+                if self.components[..i]
+                    .iter()
+                    .any(|c| c.name.eq_ignore_ascii_case(&component.name))
+                {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("The component {} is listed more than once.", component.name),
+                    ));
+                }
+                if component.include.is_empty() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("The component {} has no include rules.", component.name),
+                    ));
+                }
+                if let Err(e) = FileFilter::new(&component.include, &[]) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("The component {} contains an invalid glob rule: {}", component.name, e),
+                    ));
+                }
+            }
+            Ok(())
+        }
+
+        /// splits the release files into the files of the main package and the files of each component.
+        /// a file matched by more than one component is an error as it could only be installed by one of them.
+        fn split_components(
+            &self,
+            files: Vec<String>,
+        ) -> Result<(Vec<String>, Vec<Vec<String>>), BootstrapError> {
+            let mut filters = Vec::with_capacity(self.components.len());
+            for component in &self.components {
+                match FileFilter::new(&component.include, &[]) {
+                    Ok(f) => filters.push(f),
+                    Err(e) => return Err(BootstrapError::RecipeBakeFailure(e.to_string())),
+                }
+            }
+            let mut main_files = Vec::with_capacity(files.len());
+            let mut component_files = vec![Vec::new(); self.components.len()];
+            for file in files {
+                let matched: Vec<usize> = filters
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, filter)| filter.check(&file).is_ok())
+                    .map(|(i, _)| i)
+                    .collect();
+                match matched.as_slice() {
+                    [] => main_files.push(file),
+                    [i] => component_files[*i].push(file),
+                    [first, second, ..] => {
+                        return Err(BootstrapError::RecipeBakeFailure(format!(
+                            "{} is matched by both the {} and {} components.",
+                            file, self.components[*first].name, self.components[*second].name
+                        )))
+                    }
+                }
+            }
+            for (component, files) in self.components.iter().zip(&component_files) {
+                if files.is_empty() {
+                    return Err(BootstrapError::RecipeBakeFailure(format!(
+                        "The {} component does not match any files.",
+                        component.name
+                    )));
+                }
+            }
+            Ok((main_files, component_files))
+        }
+
+        /// packages and hashes the files of a component into a package of its own.
+        fn bake_component(
+            &self,
+            component: &ComponentRecipe,
+            files: Vec<String>,
+            branch_url: &str,
+        ) -> Result<Component, BootstrapError> {
+            let name = component.name.to_lowercase();
+            let package_name = format!("component-{}.zip", name);
+            let mut package_path = self.staging_dir.clone();
+            package_path.push(&package_name);
+            if let Err(e) = zip_files_with_progress(
+                &self.package_source,
+                &files,
+                &package_path,
+                self.get_zip_options(),
+                self.cache.as_ref(),
+                |file: String| log::info!("[DONE] {}", file),
+            ) {
+                return Err(BootstrapError::RecipeBakeFailure(format!(
+                    "Issue packaging the {} component due to unknown exception: {}",
+                    name, e
+                )));
+            }
+            let package_hash = sha_256(&package_path).unwrap_or_default();
+            if package_hash.is_empty() {
+                return Err(BootstrapError::RecipeBakeFailure(format!(
+                    "package hash for {} is empty.",
+                    package_path.display()
+                )));
+            }
+            let files = self.hash_files(files)?;
+            self.verify_package(&package_path, &files)?;
+            Ok(Component {
+                name,
+                title: component.title.clone(),
+                required: component.required,
+                package: Package {
+                    url: format!("{}/{}", branch_url, package_name),
+                    hash: package_hash,
+                    files,
+                    ..Package::default()
+                },
+            })
+        }
+
+        /// the chunk store sits next to the branch folders so every release baked into the
+        /// same output directory shares it. its name cannot clash with a branch as branch names have no dots.
+        fn get_chunk_store(&self) -> Result<PathBuf, BootstrapError> {
+            match self.output_dir.parent().and_then(|p| p.parent()) {
+                Some(root) => Ok(root.join(CHUNK_STORE)),
+                None => Err(BootstrapError::RecipeBakeFailure(
+                    "Cannot locate the output root for the chunk store.".to_string(),
+                )),
+            }
+        }
+
+        /// splits every package file into content-defined chunks, writing the ones the chunk store
+        /// does not have yet, and writes the chunk index of the release next to its manifest.
+        fn bake_chunks(
+            &self,
+            files: &Vec<PackageFile>,
+            branch_url: &str,
+        ) -> Result<Chunks, BootstrapError> {
+            let store = self.get_chunk_store()?;
+            let mut index = ChunkIndex::default();
+            let mut stats = ChunkStats::default();
+            for file in files {
+                let mut file_path = self.package_source.clone();
+                file_path.push(&file.path);
+                let (chunks, hash, file_stats) = store_file(&file_path, &store)?;
+                if !hash.eq_ignore_ascii_case(&file.hash) {
+                    return Err(BootstrapError::RecipeBakeFailure(format!(
+                        "{} changed while the release was being baked.",
+                        file_path.display()
+                    )));
+                }
+                stats.add(file_stats);
+                index.files.push(ChunkedFile {
+                    path: file.path.clone(),
+                    hash,
+                    size: file.size,
+                    chunks,
+                });
+            }
+            log::info!(
+                "split the release files into {} chunks, {} of them ({} of {} bytes) were new to the chunk store.",
+                stats.chunks,
+                stats.new_chunks,
+                stats.new_bytes,
+                stats.bytes
+            );
+
+            let mut index_path = self.staging_dir.clone();
+            index_path.push("index.toml");
+            let contents = match toml::to_string(&index) {
+                Ok(c) => c,
+                Err(e) => return Err(BootstrapError::RecipeBakeFailure(e.to_string())),
+            };
+            write(&index_path, contents)?;
+            let index_hash = sha_256(&index_path).unwrap_or_default();
+            if index_hash.is_empty() {
+                return Err(BootstrapError::RecipeBakeFailure(format!(
+                    "chunk index hash for {} is empty.",
+                    index_path.display()
+                )));
+            }
+            Ok(Chunks {
+                index_url: format!("{}/index.toml", branch_url),
+                index_hash,
+                store_url: format!("{}/{}", get_base_release_url(), CHUNK_STORE),
+            })
+        }
+
+        /// creates a delta package which turns a previously baked release into this one.
+        /// changed files are stored as bsdiff patches, new files are stored whole,
+        /// and unchanged files are left out as they will be copied from the installed version.
+        fn bake_delta(
+            &self,
+            from: &str,
+            files: &Vec<PackageFile>,
+            branch_url: &str,
+        ) -> Result<Delta, BootstrapError> {
+            let mut previous_package = match self.output_dir.parent() {
+                Some(p) => p.to_path_buf(),
+                None => {
+                    return Err(BootstrapError::RecipeBakeFailure(
+                        "Cannot locate parent for output directory.".to_string(),
+                    ))
+                }
+            };
+            previous_package.push(from);
+            previous_package.push("package.zip");
+            if !previous_package.exists() {
+                return Err(BootstrapError::RecipeBakeFailure(format!(
+                    "Unable to create a delta from {} as {} does not exist.",
+                    from,
+                    previous_package.display()
+                )));
+            }
+
+            // both are inside the staging dir so concurrent bakes never share them, and a bake that fails
+            // leaves them behind with the rest of the unfinished release for the next bake to delete.
+            let mut previous_files = self.staging_dir.clone();
+            previous_files.push(format!("delta_{}.previous", from));
+            let mut delta_files = self.staging_dir.clone();
+            delta_files.push(format!("delta_{}.files", from));
+            for dir in &[&previous_files, &delta_files] {
+                if dir.exists() {
+                    remove_dir_all(dir)?;
+                }
+                create_dir_all(dir)?;
+            }
+            unzip(&previous_package, &previous_files)?;
+
+            let mut patches = Vec::new();
+            for file in files {
+                let mut new_path = self.package_source.clone();
+                new_path.push(&file.path);
+                let mut old_path = previous_files.clone();
+                old_path.push(&file.path);
+                let mut delta_path = delta_files.clone();
+                delta_path.push(&file.path);
+                let unchanged = old_path.is_file()
+                    && sha_256(&old_path).map_or(false, |hash| hash.eq_ignore_ascii_case(&file.hash));
+                if unchanged {
+                    continue;
+                }
+                if let Some(parent) = delta_path.parent() {
+                    create_dir_all(parent)?;
+                }
+                if !old_path.is_file() {
+                    log::debug!("[NEW] {}", file.path);
+                    copy(&new_path, &delta_path)?;
+                    continue;
+                }
+                diff_files(&old_path, &new_path, &delta_path)?;
+                // a patch that is larger than the file itself saves nothing.
+                if delta_path.metadata()?.len() >= file.size {
+                    log::debug!("[CHANGED] {}", file.path);
+                    copy(&new_path, &delta_path)?;
+                } else {
+                    log::debug!("[PATCHED] {}", file.path);
+                    patches.push(file.path.clone());
+                }
+            }
+
+            let delta_name = format!("delta_{}.zip", from);
+            let mut delta_package = self.staging_dir.clone();
+            delta_package.push(&delta_name);
+            if let Err(e) = zip_with_progress(
+                &delta_files,
+                &delta_package,
+                self.get_zip_options(),
+                |file: String| {
+                    log::info!("[DELTA] {}", file);
+                },
+            ) {
+                return Err(BootstrapError::RecipeBakeFailure(format!(
+                    "Issue packaging the delta from {} due to unknown exception: {}",
+                    from, e
+                )));
+            }
+            remove_dir_all(&previous_files)?;
+            remove_dir_all(&delta_files)?;
+
+            let (delta_hash, delta_size) = match sha_256_with_size(&delta_package) {
+                Some(h) => h,
+                None => {
+                    return Err(BootstrapError::RecipeBakeFailure(format!(
+                        "delta hash for {} is empty.",
+                        delta_package.display()
+                    )))
+                }
+            };
+            log::info!(
+                "the delta from {} patches {} files and is {} bytes.",
+                from,
+                patches.len(),
+                delta_size
+            );
+            Ok(Delta {
+                from: from.to_string(),
+                url: format!("{}/{}", branch_url, delta_name),
+                hash: delta_hash,
+                size: delta_size,
+                patches,
+            })
+        }
+
         /// using the baked Manifest and Branch structures we can prepare a release.
         /// an attempt is made to fetch the already published Releases.toml file
-        /// so that it can be updated. If fetching it fails, then a brand new Releases file is made.
-        /// all the relevant files will be written to their output once this function completes.
+        /// so that it can be updated. If fetching it fails, the one in the output directory is used
+        /// and failing that a brand new Releases file is made.
+        /// the staged release is renamed into place before Releases.toml is pointed at it,
+        /// which is refused if Releases.toml changed since it was read.
         pub fn stage(&self, dinner: Dinner) -> Result<(), BootstrapError> {
             log::info!(
                 "attempting to stage the release for version {} on {}.",
                 self.version,
                 self.branch
             );
+            let output_root = match self.output_dir.parent().and_then(|p| p.parent()) {
+                Some(p) => p.to_path_buf(),
+                None => {
+                    return Err(BootstrapError::RecipeStageFailure(
+                        "Cannot locate parent for output directory.".to_string(),
+                    ))
+                }
+            };
+            // the etag is always taken from the Releases.toml this stage replaces, which is read
+            // under the output lock, even when the branches are taken from the release host.
+            let mut etag = get_releases_etag(&output_root)?;
             let mut releases = match get_releases() {
                 Ok(r) => {
                     log::info!("using default release host.");
@@ -208,32 +1071,53 @@ pub mod bakery {
                 }
                 Err(e) => {
                     log::warn!("Unable to fetch remote releases. {}", e);
-                    log::warn!("Creating a Release file from scratch.");
-                    Releases::default()
+                    match read_releases(&output_root) {
+                        Ok((r, local_etag)) => {
+                            log::warn!("Using the Releases file in {}.", output_root.display());
+                            etag = local_etag;
+                            r
+                        }
+                        Err(_e) => {
+                            log::warn!("Creating a Release file from scratch.");
+                            Releases::default()
+                        }
+                    }
                 }
             };
             log::info!("setting up the {} branch.", self.branch);
-            match self.branch {
-                ReleaseBranch::Stable => releases.stable = dinner.branch,
-                ReleaseBranch::Beta => releases.beta = dinner.branch,
-                ReleaseBranch::Nightly => releases.nightly = dinner.branch,
+            if let Err(e) = check_newer(
+                &self.branch,
+                releases.get(&self.branch).ok(),
+                &self.version,
+                self.rollback,
+            ) {
+                return Err(BootstrapError::RecipeStageFailure(e));
             }
-            let releases_encoded = match toml::to_string(&releases) {
-                Ok(c) => c,
-                Err(e) => return Err(BootstrapError::RecipeStageFailure(e.to_string())),
-            };
-            log::debug!("encoded releases \n\n{}", &releases_encoded);
-            let mut release_path = match self.output_dir.parent().unwrap().parent() {
-                Some(p) => p.to_path_buf(),
-                None => {
-                    return Err(BootstrapError::RecipeStageFailure(
-                        "Cannot locate parent for output directory.".to_string(),
-                    ))
+            let mut branch = dinner.branch;
+            if let Some(rollout) = self.rollout {
+                let previous = releases.get(&self.branch).ok().cloned().unwrap_or_default();
+                if previous.manifest_url.is_empty() || previous.version == self.version {
+                    log::warn!(
+                        "the {} branch has no previous release to fall back to, {} will be offered to everyone.",
+                        self.branch,
+                        self.version
+                    );
+                } else {
+                    log::info!(
+                        "offering {} to {}% of machines, the rest stay on {}.",
+                        self.version,
+                        rollout,
+                        previous.version
+                    );
+                    branch.rollout = Some(rollout);
+                    branch.fallback = Some(Fallback {
+                        version: previous.version,
+                        manifest_url: previous.manifest_url,
+                    });
                 }
-            };
-            release_path.push("Releases.toml");
-            write(&release_path, &releases_encoded)?;
-            log::info!("wrote Releases.toml to {}", &release_path.display());
+            }
+            releases.set(self.branch.clone(), branch.clone());
+            let signing_key = get_signing_key(self.signing_key.as_ref().map(|p| p.as_path()))?;
 
             let manifest_encoded = match toml::to_string(&dinner.manifest) {
                 Ok(c) => c,
@@ -241,12 +1125,16 @@ pub mod bakery {
             };
             log::debug!("encoded manifest \n\n{}", &manifest_encoded);
 
-            let mut manifest_path = self.output_dir.clone();
+            let mut manifest_path = self.staging_dir.clone();
             manifest_path.push("manifest.toml");
             write(&manifest_path, &manifest_encoded)?;
             log::info!("wrote release manifest to {}", &manifest_path.display());
+            if let Some(key) = &signing_key {
+                let signature_path = sign_file(key, &manifest_path)?;
+                log::info!("signed release manifest to {}", &signature_path.display());
+            }
 
-            let mut copied_installer_path = self.output_dir.clone();
+            let mut copied_installer_path = self.staging_dir.clone();
             copied_installer_path.push("installer.exe");
 
             if let Err(e) = copy_file(&self.installer_path, &copied_installer_path) {
@@ -257,26 +1145,687 @@ pub mod bakery {
                 &copied_installer_path.display()
             );
 
+            if !self.formats.is_empty() {
+                self.write_formats(&dinner.manifest, &branch, signing_key.as_ref())?;
+            }
+
+            // checked before the release is moved into place, so a stale Releases.toml never leaves
+            // behind a published release that no branch points at.
+            check_releases_etag(&output_root, &etag)?;
+            let previous_dir = match commit_release(&self.staging_dir, &self.output_dir) {
+                Ok(p) => p,
+                Err(e) => {
+                    return Err(BootstrapError::RecipeStageFailure(format!(
+                        "Unable to move the release into {}: {}",
+                        self.output_dir.display(),
+                        e
+                    )))
+                }
+            };
+            log::info!("moved the release into {}", &self.output_dir.display());
+            if let Err(e) = write_releases(&releases, &output_root, signing_key.as_ref(), &etag) {
+                log::warn!("moving the release back as Releases.toml could not be written.");
+                if let Err(e) =
+                    revert_release(&self.staging_dir, &self.output_dir, previous_dir.as_deref())
+                {
+                    log::error!("unable to move the release back. {}", e);
+                }
+                return Err(e);
+            }
+            if let Some(previous_dir) = previous_dir {
+                remove_dir_all(&previous_dir)?;
+            }
+
+            Ok(())
+        }
+
+        /// writes the staged release in each of the recipe formats next to manifest.toml,
+        /// signing them like the manifest when there is a signing key.
+        fn write_formats(
+            &self,
+            manifest: &Manifest,
+            branch: &Branch,
+            signing_key: Option<&Keypair>,
+        ) -> Result<(), BootstrapError> {
+            let nupkg = match self.formats.contains(&OutputFormat::Squirrel) {
+                true => Some(self.write_nupkg(manifest, signing_key)?),
+                false => None,
+            };
+            let release = ReleaseInfo {
+                branch_name: &self.branch,
+                branch,
+                manifest,
+                installer: self.get_artifact("installer.exe", &manifest.installer.url, signing_key)?,
+                package: self.get_artifact("package.zip", &manifest.package.url, signing_key)?,
+                nupkg,
+            };
+            for format in &self.formats {
+                let writer = format.writer();
+                let contents = match writer.render(&release) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        return Err(BootstrapError::RecipeStageFailure(format!(
+                            "Unable to write the release as {}: {}",
+                            writer.name(),
+                            e
+                        )))
+                    }
+                };
+                let mut path = self.staging_dir.clone();
+                path.push(writer.file_name());
+                write(&path, &contents)?;
+                log::info!("wrote the {} release to {}", writer.name(), &path.display());
+                if let Some(key) = signing_key {
+                    sign_file(key, &path)?;
+                }
+            }
             Ok(())
         }
+
+        /// writes the full Squirrel package of the release, which holds the package files under `lib/net45`.
+        /// it is deflated whatever the recipe compression is, as that is all NuGet reads.
+        fn write_nupkg(
+            &self,
+            manifest: &Manifest,
+            signing_key: Option<&Keypair>,
+        ) -> Result<Artifact, BootstrapError> {
+            let name = nupkg_name(&self.package_id, &manifest.version);
+            let mut entries = Vec::with_capacity(manifest.package.files.len() + 3);
+            for file in &manifest.package.files {
+                entries.push((
+                    nupkg_entry_name(&file.path),
+                    EntrySource::File(self.package_source.join(&file.path)),
+                ));
+            }
+            let names: Vec<String> = entries.iter().map(|(n, _)| n.clone()).collect();
+            for (entry, contents) in nupkg_metadata(&self.package_id, manifest, &names) {
+                entries.push((entry, EntrySource::Text(contents)));
+            }
+            let options = ZipOptions {
+                reproducible: self.reproducible,
+                codec: Codec::Deflate,
+                ..Default::default()
+            };
+            let path = self.staging_dir.join(&name);
+            if let Err(e) = zip_entries(&mut entries, &path, options) {
+                return Err(BootstrapError::RecipeStageFailure(format!(
+                    "Unable to write the Squirrel package {}: {}",
+                    path.display(),
+                    e
+                )));
+            }
+            log::info!("wrote the Squirrel package to {}", &path.display());
+            let url = format!("{}/{}", self.get_release_url(), name);
+            self.get_artifact(&name, &url, signing_key)
+        }
+
+        /// the URL the files of the release are published under.
+        fn get_release_url(&self) -> String {
+            format!(
+                "{}/{}/{}",
+                get_base_release_url(),
+                self.branch.to_string(),
+                self.version
+            )
+        }
+
+        /// hashes and measures a file of the staged release for the formats that describe it.
+        fn get_artifact(
+            &self,
+            name: &str,
+            url: &str,
+            signing_key: Option<&Keypair>,
+        ) -> Result<Artifact, BootstrapError> {
+            let mut path = self.staging_dir.clone();
+            path.push(name);
+            let data = read(&path)?;
+            Ok(Artifact {
+                url: url.to_string(),
+                hash: sha_256_bytes(&data),
+                size: data.len() as u64,
+                name: name.to_string(),
+                sha1: sha_1_bytes(&data),
+                ed_signature: signing_key.map(|k| sign_base64(k, &data)).unwrap_or_default(),
+            })
+        }
+    }
+
+    /// the result of re-hashing a single artifact of a staged release.
+    #[derive(Serialize)]
+    pub struct ArtifactCheck {
+        /// the file name of the artifact inside of the release directory.
+        pub name: String,
+        /// the hash recorded in the manifest.
+        pub expected_hash: String,
+        /// the hash of the artifact on disk, empty when it is missing.
+        pub actual_hash: String,
+        pub valid: bool,
+    }
+
+    /// the outcome of re-checking a staged release directory against its manifest.
+    #[derive(Serialize)]
+    pub struct ReleaseCheck {
+        pub version: String,
+        /// whether manifest.toml carries a signature made by a trusted release key.
+        pub signed: bool,
+        pub artifacts: Vec<ArtifactCheck>,
+    }
+
+    impl ReleaseCheck {
+        /// a release is only shippable when it is signed and every artifact matches.
+        pub fn is_valid(&self) -> bool {
+            self.signed && self.artifacts.iter().all(|a| a.valid)
+        }
+    }
+
+    /// a comparison of the package files of two release manifest.
+    #[derive(Serialize)]
+    pub struct ManifestDiff {
+        pub from_version: String,
+        pub to_version: String,
+        pub added: Vec<String>,
+        pub removed: Vec<String>,
+        pub changed: Vec<String>,
+        pub unchanged: usize,
+        /// the difference in the total size of the package files in bytes.
+        pub size_change: i64,
+    }
+
+    impl ManifestDiff {
+        pub fn is_empty(&self) -> bool {
+            self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+        }
     }
 
-    impl From<&Path> for Recipe {
-        /// turn a raw TOML string into a Recipe struct
-        fn from(recipe_path: &Path) -> Self {
-            if !recipe_path.exists() {
-                panic!("The recipe at {} does not exist.", recipe_path.display());
+    /// reads a TOML file from disk and deseralizes it into a provided <T> generic.
+    pub fn read_toml<T>(path: &Path) -> Result<T, BootstrapError>
+    where
+        T: DeserializeOwned,
+    {
+        let contents = read(path)?;
+        match toml::from_slice(&contents) {
+            Err(e) => Err(BootstrapError::TomlParseFailure(
+                path.display().to_string(),
+                e.to_string(),
+            )),
+            Ok(model) => Ok(model),
+        }
+    }
+
+    /// re-checks a staged release directory by verifying the signature of its manifest.toml
+    /// and hashing every artifact the manifest references.
+    pub fn verify_release(release_dir: &Path) -> Result<ReleaseCheck, BootstrapError> {
+        let mut manifest_path = release_dir.to_path_buf();
+        manifest_path.push("manifest.toml");
+        let manifest = read_toml::<Manifest>(&manifest_path)?;
+        let signed = match read_to_string(signature_path(&manifest_path.to_string_lossy())) {
+            Ok(signature) => {
+                verify_signature(&read(&manifest_path)?, &signature, &get_release_public_keys())
             }
+            Err(_e) => false,
+        };
+        if !signed {
+            log::warn!("{} is not signed by a trusted key.", manifest_path.display());
+        }
+
+        let mut expected = vec![
+            (&manifest.package.url, &manifest.package.hash),
+            (&manifest.installer.url, &manifest.installer.hash),
+        ];
+        for delta in &manifest.package.deltas {
+            expected.push((&delta.url, &delta.hash));
+        }
+        if let Some(chunks) = &manifest.package.chunks {
+            expected.push((&chunks.index_url, &chunks.index_hash));
+        }
+        for component in &manifest.components {
+            expected.push((&component.package.url, &component.package.hash));
+        }
+        let artifacts = expected
+            .into_iter()
+            .map(|(url, hash)| check_artifact(release_dir, url, hash))
+            .collect();
+        Ok(ReleaseCheck {
+            version: manifest.version.clone(),
+            signed,
+            artifacts,
+        })
+    }
+
+    /// hashes the local copy of a remote artifact and compares it to the expected hash.
+    fn check_artifact(release_dir: &Path, url: &str, expected_hash: &str) -> ArtifactCheck {
+        let name = url.rsplit('/').next().unwrap_or(url).to_string();
+        let mut artifact_path = release_dir.to_path_buf();
+        artifact_path.push(&name);
+        let actual_hash = sha_256(&artifact_path).unwrap_or_default();
+        let valid = !actual_hash.is_empty() && actual_hash.eq_ignore_ascii_case(expected_hash);
+        if !valid {
+            log::warn!("{} does not match the manifest.", artifact_path.display());
+        }
+        ArtifactCheck {
+            name,
+            expected_hash: expected_hash.to_string(),
+            actual_hash,
+            valid,
+        }
+    }
+
+    /// points a branch of the Releases.toml inside of `output_root` at the release another branch is on.
+    pub fn promote_branch(
+        output_root: &Path,
+        from: ReleaseBranch,
+        to: ReleaseBranch,
+        signing_key: Option<&Path>,
+    ) -> Result<Releases, BootstrapError> {
+        let mut release_path = output_root.to_path_buf();
+        release_path.push("Releases.toml");
+        let releases = read_toml::<Releases>(&release_path)?;
+        let release_dir = match get_release_dir(output_root, &releases.get(&from)?.manifest_url) {
+            Some(d) => d,
+            None => {
+                return Err(BootstrapError::ReleasePromotionFailure(format!(
+                    "The {} branch does not point at a release.",
+                    from
+                )))
+            }
+        };
+        promote_release(&release_dir, to, signing_key)
+    }
+
+    /// points a branch at an already staged release without re-baking it.
+    /// every artifact is checked against the staged manifest first, the promoted branch then
+    /// serves that exact manifest so the bits that were tested are the bits that ship.
+    pub fn promote_release(
+        release_dir: &Path,
+        to: ReleaseBranch,
+        signing_key: Option<&Path>,
+    ) -> Result<Releases, BootstrapError> {
+        let output_root = match release_dir.parent().and_then(|p| p.parent()) {
+            Some(p) => p.to_path_buf(),
+            None => {
+                return Err(BootstrapError::ReleasePromotionFailure(
+                    "Cannot locate the output directory of the release.".to_string(),
+                ))
+            }
+        };
+        let _lock = OutputLock::acquire(&output_root)?;
+        let check = verify_release(release_dir)?;
+        if !check.is_valid() {
+            let invalid: Vec<&str> = check
+                .artifacts
+                .iter()
+                .filter(|a| !a.valid)
+                .map(|a| a.name.as_str())
+                .collect();
+            return Err(BootstrapError::ReleasePromotionFailure(format!(
+                "{} failed verification. signed: {}, mismatched artifacts: [{}]",
+                release_dir.display(),
+                check.signed,
+                invalid.join(", ")
+            )));
+        }
+        for artifact in &check.artifacts {
+            log::info!("{} == {}", artifact.name, artifact.actual_hash);
+        }
+
+        let mut manifest_path = release_dir.to_path_buf();
+        manifest_path.push("manifest.toml");
+        let manifest = read_toml::<Manifest>(&manifest_path)?;
+        // the manifest is served next to the package it was baked with.
+        let manifest_url = match manifest.package.url.rfind('/') {
+            Some(i) => format!("{}/manifest.toml", &manifest.package.url[..i]),
+            None => {
+                return Err(BootstrapError::ReleasePromotionFailure(format!(
+                    "The package URL {} of the release is malformed.",
+                    manifest.package.url
+                )))
+            }
+        };
+
+        let (mut releases, etag) = read_releases(&output_root)?;
+        if let Err(e) = check_newer(
+            &to,
+            releases.get(&to).ok(),
+            &manifest.version,
+            manifest.rollback,
+        ) {
+            return Err(BootstrapError::ReleasePromotionFailure(e));
+        }
+        match releases.get(&to) {
+            Ok(previous) => log::info!(
+                "promoting {} to the {} branch, previously on {}.",
+                manifest.version,
+                to,
+                previous.version
+            ),
+            Err(_) => log::info!(
+                "promoting {} to the new {} branch.",
+                manifest.version,
+                to
+            ),
+        }
+        releases.set(
+            to,
+            Branch {
+                version: manifest.version.clone(),
+                manifest_url,
+                ..Branch::default()
+            },
+        );
+        let signing_key = get_signing_key(signing_key)?;
+        write_releases(&releases, &output_root, signing_key.as_ref(), &etag)?;
+        Ok(releases)
+    }
+
+    /// changes the percentage of machines a branch offers its release to.
+    /// at 100% the rollout is complete and the fallback is dropped, at 0% it is halted
+    /// and every machine stays on the fallback.
+    pub fn ramp_branch(
+        output_root: &Path,
+        branch: ReleaseBranch,
+        percentage: u8,
+        signing_key: Option<&Path>,
+    ) -> Result<Releases, BootstrapError> {
+        if percentage > 100 {
+            return Err(BootstrapError::RolloutFailure(format!(
+                "{} is not a percentage between 0 and 100.",
+                percentage
+            )));
+        }
+        let _lock = OutputLock::acquire(output_root)?;
+        let (mut releases, etag) = read_releases(output_root)?;
+        let mut release = releases.get(&branch)?.clone();
+        if release.manifest_url.is_empty() {
+            return Err(BootstrapError::RolloutFailure(format!(
+                "The {} branch does not point at a release.",
+                branch
+            )));
+        }
+        if percentage == 100 {
+            log::info!("completing the rollout of {} on {}.", release.version, branch);
+            release.rollout = None;
+            release.fallback = None;
+        } else {
+            let fallback = match &release.fallback {
+                Some(f) => f,
+                None => {
+                    return Err(BootstrapError::RolloutFailure(format!(
+                        "The {} branch has no release to fall back to, bake {} with a rollout to start one.",
+                        branch, release.version
+                    )))
+                }
+            };
+            log::info!(
+                "offering {} on {} to {}% of machines, previously {}%. the rest stay on {}.",
+                release.version,
+                branch,
+                percentage,
+                release.rollout.unwrap_or(100),
+                fallback.version
+            );
+            release.rollout = Some(percentage);
+        }
+        releases.set(branch, release);
+        let signing_key = get_signing_key(signing_key)?;
+        write_releases(&releases, output_root, signing_key.as_ref(), &etag)?;
+        Ok(releases)
+    }
+
+    /// rejects a release that is not newer than the one a branch already points at,
+    /// unless it is marked as a rollback target.
+    fn check_newer(
+        branch: &ReleaseBranch,
+        current: Option<&Branch>,
+        version: &str,
+        rollback: bool,
+    ) -> Result<(), String> {
+        let current = match current {
+            Some(c) if !c.version.is_empty() => c,
+            _ => return Ok(()),
+        };
+        match compare_versions(version, &current.version) {
+            Some(Ordering::Greater) => Ok(()),
+            Some(_) if rollback => {
+                log::warn!(
+                    "rolling the {} branch back from {} to {}.",
+                    branch,
+                    current.version,
+                    version
+                );
+                Ok(())
+            }
+            Some(_) => Err(format!(
+                "{} is not newer than {} which the {} branch is on. bake it with rollback = true to stage it anyway.",
+                version, current.version, branch
+            )),
+            None => {
+                log::warn!(
+                    "unable to compare {} with {} on the {} branch as semver.",
+                    version,
+                    current.version,
+                    branch
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// maps the manifest URL of a branch back to the staged release directory it was baked into.
+    /// the output directory mirrors the release host, so the last segments are the branch and version.
+    fn get_release_dir(output_root: &Path, manifest_url: &str) -> Option<PathBuf> {
+        let mut segments = manifest_url.rsplit('/').skip(1);
+        let version = segments.next().filter(|s| !s.is_empty())?;
+        let branch = segments.next().filter(|s| !s.is_empty())?;
+        let mut release_dir = output_root.to_path_buf();
+        release_dir.push(branch);
+        release_dir.push(version);
+        Some(release_dir)
+    }
+
+    /// compares the package files of two manifest by path, hash and size.
+    pub fn diff_manifests(from: &Manifest, to: &Manifest) -> ManifestDiff {
+        let previous_files: HashMap<&str, &PackageFile> = from
+            .package
+            .files
+            .iter()
+            .map(|f| (f.path.as_str(), f))
+            .collect();
+        let mut diff = ManifestDiff {
+            from_version: from.version.clone(),
+            to_version: to.version.clone(),
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+            unchanged: 0,
+            size_change: 0,
+        };
+        for file in &to.package.files {
+            match previous_files.get(file.path.as_str()) {
+                Some(old) if old.hash == file.hash && old.size == file.size => diff.unchanged += 1,
+                Some(_old) => diff.changed.push(file.path.clone()),
+                None => diff.added.push(file.path.clone()),
+            }
+        }
+        for file in &from.package.files {
+            if !to.package.files.iter().any(|f| f.path == file.path) {
+                diff.removed.push(file.path.clone());
+            }
+        }
+        let total_size = |m: &Manifest| m.package.files.iter().map(|f| f.size as i64).sum::<i64>();
+        diff.size_change = total_size(to) - total_size(from);
+        diff
+    }
+
+    /// loads the release signing key if one was provided.
+    fn get_signing_key(key_path: Option<&Path>) -> Result<Option<Keypair>, BootstrapError> {
+        match key_path {
+            Some(p) => Ok(Some(load_signing_key(p)?)),
+            None => {
+                log::warn!("no signing key was provided. clients will refuse this release until it is signed.");
+                Ok(None)
+            }
+        }
+    }
+
+    /// reads the Releases.toml of an output directory along with the hash of its contents,
+    /// which `write_releases` checks again before replacing it.
+    fn read_releases(output_root: &Path) -> Result<(Releases, String), BootstrapError> {
+        let mut release_path = output_root.to_path_buf();
+        release_path.push("Releases.toml");
+        let contents = read(&release_path)?;
+        match toml::from_slice(&contents) {
+            Err(e) => Err(BootstrapError::TomlParseFailure(
+                release_path.display().to_string(),
+                e.to_string(),
+            )),
+            Ok(releases) => Ok((releases, sha_256_bytes(&contents))),
+        }
+    }
+
+    /// the hash of the Releases.toml in the root of the output directory, empty if there is none.
+    fn get_releases_etag(output_root: &Path) -> Result<String, BootstrapError> {
+        let mut release_path = output_root.to_path_buf();
+        release_path.push("Releases.toml");
+        match read(&release_path) {
+            Ok(current) => Ok(sha_256_bytes(&current)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(BootstrapError::from(e)),
+        }
+    }
+
+    /// refuses to go on if the Releases.toml in the root of the output directory no longer has
+    /// the hash `etag`, including when it was created or deleted since. returns its path.
+    fn check_releases_etag(output_root: &Path, etag: &str) -> Result<PathBuf, BootstrapError> {
+        let mut release_path = output_root.to_path_buf();
+        release_path.push("Releases.toml");
+        if get_releases_etag(output_root)? != etag {
+            return Err(BootstrapError::ReleasesChanged(
+                release_path.display().to_string(),
+            ));
+        }
+        Ok(release_path)
+    }
+
+    /// writes Releases.toml into the root of the output directory and signs it when a key is provided.
+    /// `etag` is the hash of the Releases.toml the changes were made to, empty if there was none.
+    /// the write is refused if the file on disk no longer has that hash, so a publisher never
+    /// overwrites changes it did not see. both files are written aside and renamed into place.
+    fn write_releases(
+        releases: &Releases,
+        output_root: &Path,
+        signing_key: Option<&Keypair>,
+        etag: &str,
+    ) -> Result<PathBuf, BootstrapError> {
+        let releases_encoded = match toml::to_string(&releases) {
+            Ok(c) => c,
+            Err(e) => return Err(BootstrapError::RecipeStageFailure(e.to_string())),
+        };
+        log::debug!("encoded releases \n\n{}", &releases_encoded);
+        let release_path = check_releases_etag(output_root, etag)?;
+        let mut partial_path = output_root.to_path_buf();
+        partial_path.push("Releases.toml.partial");
+        write(&partial_path, &releases_encoded)?;
+        let partial_signature = match signing_key {
+            Some(key) => Some(sign_file(key, &partial_path)?),
+            None => None,
+        };
+        // the toml and its signature are two renames, a copy of the previous toml is kept
+        // until both are in place so a failed signature never leaves them mismatched.
+        let mut previous_path = output_root.to_path_buf();
+        previous_path.push("Releases.toml.previous");
+        let has_previous = release_path.exists();
+        if has_previous {
+            copy(&release_path, &previous_path)?;
+        }
+        rename(&partial_path, &release_path)?;
+        log::info!("wrote Releases.toml to {}", &release_path.display());
+        if let Some(partial_signature) = partial_signature {
+            let signature_path = PathBuf::from(signature_path(&release_path.to_string_lossy()));
+            if let Err(e) = rename(&partial_signature, &signature_path) {
+                log::error!("unable to sign Releases.toml, restoring the previous one. {}", e);
+                if has_previous {
+                    rename(&previous_path, &release_path)?;
+                } else {
+                    remove_file(&release_path)?;
+                }
+                return Err(e.into());
+            }
+            log::info!("signed Releases.toml to {}", &signature_path.display());
+        }
+        if has_previous {
+            remove_file(&previous_path)?;
+        }
+        Ok(release_path)
+    }
+
+    /// the directory next to a release that it is staged in or moved aside to, such as `.2.0.0.staging`.
+    /// the leading dot keeps it from being mistaken for a version.
+    fn get_sibling_dir(release_dir: &Path, purpose: &str) -> PathBuf {
+        let version = release_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        release_dir.with_file_name(format!(".{}.{}", version, purpose))
+    }
+
+    /// renames a staged release into place. a previous release of the same version is moved
+    /// aside first as a directory cannot be renamed over another, and is put back if that fails.
+    /// returns where the previous release was moved to, it is left for the caller to delete.
+    fn commit_release(staging_dir: &Path, release_dir: &Path) -> Result<Option<PathBuf>, Error> {
+        if !release_dir.exists() {
+            rename(staging_dir, release_dir)?;
+            return Ok(None);
+        }
+        let previous_dir = get_sibling_dir(release_dir, "previous");
+        if previous_dir.exists() {
+            remove_dir_all(&previous_dir)?;
+        }
+        rename(release_dir, &previous_dir)?;
+        if let Err(e) = rename(staging_dir, release_dir) {
+            rename(&previous_dir, release_dir)?;
+            return Err(e);
+        }
+        log::info!("replaced the previous release inside {}", release_dir.display());
+        Ok(Some(previous_dir))
+    }
+
+    /// undoes `commit_release`, moving the release back to its staging dir and the previous release back into place.
+    fn revert_release(
+        staging_dir: &Path,
+        release_dir: &Path,
+        previous_dir: Option<&Path>,
+    ) -> Result<(), Error> {
+        rename(release_dir, staging_dir)?;
+        if let Some(previous_dir) = previous_dir {
+            rename(previous_dir, release_dir)?;
+        }
+        Ok(())
+    }
+
+    impl Recipe {
+        /// reads and parses the recipe at a path.
+        pub fn read(recipe_path: &Path) -> Result<Recipe, BootstrapError> {
             if !recipe_path.is_file() {
-                panic!("The provided recipe path is not a file.");
+                return Err(BootstrapError::RecipeBakeFailure(format!(
+                    "The recipe at {} does not exist or is not a file.",
+                    recipe_path.display()
+                )));
             }
-            let contents =
-                read_to_string(recipe_path).expect("Something went wrong reading the recipe file");
-            if contents.is_empty() {
-                panic!("The provided recipe at {} is empty.", recipe_path.display());
+            let contents = read_to_string(recipe_path)?;
+            if contents.trim().is_empty() {
+                return Err(BootstrapError::RecipeBakeFailure(format!(
+                    "The provided recipe at {} is empty.",
+                    recipe_path.display()
+                )));
+            }
+            match toml::from_str::<Recipe>(&contents) {
+                Ok(recipe) => Ok(recipe),
+                Err(e) => Err(BootstrapError::RecipeBakeFailure(format!(
+                    "Unable to parse the recipe at {}: {}",
+                    recipe_path.display(),
+                    e
+                ))),
             }
-            let recipe = toml::from_str::<Recipe>(&contents).expect("Unable to parse recipe");
-            recipe
         }
     }
 }
@@ -284,21 +1833,38 @@ pub mod bakery {
 pub mod updater {
 
     use crate::etc::constants::BootstrapError;
+    use crate::etc::deferral::{Deferral, DeferralPolicy};
+    use crate::etc::rollout::in_rollout;
+    use crate::etc::version::compare_versions;
     use crate::io::disk::to_slash;
     use crate::io::disk::{
-        copy_dir, delete_dir_contents, dir_contains_all_files, get_filename, move_dir, swap_files,
+        copy_dir, delete_dir_contents, dir_contains_all_files, get_dir_files, get_filename,
+        move_dir, swap_files,
+    };
+    use crate::io::chunk::{
+        assemble_file, chunk_hash, chunk_path, chunk_url, read_local_chunk, scan_local_chunks, write_chunk,
+        ChunkIndex, ChunkRef, LocalChunk,
     };
-    use crate::io::hash::sha_256;
+    use crate::io::delta::patch_file;
+    use crate::io::hash::{sha_256, sha_256_bytes, sha_256_with_size};
+    use crate::io::journal::{ApplyJournal, ApplyStep, Recovery};
+    use crate::io::sign::{get_release_public_keys, signature_path, verify_signature};
     use crate::io::zip::unzip;
-    use crate::net::http::{download_file, download_toml, post};
+    use crate::net::http::{download_bytes, download_file, post};
+    use crate::os::actions::{run_actions, Action, ActionContext};
     use crate::os::files::{
         grant_full_permissions, take_ownership_of_dir, unblock_file, unblock_path,
     };
+    use crate::os::requirements::Requirements;
     use crate::os::windows::{
         create_reg_key, delete_reg_key, get_reg_key, get_uninstallers, set_uninstall_value, RegistryHandle,
+        ToRegValue, WindowsActionHost,
     };
+    use serde::de::DeserializeOwned;
     use serde::{Deserialize, Serialize};
-    use std::fs::{create_dir_all, remove_dir_all};
+    use std::cmp::Ordering;
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs::{copy, create_dir_all, read, remove_dir_all, rename};
 
     use std::{
         env::{temp_dir, var},
@@ -329,11 +1895,30 @@ pub mod updater {
         Patch,
     }
 
-    #[derive(Debug, Deserialize, Copy, Clone)]
-    pub enum ReleaseBranch {
-        Stable,
-        Beta,
-        Nightly,
+    /// what kind of update is being offered, which decides if the user can put it off.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum UpdateKind {
+        /// Rainway is not installed yet.
+        Install,
+        /// the installed version is older than the minimum supported version.
+        Required,
+        /// the release is marked as critical.
+        Critical,
+        /// the update can be deferred if the release allows it.
+        Optional,
+    }
+
+    /// a named release branch, such as stable, beta, qa or partner-acme.
+    /// names are lowercase letters, digits, dashes and underscores so they are safe to use
+    /// as a directory and URL segment. any other name is rejected when it is parsed.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+    #[serde(try_from = "String", into = "String")]
+    pub struct ReleaseBranch(pub(crate) String);
+
+    impl ReleaseBranch {
+        pub fn as_str(&self) -> &str {
+            &self.0
+        }
     }
     #[derive(Default, Clone)]
     pub struct ActiveUpdate {
@@ -346,25 +1931,33 @@ pub mod updater {
         pub temp_name: String,
         /// the currently installed info of the parent applicaton.
         pub install_info: InstallInfo,
+        /// forces the full package to be used even when a delta is available.
+        pub force_full_package: bool,
     }
 
     impl ActiveUpdate {
         /// fetches and sets the manifest for a given branch
-        pub fn get_manifest(&mut self, branch: ReleaseBranch) -> Result<(), BootstrapError> {
+        pub fn get_manifest(&mut self, branch: &ReleaseBranch) -> Result<(), BootstrapError> {
             let releases = get_releases()?;
-            let manifest_url = match branch {
-                ReleaseBranch::Stable => &releases.stable.manifest_url,
-                ReleaseBranch::Beta => &releases.beta.manifest_url,
-                ReleaseBranch::Nightly => &releases.nightly.manifest_url,
-            };
+            let setup_id = self.get_setup_id();
+            let release = releases.get(branch)?;
+            let (version, manifest_url) = release.resolve(&setup_id);
+            if let Some(percentage) = release.rollout {
+                log::info!(
+                    "{} is rolling out to {}% of machines, this machine is on {}.",
+                    release.version,
+                    percentage,
+                    version
+                );
+            }
             if manifest_url.is_empty() {
                 return Err(BootstrapError::ReleaseLookupFailed(format!(
                     "Manifest URL missing the {} branch.",
                     &branch
                 )));
             }
-            log::info!("pulling the latest release for the {:?} branch", branch);
-            match download_toml::<Manifest>(&manifest_url) {
+            log::info!("pulling the latest release for the {} branch", branch);
+            match download_signed_toml::<Manifest>(&manifest_url) {
                 Ok(m) => {
                     self.manifest = m;
                     return Ok(());
@@ -378,10 +1971,83 @@ pub mod updater {
             }
         }
 
+        /// returns the setup id used to place this machine into rollouts.
+        /// fresh installs do not have one stored yet, so it is read from the installer instead.
+        fn get_setup_id(&self) -> String {
+            if !self.install_info.id.is_empty() {
+                return self.install_info.id.clone();
+            }
+            get_installer_id().unwrap_or_default()
+        }
+
         /// returns a list of all the files inside of a releases package.zip
         pub fn get_package_files(&self) -> Vec<String> {
-           self.manifest.package.files.clone()
+            self.manifest
+                .package
+                .files
+                .iter()
+                .map(|f| f.path.clone())
+                .collect()
+        }
+        /// returns the components that are installed, the required ones and the ones the user selected.
+        pub fn get_components(&self) -> Vec<&Component> {
+            self.partition_components(&get_selected_components()).0
+        }
+
+        /// splits the components into the ones installed with a selection and the ones that are not.
+        fn partition_components(&self, selected: &[String]) -> (Vec<&Component>, Vec<&Component>) {
+            self.manifest
+                .components
+                .iter()
+                .partition(|c| c.required || selected.iter().any(|s| s.eq_ignore_ascii_case(&c.name)))
+        }
+
+        /// returns the files of components left out of a selection that are still in the install.
+        fn get_deselected_files(&self, selected: &[String]) -> Vec<String> {
+            let (installed, deselected) = self.partition_components(selected);
+            let kept: Vec<&String> = self
+                .manifest
+                .package
+                .files
+                .iter()
+                .chain(installed.iter().flat_map(|c| c.package.files.iter()))
+                .map(|f| &f.path)
+                .collect();
+            deselected
+                .iter()
+                .flat_map(|c| c.package.files.iter())
+                .filter(|f| !kept.contains(&&f.path) && self.install_info.path.join(&f.path).exists())
+                .map(|f| f.path.clone())
+                .collect()
+        }
+
+        /// changes which optional components are installed, the selection is kept in the install state.
+        /// `validate` fails while files of a component that is no longer selected are installed,
+        /// so the release is applied again without them.
+        pub fn select_components(&self, names: &[String]) -> Result<(), BootstrapError> {
+            let mut selected = Vec::with_capacity(names.len());
+            for name in names {
+                let name = name.trim().to_lowercase();
+                if name.is_empty() || selected.contains(&name) {
+                    continue;
+                }
+                if !self.manifest.components.iter().any(|c| c.name == name) {
+                    return Err(BootstrapError::UnknownComponent(name));
+                }
+                selected.push(name);
+            }
+            log::info!("selected components: {}", selected.join(", "));
+            set_rainway_key_value("Components", &selected.join(","))
+        }
+
+        /// returns the files of the installed components.
+        fn get_component_files(&self) -> Vec<PackageFile> {
+            self.get_components()
+                .into_iter()
+                .flat_map(|c| c.package.files.iter().cloned())
+                .collect()
         }
+
         /// returns the temporary file path where downloaded packages will be written.
         pub fn get_temp_name(&self) -> String {
             self.temp_name.clone()
@@ -393,21 +2059,55 @@ pub mod updater {
         pub fn get_ext(&self) -> String {
             match self.update_type {
                 UpdateType::Install => ".exe",
+                UpdateType::Patch if self.get_chunks().is_some() => ".toml",
                 UpdateType::Patch => ".zip",
             }
             .to_string()
         }
 
+        /// returns the delta that patches the installed version up to the remote version.
+        /// when no delta path exists from the installed version the full package is used instead.
+        pub fn get_delta(&self) -> Option<&Delta> {
+            if self.update_type != UpdateType::Patch || self.force_full_package {
+                return None;
+            }
+            self.manifest
+                .package
+                .deltas
+                .iter()
+                .find(|d| d.from == self.install_info.version)
+        }
+
+        /// returns the chunks of the release when there is no delta from the installed version.
+        /// only the chunk index is downloaded up front, the chunks are fetched while staging.
+        pub fn get_chunks(&self) -> Option<&Chunks> {
+            if self.update_type != UpdateType::Patch
+                || self.force_full_package
+                || self.get_delta().is_some()
+            {
+                return None;
+            }
+            self.manifest.package.chunks.as_ref()
+        }
+
         pub fn get_url(&self) -> String {
             match self.update_type {
                 UpdateType::Install => self.manifest.installer.url.clone(),
-                UpdateType::Patch => self.manifest.package.url.clone(),
+                UpdateType::Patch => match (self.get_delta(), self.get_chunks()) {
+                    (Some(delta), _) => delta.url.clone(),
+                    (None, Some(chunks)) => chunks.index_url.clone(),
+                    (None, None) => self.manifest.package.url.clone(),
+                },
             }
         }
         pub fn get_hash(&self) -> String {
             match self.update_type {
                 UpdateType::Install => self.manifest.installer.hash.clone(),
-                UpdateType::Patch => self.manifest.package.hash.clone(),
+                UpdateType::Patch => match (self.get_delta(), self.get_chunks()) {
+                    (Some(delta), _) => delta.hash.clone(),
+                    (None, Some(chunks)) => chunks.index_hash.clone(),
+                    (None, None) => self.manifest.package.hash.clone(),
+                },
             }
         }
         /// creates a temporary file name for the update.
@@ -416,15 +2116,123 @@ pub mod updater {
         }
 
         /// Checks if the current installation is out of date.
-        /// It does this by first checking if all the files listed in the manifest are present on disk.
+        /// A remote version older than the installed one is never installed unless the
+        /// manifest marks it as a rollback, the installation is then treated as up to date.
+        /// Otherwise it checks if all the files listed in the manifest are present on disk.
         /// If all files are present, it then compares the remote and local version.
         /// Using this method bad installs/updates can be recovered.
-        pub fn validate(&self) -> bool {
-            if !validate_files(&self.install_info.path, &self.get_package_files()) {
+        /// When `rehash` is set, every file with a recorded hash is also hashed on disk
+        /// so corrupted or tampered files are caught.
+        pub fn validate(&self, rehash: bool) -> bool {
+            if self.is_downgrade() {
+                if !self.manifest.rollback {
+                    log::warn!(
+                        "refusing to downgrade from {} to {} as it is not marked as a rollback.",
+                        self.install_info.version,
+                        self.manifest.version
+                    );
+                    return true;
+                }
+                log::warn!(
+                    "rolling back from {} to {}.",
+                    self.install_info.version,
+                    self.manifest.version
+                );
+                return false;
+            }
+            let component_files = self.get_component_files();
+            let mut files = self.get_package_files();
+            files.extend(component_files.iter().map(|f| f.path.clone()));
+            if !validate_files(&self.install_info.path, &files) {
                 log::warn!("We need to update because required files are missing.");
                 return false;
             }
-            return &self.install_info.version == &self.manifest.version;
+            let deselected_files = self.get_deselected_files(&get_selected_components());
+            if !deselected_files.is_empty() {
+                log::warn!(
+                    "We need to update because {} files of deselected components are installed.",
+                    deselected_files.len()
+                );
+                return false;
+            }
+            if rehash
+                && (!validate_file_hashes(&self.install_info.path, &self.manifest.package.files)
+                    || !validate_file_hashes(&self.install_info.path, &component_files))
+            {
+                log::warn!("We need to update because installed files do not match the manifest.");
+                return false;
+            }
+            match compare_versions(&self.install_info.version, &self.manifest.version) {
+                Some(ordering) => ordering == Ordering::Equal,
+                None => &self.install_info.version == &self.manifest.version,
+            }
+        }
+
+        /// returns what kind of update this is. installs below the minimum supported version
+        /// and critical releases have to be installed before the app starts.
+        pub fn get_update_kind(&self) -> UpdateKind {
+            if self.update_type == UpdateType::Install {
+                return UpdateKind::Install;
+            }
+            let minimum = &self.manifest.minimum_supported_version;
+            if !minimum.is_empty()
+                && compare_versions(&self.install_info.version, minimum) == Some(Ordering::Less)
+            {
+                return UpdateKind::Required;
+            }
+            if self.manifest.critical {
+                return UpdateKind::Critical;
+            }
+            UpdateKind::Optional
+        }
+
+        fn get_deferral_policy(&self) -> DeferralPolicy {
+            DeferralPolicy {
+                max_deferrals: self.manifest.max_deferrals,
+                max_days: self.manifest.max_deferral_days,
+            }
+        }
+
+        /// returns true if the user can put this update off and start the installed version.
+        pub fn can_defer(&self) -> bool {
+            if self.get_update_kind() != UpdateKind::Optional {
+                return false;
+            }
+            get_deferral().can_defer(
+                &self.manifest.version,
+                self.get_deferral_policy(),
+                unix_now(),
+            )
+        }
+
+        /// returns how many more times the update can be deferred, None if it is only limited by days.
+        pub fn deferrals_remaining(&self) -> Option<u32> {
+            get_deferral().remaining(
+                &self.manifest.version,
+                self.get_deferral_policy(),
+                unix_now(),
+            )
+        }
+
+        /// records that the user put this update off.
+        pub fn defer(&self) -> Result<(), BootstrapError> {
+            if !self.can_defer() {
+                return Err(BootstrapError::UpdateNotDeferrable(self.manifest.version.clone()));
+            }
+            let deferral = get_deferral().record(&self.manifest.version, unix_now());
+            log::info!(
+                "deferred {} {} time(s) since {}.",
+                deferral.version,
+                deferral.count,
+                deferral.since
+            );
+            store_deferral(&deferral)
+        }
+
+        /// returns true if the remote version is older than the installed version.
+        pub fn is_downgrade(&self) -> bool {
+            compare_versions(&self.manifest.version, &self.install_info.version)
+                == Some(Ordering::Less)
         }
 
         /// updates the version string used by the Add/Remove program menu on Windows
@@ -441,7 +2249,7 @@ pub mod updater {
         }
 
         /// allows the release branch to be changed to a new preferred.
-        pub fn change_release_branch(&self, branch: ReleaseBranch) {
+        pub fn change_release_branch(&self, branch: &ReleaseBranch) {
             if let Err(e) = set_uninstall_value(
                 "QuicheBranch",
                 &branch.to_string(),
@@ -506,7 +2314,15 @@ pub mod updater {
 
             let install_info = InstallInfo {
                 version: uninstaller.version,
-                branch: ReleaseBranch::from(uninstaller.branch),
+                // an install whose stored branch is not a valid name keeps updating from the
+                // default branch, the error is logged so the move off its branch is not silent.
+                branch: match ReleaseBranch::from_installed(&uninstaller.branch) {
+                    Ok(branch) => branch,
+                    Err(e) => {
+                        log::error!("{}", e);
+                        ReleaseBranch::default()
+                    }
+                },
                 name: uninstaller.name,
                 path,
                 registry_key: uninstaller.key,
@@ -521,12 +2337,83 @@ pub mod updater {
             match ActiveUpdate::get_install_info() {
                 Ok(install_info) => {
                     self.install_info = install_info;
+                    // an unrecovered journal is tried again on the next start, apply refuses to run until then.
+                    if let Err(e) = self.recover_apply() {
+                        log::error!("unable to recover an interrupted update. {}", e);
+                    }
                     Ok(())
                 }
                 Err(e) => Err(e),
             }
         }
 
+        /// finishes or rolls back an update that was interrupted while it was applied,
+        /// such as by a crash or power loss, using the journal it left behind.
+        pub fn recover_apply(&mut self) -> Result<(), BootstrapError> {
+            let journal_path = get_journal_path();
+            let mut journal = match ApplyJournal::read(&journal_path)? {
+                Some(j) => j,
+                None => return Ok(()),
+            };
+            match journal.recovery() {
+                Recovery::Discard => {
+                    log::info!(
+                        "the update to {} was interrupted before the install was changed.",
+                        journal.version
+                    );
+                    ApplyJournal::remove(&journal_path)?;
+                }
+                Recovery::RollBack => {
+                    log::warn!(
+                        "the update to {} was interrupted while replacing {}. rolling it back.",
+                        journal.version,
+                        journal.install_path.display()
+                    );
+                    roll_back_apply(&journal)?;
+                    log::warn!("rolled back update.");
+                }
+                Recovery::RunActions => {
+                    log::warn!(
+                        "the update to {} was interrupted while running its post-install actions. running them again.",
+                        journal.version
+                    );
+                    // the actions are safe to run again, any that already ran are redone or skipped.
+                    if let Err(e) = run_post_install_actions(&journal) {
+                        log::warn!("{}. rolling back the update.", e);
+                        roll_back_apply(&journal)?;
+                        log::warn!("rolled back update.");
+                        return Ok(());
+                    }
+                    journal.record(&journal_path, ApplyStep::Applied)?;
+                    self.finish_recovered_apply(&journal)?;
+                }
+                Recovery::Finish => {
+                    log::info!(
+                        "the update to {} was applied but not recorded. finishing it.",
+                        journal.version
+                    );
+                    self.finish_recovered_apply(&journal)?;
+                }
+            }
+            Ok(())
+        }
+
+        /// records the new version of a recovered apply whose actions completed and removes its journal.
+        fn finish_recovered_apply(&mut self, journal: &ApplyJournal) -> Result<(), BootstrapError> {
+            if let Err(e) = set_uninstall_value(
+                "DisplayVersion",
+                &journal.version,
+                &self.install_info.registry_key,
+                self.install_info.registry_handle,
+            ) {
+                log::warn!("Unable to update display version: {}", e.to_string());
+            }
+            self.post_update(&journal.version);
+            self.install_info.version = journal.version.clone();
+            ApplyJournal::remove(&get_journal_path())?;
+            Ok(())
+        }
+
         pub fn post_headers() -> std::collections::HashMap<&'static str, &'static str> {
             use std::collections::HashMap;
 
@@ -601,27 +2488,65 @@ pub mod updater {
         }
     }
 
-    #[derive(Serialize, Deserialize, Default)]
+    #[derive(Serialize, Deserialize, Default, Clone)]
     pub struct Branch {
         /// The version of the active branch.
         pub version: String,
         /// The URL to the manifest file of the branches latest release.
         pub manifest_url: String,
+        /// The percentage of machines, from 0 to 100, that are offered this release.
+        /// Every machine is when this is not set.
+        pub rollout: Option<u8>,
+        /// The release machines outside of the rollout stay on.
+        pub fallback: Option<Fallback>,
+    }
+
+    #[derive(Serialize, Deserialize, Default, Clone)]
+    pub struct Fallback {
+        /// The version machines outside of the rollout are given.
+        pub version: String,
+        /// The URL to the manifest file of the fallback release.
+        pub manifest_url: String,
+    }
+
+    impl Branch {
+        /// returns the version and manifest URL a machine should use.
+        /// a machine is placed into the rollout by hashing its setup id with the version,
+        /// so it makes the same decision every time it checks for updates.
+        pub fn resolve(&self, setup_id: &str) -> (&str, &str) {
+            match (self.rollout, &self.fallback) {
+                (Some(percentage), Some(fallback))
+                    if !in_rollout(percentage, setup_id, &self.version) =>
+                {
+                    (&fallback.version, &fallback.manifest_url)
+                }
+                _ => (&self.version, &self.manifest_url),
+            }
+        }
     }
 
     #[derive(Serialize, Deserialize, Default)]
+    #[serde(transparent)]
     pub struct Releases {
-        /// The stable branch, used by default.
-        pub stable: Branch,
-        /// The beta branch, not used right now, but can be used for
-        /// doing things such as 10% rollouts.
-        pub beta: Branch,
-        /// The nightly branch, not used now, but can be used for people
-        /// who want bleeding edge changes.
-        pub nightly: Branch,
+        /// Every release branch by name. stable is used by default, but a branch can be
+        /// added for anything such as qa, partner-acme or hotfix-1234.
+        pub branches: BTreeMap<ReleaseBranch, Branch>,
     }
 
     impl Releases {
+        /// returns the release a branch currently points at.
+        pub fn get(&self, branch: &ReleaseBranch) -> Result<&Branch, BootstrapError> {
+            match self.branches.get(branch) {
+                Some(b) => Ok(b),
+                None => Err(BootstrapError::UnknownBranch(branch.to_string())),
+            }
+        }
+
+        /// points a branch at a new release, the branch is created if it does not exist yet.
+        pub fn set(&mut self, branch: ReleaseBranch, release: Branch) {
+            self.branches.insert(branch, release);
+        }
+
         pub fn to_string(&self) -> String {
             toml::to_string(&self).unwrap()
         }
@@ -631,6 +2556,42 @@ pub mod updater {
     pub struct Manifest {
         /// the version of the release
         pub version: String,
+        /// a short headline for the release.
+        #[serde(default)]
+        pub title: String,
+        /// markdown release notes describing what changed.
+        #[serde(default)]
+        pub notes: String,
+        /// when the release was baked as an RFC 3339 timestamp.
+        #[serde(default)]
+        pub published: String,
+        /// marks an older release as a deliberate rollback target.
+        /// clients never install a version older than their own unless this is set.
+        #[serde(default)]
+        pub rollback: bool,
+        /// a critical release is installed before the app starts and cannot be deferred.
+        #[serde(default)]
+        pub critical: bool,
+        /// installs older than this version must update before the app starts.
+        #[serde(default)]
+        pub minimum_supported_version: String,
+        /// how many times an optional update can be deferred, zero does not limit it by count.
+        #[serde(default)]
+        pub max_deferrals: u32,
+        /// how many days an optional update can be deferred for, zero does not limit it by days.
+        /// the update cannot be deferred at all when both limits are zero.
+        #[serde(default)]
+        pub max_deferral_days: u32,
+        /// the system requirements the release needs, checked before it is installed.
+        #[serde(default, skip_serializing_if = "Requirements::is_empty")]
+        pub requirements: Requirements,
+        /// the ordered steps run after the files are applied, each one is undone if a later one fails.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub post_install: Vec<Action>,
+        /// optional parts of the release, each with a package of its own.
+        /// only required components and the ones a user selected are installed.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub components: Vec<Component>,
         /// The update package.
         pub package: Package,
         /// The full installer.
@@ -652,7 +2613,88 @@ pub mod updater {
         /// A hash of the zip package, used to verify it downloaded properly.
         pub hash: String,
         /// A vector of all the files present inside the package.
-        pub files: Vec<String>,
+        pub files: Vec<PackageFile>,
+        /// Delta packages that patch a previous version up to this one.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub deltas: Vec<Delta>,
+        /// The content-defined chunks of the package files, so only missing chunks are downloaded.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub chunks: Option<Chunks>,
+    }
+
+    #[derive(Serialize, Deserialize, Default, Clone)]
+    pub struct Component {
+        /// The name the component is selected by.
+        pub name: String,
+        /// A readable name for the component.
+        #[serde(default)]
+        pub title: String,
+        /// Required components are installed for every user.
+        #[serde(default)]
+        pub required: bool,
+        /// The package containing the files of the component.
+        pub package: Package,
+    }
+
+    #[derive(Serialize, Deserialize, Default, Clone)]
+    pub struct Chunks {
+        /// The URL of the chunk index, which lists the chunks every package file is made from.
+        pub index_url: String,
+        /// A hash of the chunk index, used to verify it downloaded properly.
+        pub index_hash: String,
+        /// The URL of the chunk store the chunks are downloaded from.
+        pub store_url: String,
+    }
+
+    #[derive(Serialize, Deserialize, Default, Clone)]
+    pub struct Delta {
+        /// The installed version this delta can be applied to.
+        pub from: String,
+        /// The URL to the zip containing the patches and new files.
+        pub url: String,
+        /// A hash of the delta zip, used to verify it downloaded properly.
+        pub hash: String,
+        /// The size of the delta zip in bytes.
+        pub size: u64,
+        /// Files inside the delta zip which are bsdiff patches against the installed copy.
+        /// Every other file inside of it is stored whole.
+        pub patches: Vec<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Default, Clone)]
+    #[serde(from = "PackageFileEntry")]
+    pub struct PackageFile {
+        /// The path of the file relative to the install directory.
+        pub path: String,
+        /// The SHA256 of the file, empty if the manifest predates file hashes.
+        pub hash: String,
+        /// The size of the file in bytes.
+        pub size: u64,
+    }
+
+    /// older manifest only list file names, so we accept either a bare
+    /// name or a full table when reading the files of a package.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum PackageFileEntry {
+        Name(String),
+        Detailed {
+            path: String,
+            hash: String,
+            size: u64,
+        },
+    }
+
+    impl From<PackageFileEntry> for PackageFile {
+        fn from(entry: PackageFileEntry) -> Self {
+            match entry {
+                PackageFileEntry::Name(path) => PackageFile {
+                    path,
+                    ..Default::default()
+                },
+                PackageFileEntry::Detailed { path, hash, size } => PackageFile { path, hash, size },
+            }
+        }
     }
 
     pub fn get_base_release_url() -> String {
@@ -668,7 +2710,38 @@ pub mod updater {
 
     /// fetches all the available releases for each branch.
     pub fn get_releases() -> Result<Releases, BootstrapError> {
-        download_toml::<Releases>(&get_release_url())
+        download_signed_toml::<Releases>(&get_release_url())
+    }
+
+    /// downloads a file along with its detached signature and only returns it
+    /// if the signature was made by one of the release keys built into the client.
+    fn download_signed_bytes(url: &str) -> Result<Vec<u8>, BootstrapError> {
+        let data = download_bytes(url)?;
+        let signature = download_bytes(&signature_path(url))?;
+        if !verify_signature(
+            &data,
+            &String::from_utf8_lossy(&signature),
+            &get_release_public_keys(),
+        ) {
+            log::error!("the signature of {} could not be verified.", url);
+            return Err(BootstrapError::MetadataSignatureInvalid(url.to_string()));
+        }
+        Ok(data)
+    }
+
+    /// downloads a signed TOML file and deserializes it, see `download_signed_bytes`.
+    fn download_signed_toml<T>(url: &str) -> Result<T, BootstrapError>
+    where
+        T: DeserializeOwned,
+    {
+        let data = download_signed_bytes(url)?;
+        match toml::from_slice(&data) {
+            Err(e) => Err(BootstrapError::TomlParseFailure(
+                url.to_string(),
+                e.to_string(),
+            )),
+            Ok(model) => Ok(model),
+        }
     }
 
     /// checks if all the files present in a vector exist in a given directory.
@@ -681,6 +2754,35 @@ pub mod updater {
         dir_contains_all_files(&Path::new(input), &files)
     }
 
+    /// checks if the hash and size of every file in a vector matches its copy in a given directory.
+    /// files without a recorded hash are skipped as they come from manifest that only list names.
+    fn validate_file_hashes(input: &PathBuf, target_files: &Vec<PackageFile>) -> bool {
+        let new_exe = match &std::env::current_exe() {
+            Ok(current_exe_path) => format!("{}_new", get_filename(current_exe_path)),
+            Err(_e) => String::default(),
+        };
+        for file in target_files {
+            if file.hash.is_empty() || file.path == new_exe {
+                continue;
+            }
+            let mut file_path = input.clone();
+            file_path.push(&file.path);
+            match sha_256_with_size(&file_path) {
+                Some((hash, size)) => {
+                    if size != file.size || !hash.eq_ignore_ascii_case(&file.hash) {
+                        log::warn!("{} does not match the manifest.", file_path.display());
+                        return false;
+                    }
+                }
+                None => {
+                    log::warn!("unable to hash {}", file_path.display());
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
     /// checks if the downloaded file hash matches that of the one in the manifest.
     pub fn verify(update: ActiveUpdate) -> Result<String, String> {
         let mut download_path = temp_dir();
@@ -727,9 +2829,53 @@ pub mod updater {
         results
     }
 
+    /// the journal of the update being applied.
+    fn get_journal_path() -> PathBuf {
+        let mut journal_path = temp_dir();
+        journal_path.push("Rainway_Apply.toml");
+        journal_path
+    }
+
+    /// moves the journal of an apply on to its next step, see `ApplyJournal::record`.
+    fn record_apply_step(journal: &mut ApplyJournal, step: ApplyStep) -> Result<(), String> {
+        let journal_path = get_journal_path();
+        if let Err(e) = journal.record(&journal_path, step) {
+            let journal_error = format!(
+                "Unable to write the update journal {}: {}",
+                journal_path.display(),
+                e
+            );
+            log::error!("{}", journal_error);
+            return Err(BootstrapError::InstallationFailed(journal_error).to_string());
+        }
+        Ok(())
+    }
+
+    /// restores the install from the backup of an apply and removes its journal.
+    /// the backup is copied rather than moved so a rollback that is interrupted can be run again.
+    /// the install is cleared first so no file or folder that only the update added is left behind.
+    fn roll_back_apply(journal: &ApplyJournal) -> Result<(), std::io::Error> {
+        delete_dir_contents(&journal.install_path, &journal.ignored_files)?;
+        copy_dir(
+            &journal.backup_path,
+            &journal.install_path,
+            &journal.ignored_files,
+        )?;
+        ApplyJournal::remove(&get_journal_path())
+    }
+
     /// applies an update package from a remote manifest.
     /// if any issues are encountered then the process will be rolled back.  
+    /// each step is journaled so an apply that is cut short is recovered by `ActiveUpdate::recover_apply`.
     pub fn apply(update: ActiveUpdate) -> Result<String, String> {
+        if get_journal_path().exists() {
+            let journal_error = format!(
+                "An interrupted update has not been recovered yet, see {}.",
+                get_journal_path().display()
+            );
+            log::error!("{}", journal_error);
+            return Err(BootstrapError::InstallationFailed(journal_error).to_string());
+        }
         let mut download_path = temp_dir();
         download_path.push(update.get_temp_name());
         let mut update_staging_path = temp_dir();
@@ -781,30 +2927,50 @@ pub mod updater {
                 return Err(BootstrapError::InstallationFailed(backup_clean_error).to_string());
             }
         }
+        let mut journal = ApplyJournal {
+            version: update.get_version(),
+            install_path: update.install_info.path.clone(),
+            backup_path: backup_path.clone(),
+            staging_path: update_staging_path.clone(),
+            ignored_files: ignored_files.clone(),
+            step: ApplyStep::Started,
+            post_install: update.manifest.post_install.clone(),
+        };
+        record_apply_step(&mut journal, ApplyStep::Started)?;
+        // until the install is replaced a failure leaves it as it was, so the journal is simply dropped.
+        let journal_path = get_journal_path();
+        let abandon = |error: String| {
+            log::error!("{}", error);
+            if let Err(e) = ApplyJournal::remove(&journal_path) {
+                log::warn!("unable to remove the update journal. {}", e);
+            }
+            Err(BootstrapError::InstallationFailed(error).to_string())
+        };
         //make the backup
         log::info!("attempting to create a backup of the current installation.");
         if let Err(e) = copy_dir(&update.install_info.path, &backup_path, &ignored_files) {
-            let backup_error = format!(
+            return abandon(format!(
                 "Unable to backup installation to {}: {}",
                 backup_path.display(),
                 e
-            );
-            log::error!("{}", backup_error);
-            return Err(BootstrapError::InstallationFailed(backup_error).to_string());
+            ));
         }
         log::info!("backup completed.");
+        record_apply_step(&mut journal, ApplyStep::BackedUp)?;
         log::info!("attempting to extract update package.");
         //stage the update
-        if let Err(e) = unzip(&download_path, &update_staging_path) {
-            let unzip_error = format!(
+        if let Err(e) = stage_update(&update, &download_path, &update_staging_path) {
+            return abandon(format!(
                 "Unable to extract update to {} due to issue: {}",
                 update_staging_path.display(),
                 e
-            );
-            log::error!("{}", unzip_error);
-            return Err(BootstrapError::InstallationFailed(unzip_error).to_string());
+            ));
         }
         log::info!("update extracted to {}", &update_staging_path.display());
+        if let Err(e) = stage_components(&update, &update_staging_path) {
+            return abandon(format!("Unable to stage the update components: {}", e));
+        }
+        record_apply_step(&mut journal, ApplyStep::Replacing)?;
 
         //delete the install without deleting the root folder.
         log::info!(
@@ -821,7 +2987,7 @@ pub mod updater {
             );
             log::error!("{}", delete_error);
             log::warn!("attempting to roll back.");
-            if let Err(e) = move_dir(&backup_path, &update.install_info.path, &ignored_files) {
+            if let Err(e) = roll_back_apply(&journal) {
                 log::error!("failed to rollback update process. {}", e);
             }
             return Err(BootstrapError::InstallationFailed(delete_error).to_string());
@@ -839,13 +3005,17 @@ pub mod updater {
                 e
             );
             log::error!("{}", update_error_message);
-            if let Ok(_e) = move_dir(&backup_path, &update.install_info.path, &ignored_files) {
+            if let Ok(_e) = roll_back_apply(&journal) {
                 log::warn!("rolled back update.");
             } else {
                 log::error!("failed to rollback update.")
             }
             return Err(BootstrapError::InstallationFailed(update_error_message).to_string());
         }
+        // the files are in place, a journal stuck on replacing only means the next start rolls back the update.
+        if let Err(e) = record_apply_step(&mut journal, ApplyStep::Replaced) {
+            log::warn!("{}", e);
+        }
 
         if let Ok(_o) = unblock_path(&update.install_info.path) {
             log::info!("unblocked the install path.");
@@ -865,6 +3035,19 @@ pub mod updater {
             log::info!("unable to grant full permissions to the install path.");
         }
 
+        if let Err(e) = run_post_install_actions(&journal) {
+            log::warn!("attempting to roll back.");
+            if let Err(e) = roll_back_apply(&journal) {
+                log::error!("failed to rollback update process. {}", e);
+            } else {
+                log::warn!("rolled back update.");
+            }
+            return Err(BootstrapError::InstallationFailed(e).to_string());
+        }
+        if let Err(e) = record_apply_step(&mut journal, ApplyStep::Applied) {
+            log::warn!("{}", e);
+        }
+
         log::info!("update went off without a hitch.");
 
      
@@ -872,14 +3055,255 @@ pub mod updater {
 
         update.post_update(&update.get_version());
 
+        if let Err(e) = ApplyJournal::remove(&journal_path) {
+            log::error!("unable to remove the update journal. {}", e);
+        }
+
         Ok("Rainway updated!".to_string())
         //dir_contains_all_files(package_files, &install_path);
     }
 
+    /// runs the post-install actions of the journaled update against the applied install path.
+    /// actions which completed are undone if one of them fails.
+    fn run_post_install_actions(journal: &ApplyJournal) -> Result<(), String> {
+        if journal.post_install.is_empty() {
+            return Ok(());
+        }
+        let mut trash_path = temp_dir();
+        trash_path.push(format!("Rainway_Trash_{}", &journal.version));
+        let context = ActionContext {
+            install_path: journal.install_path.clone(),
+            version: journal.version.clone(),
+            trash_path,
+        };
+        run_actions(&journal.post_install, &context, &WindowsActionHost)
+    }
+
+    /// extracts the downloaded package into the staging path.
+    /// when a delta was downloaded the new files are rebuilt from the installed copies,
+    /// falling back to downloading the full package if that is not possible.
+    fn stage_update(
+        update: &ActiveUpdate,
+        download_path: &PathBuf,
+        staging_path: &PathBuf,
+    ) -> Result<(), String> {
+        let staged = match (update.get_delta(), update.get_chunks()) {
+            (Some(delta), _) => stage_delta(&update, &delta, &download_path, &staging_path)
+                .map_err(|e| format!("unable to rebuild the update from the delta of {}: {}", delta.from, e)),
+            (None, Some(chunks)) => stage_chunks(&update, &chunks, &download_path, &staging_path)
+                .map_err(|e| format!("unable to assemble the update from its chunks: {}", e)),
+            (None, None) => return unzip(&download_path, &staging_path).map_err(|e| e.to_string()),
+        };
+        match staged {
+            Ok(_) => return Ok(()),
+            Err(e) => log::warn!("{}. falling back to the full package.", e),
+        }
+        let mut full_update = update.clone();
+        full_update.force_full_package = true;
+        full_update.set_temp_file();
+        download_with_callback(full_update.clone(), |_total_bytes, _downloaded_bytes| {})?;
+        verify(full_update.clone())?;
+        let mut full_package_path = temp_dir();
+        full_package_path.push(full_update.get_temp_name());
+        if staging_path.exists() {
+            remove_dir_all(&staging_path).map_err(|e| e.to_string())?;
+        }
+        unzip(&full_package_path, &staging_path).map_err(|e| e.to_string())
+    }
+
+    /// rebuilds every file of the new release inside of the staging path from the installed files
+    /// and the contents of a delta package. each rebuilt file is checked against the manifest.
+    fn stage_delta(
+        update: &ActiveUpdate,
+        delta: &Delta,
+        download_path: &PathBuf,
+        staging_path: &PathBuf,
+    ) -> Result<(), BootstrapError> {
+        let mut delta_path = temp_dir();
+        delta_path.push(format!("Rainway_Delta_{}", &update.get_version()));
+        if delta_path.exists() {
+            remove_dir_all(&delta_path)?;
+        }
+        unzip(&download_path, &delta_path)?;
+        log::info!("delta extracted to {}", &delta_path.display());
+
+        for file in &update.manifest.package.files {
+            let mut installed_file = update.install_info.path.clone();
+            installed_file.push(&file.path);
+            let mut delta_file = delta_path.clone();
+            delta_file.push(&file.path);
+            let mut staged_file = staging_path.clone();
+            staged_file.push(&file.path);
+            if let Some(parent) = staged_file.parent() {
+                create_dir_all(&parent)?;
+            }
+            if delta.patches.contains(&file.path) {
+                patch_file(&installed_file, &delta_file, &staged_file)?;
+            } else if delta_file.exists() {
+                rename(&delta_file, &staged_file)?;
+            } else {
+                copy(&installed_file, &staged_file)?;
+            }
+            let matches = sha_256(&staged_file)
+                .map_or(false, |hash| hash.eq_ignore_ascii_case(&file.hash));
+            if !file.hash.is_empty() && !matches {
+                return Err(BootstrapError::InstallationFailed(format!(
+                    "{} does not match the manifest after patching.",
+                    file.path
+                )));
+            }
+        }
+        remove_dir_all(&delta_path)?;
+        Ok(())
+    }
+
+    /// downloads the package of every installed component and extracts it into the staging path.
+    /// a package left over from an earlier attempt is reused if its hash still matches.
+    fn stage_components(update: &ActiveUpdate, staging_path: &PathBuf) -> Result<(), String> {
+        for component in update.get_components() {
+            let mut package_path = temp_dir();
+            package_path.push(format!("{}.zip", component.package.hash));
+            let downloaded = sha_256(&package_path)
+                .map_or(false, |hash| hash.eq_ignore_ascii_case(&component.package.hash));
+            if !downloaded {
+                log::info!("downloading the {} component.", component.name);
+                download_package(&component.package.url, &package_path)?;
+                let valid = sha_256(&package_path)
+                    .map_or(false, |hash| hash.eq_ignore_ascii_case(&component.package.hash));
+                if !valid {
+                    return Err(BootstrapError::SignatureMismatch.to_string());
+                }
+            }
+            unzip(&package_path, &staging_path).map_err(|e| e.to_string())?;
+            log::info!("staged the {} component.", component.name);
+        }
+        Ok(())
+    }
+
+    /// downloads a package to a path without reporting progress.
+    fn download_package(url: &str, path: &PathBuf) -> Result<(), String> {
+        use tokio::runtime::Runtime;
+        let mut runtime = match Runtime::new() {
+            Ok(rt) => rt,
+            Err(e) => return Err(format!("{}", BootstrapError::from(e))),
+        };
+        let results = runtime.block_on(async {
+            download_file(|_total_bytes, _downloaded_bytes| {}, url, path)
+                .await
+                .map_err(|err| format!("{}", err))
+        });
+        drop(runtime);
+        if results.is_ok() {
+            unblock_file(path.clone());
+        }
+        results.map(|_| ())
+    }
+
+    /// returns the folder chunks downloaded for an update are kept in until it is staged,
+    /// so an interrupted update does not download them again.
+    fn get_chunk_cache() -> PathBuf {
+        let mut cache = temp_dir();
+        cache.push("Rainway_Chunks");
+        cache
+    }
+
+    /// assembles every file of the new release inside of the staging path from its chunks.
+    /// chunks are read from the installed files first, then the chunk cache,
+    /// and only the ones found in neither are downloaded from the chunk store.
+    fn stage_chunks(
+        update: &ActiveUpdate,
+        chunks: &Chunks,
+        index_path: &PathBuf,
+        staging_path: &PathBuf,
+    ) -> Result<(), BootstrapError> {
+        let index: ChunkIndex = match toml::from_slice(&read(index_path)?) {
+            Ok(i) => i,
+            Err(e) => {
+                return Err(BootstrapError::TomlParseFailure(
+                    chunks.index_url.clone(),
+                    e.to_string(),
+                ))
+            }
+        };
+        let package_files = &update.manifest.package.files;
+        let matches_manifest = index.files.len() == package_files.len()
+            && index.files.iter().all(|file| {
+                package_files
+                    .iter()
+                    .any(|f| f.path == file.path && f.hash.eq_ignore_ascii_case(&file.hash))
+            });
+        if !matches_manifest {
+            return Err(BootstrapError::InstallationFailed(
+                "the chunk index does not match the manifest.".to_string(),
+            ));
+        }
+
+        let installed_files = get_dir_files(&update.install_info.path).unwrap_or_default();
+        let local = scan_local_chunks(&update.install_info.path, &installed_files);
+        log::info!(
+            "found {} reusable chunks in {}",
+            local.len(),
+            update.install_info.path.display()
+        );
+        let cache = get_chunk_cache();
+        let mut downloaded = 0usize;
+        for file in &index.files {
+            let mut staged_file = staging_path.clone();
+            staged_file.push(&file.path);
+            assemble_file(file, &staged_file, |chunk| {
+                fetch_chunk(chunk, &local, &cache, &chunks.store_url, &mut downloaded)
+            })?;
+        }
+        log::info!("downloaded {} chunks from {}", downloaded, chunks.store_url);
+        if let Err(e) = remove_dir_all(&cache) {
+            log::warn!("unable to clean the chunk cache {}: {}", cache.display(), e);
+        }
+        Ok(())
+    }
+
+    /// returns the contents of a chunk from the installed files, the chunk cache or the chunk store.
+    /// a local copy which does not match its hash is skipped in favor of the next place.
+    fn fetch_chunk(
+        chunk: &ChunkRef,
+        local: &HashMap<String, LocalChunk>,
+        cache: &Path,
+        store_url: &str,
+        downloaded: &mut usize,
+    ) -> Result<Vec<u8>, std::io::Error> {
+        let is_valid = |data: &Vec<u8>| chunk_hash(data).eq_ignore_ascii_case(&chunk.hash);
+        if let Some(local_chunk) = local.get(&chunk.hash) {
+            if let Ok(data) = read_local_chunk(local_chunk) {
+                if is_valid(&data) {
+                    return Ok(data);
+                }
+            }
+        }
+        let cached = chunk_path(cache, &chunk.hash);
+        if let Ok(data) = read(&cached) {
+            if is_valid(&data) {
+                return Ok(data);
+            }
+        }
+        let data = match download_bytes(&chunk_url(store_url, &chunk.hash)) {
+            Ok(d) => d,
+            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, e.to_string())),
+        };
+        if !is_valid(&data) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("the downloaded chunk {} does not match its hash.", chunk.hash),
+            ));
+        }
+        write_chunk(&cached, &data)?;
+        *downloaded += 1;
+        Ok(data)
+    }
+
     /// Runs the full installer and waits for it to exit.
     /// The bootstrapper will not launch Rainway after this.
     /// The installer should be configured to launch post-install.
     pub fn install(update: &mut ActiveUpdate) -> Result<String, String> {
+        #[cfg(windows)]
         use std::os::windows::process::CommandExt;
         use std::process::Command;
         let mut download_path = temp_dir();
@@ -892,9 +3316,12 @@ pub mod updater {
         update.post_install();
         ActiveUpdate::store_event(RainwayAppState::Installed);
 
-        let results = Command::new(download_path)
-            .args(&["/qn"])
-            .creation_flags(0x08000000)
+        let mut command = Command::new(download_path);
+        command.args(&["/qn"]);
+        // CREATE_NO_WINDOW
+        #[cfg(windows)]
+        command.creation_flags(0x08000000);
+        let results = command
             .output()
             .map_err(|err| BootstrapError::InstallationFailed(err.to_string()).to_string())
             .map(|output| {
@@ -973,6 +3400,10 @@ pub mod updater {
     pub struct RainwayApp {
         pub setup_id: String,
         pub install_state: RainwayAppState,
+        /// the update the user last put off.
+        pub deferral: Deferral,
+        /// the optional components the user selected.
+        pub components: Vec<String>,
     }
 
     pub fn delete_rainway_key() {
@@ -994,12 +3425,54 @@ pub mod updater {
                 key.get_value::<u32, &str>("SetupState")
                     .unwrap_or(RainwayAppState::Nothing as u32),
             ),
+            deferral: Deferral {
+                version: key.get_value("DeferredVersion").unwrap_or_default(),
+                count: key.get_value("DeferralCount").unwrap_or_default(),
+                since: key.get_value("DeferredSince").unwrap_or_default(),
+            },
+            components: key
+                .get_value::<String, &str>("Components")
+                .unwrap_or_default()
+                .split(',')
+                .filter(|c| !c.trim().is_empty())
+                .map(|c| c.trim().to_string())
+                .collect(),
         };
 
         Ok(app)
     }
 
-    fn set_rainway_key_value<T: winreg::types::ToRegValue>(
+    /// returns the update the user last put off, nothing has been deferred if it cannot be read.
+    fn get_deferral() -> Deferral {
+        match get_rainway_key() {
+            Ok(app) => app.deferral,
+            Err(_) => Deferral::default(),
+        }
+    }
+
+    /// returns the optional components the user selected, none have been if it cannot be read.
+    fn get_selected_components() -> Vec<String> {
+        match get_rainway_key() {
+            Ok(app) => app.components,
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn store_deferral(deferral: &Deferral) -> Result<(), BootstrapError> {
+        set_rainway_key_value("DeferredVersion", &deferral.version)?;
+        set_rainway_key_value("DeferralCount", &deferral.count)?;
+        set_rainway_key_value("DeferredSince", &deferral.since)
+    }
+
+    /// the current time in seconds since the unix epoch.
+    fn unix_now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+
+    fn set_rainway_key_value<T: ToRegValue>(
         subkey: &str,
         value: &T,
     ) -> Result<(), BootstrapError> {
@@ -1033,4 +3506,56 @@ pub mod updater {
             x => log::info!("Failed to post {:?}", x),
         }
     }
+
+    #[cfg(test)]
+    mod test {
+        use super::{ActiveUpdate, Component, Package, PackageFile};
+        use std::env::temp_dir;
+        use std::fs::{create_dir_all, remove_dir_all, write};
+
+        fn package(files: &[&str]) -> Package {
+            Package {
+                files: files
+                    .iter()
+                    .map(|f| PackageFile {
+                        path: f.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn deselected_components() {
+            let mut update = ActiveUpdate::default();
+            update.install_info.path =
+                temp_dir().join(format!("Quiche_Components_Test_{}", std::process::id()));
+            let _ = remove_dir_all(&update.install_info.path);
+            create_dir_all(update.install_info.path.join("codecs")).unwrap();
+            for file in &["Rainway.exe", "codecs/hevc.dll"] {
+                write(update.install_info.path.join(file), b"").unwrap();
+            }
+            update.manifest.package = package(&["Rainway.exe"]);
+            update.manifest.components = vec![
+                Component {
+                    name: "codecs".to_string(),
+                    package: package(&["codecs/hevc.dll"]),
+                    ..Default::default()
+                },
+                Component {
+                    name: "languages".to_string(),
+                    package: package(&["lang/fr.dll"]),
+                    ..Default::default()
+                },
+            ];
+
+            assert!(update.get_deselected_files(&["codecs".to_string()]).is_empty());
+            // an optional component that is left out stays installed until the release is applied again.
+            assert_eq!(update.get_deselected_files(&[]), vec!["codecs/hevc.dll".to_string()]);
+            update.manifest.components[0].required = true;
+            assert!(update.get_deselected_files(&[]).is_empty());
+            remove_dir_all(&update.install_info.path).unwrap();
+        }
+    }
 }
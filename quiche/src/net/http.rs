@@ -1,5 +1,6 @@
 use crate::etc::constants::BootstrapError;
 use hyper::body::HttpBody as _;
+use hyper::client::HttpConnector;
 use hyper::Client;
 use hyper::{header::HeaderValue, Body, Request};
 use hyper_tls::HttpsConnector;
@@ -7,20 +8,58 @@ use serde::de::DeserializeOwned;
 use std::path::PathBuf;
 use tokio::io::AsyncWriteExt;
 
+/// Builds the client every request is made with, only https URLs are allowed.
+/// Debug builds also trust the PEM certificate found at QUICHE_TRUST_CERT,
+/// such as the self-signed one `cli serve` generates for local testing.
+fn https_client() -> Result<Client<HttpsConnector<HttpConnector>>, BootstrapError> {
+    let mut tls = native_tls::TlsConnector::builder();
+    if cfg!(debug_assertions) {
+        if let Ok(cert_path) = std::env::var("QUICHE_TRUST_CERT") {
+            let pem = std::fs::read(&cert_path)?;
+            let cert = match native_tls::Certificate::from_pem(&pem) {
+                Ok(c) => c,
+                Err(e) => return Err(BootstrapError::HttpFailed(e.to_string())),
+            };
+            log::warn!("trusting the certificate at {}.", cert_path);
+            tls.add_root_certificate(cert);
+        }
+    }
+    let tls = match tls.build() {
+        Ok(t) => t,
+        Err(e) => return Err(BootstrapError::HttpFailed(e.to_string())),
+    };
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    let mut https = HttpsConnector::from((http, tokio_tls::TlsConnector::from(tls)));
+    https.https_only(true);
+    Ok(Client::builder().build::<_, hyper::Body>(https))
+}
+
 /// Downloads a remote TOML string and deseralizes it into a provided <T> generic.
 pub fn download_toml<T>(url: &str) -> Result<T, BootstrapError>
 where
     T: DeserializeOwned,
 {
+    let buffer = download_bytes(url)?;
+    match toml::from_slice(&buffer) {
+        Err(e) => Err(BootstrapError::TomlParseFailure(
+            url.to_string(),
+            e.to_string(),
+        )),
+        Ok(model) => Ok(model),
+    }
+}
+
+/// Downloads the full body of a remote file into memory.
+/// This should only be used for small files such as manifest and signatures.
+pub fn download_bytes(url: &str) -> Result<Vec<u8>, BootstrapError> {
     use tokio::runtime::Runtime;
     let mut runtime = match Runtime::new() {
         Ok(rt) => rt,
         Err(e) => return Err(BootstrapError::from(e)),
     };
     let results = runtime.block_on(async {
-        let mut https = HttpsConnector::new();
-        https.https_only(true);
-        let client = Client::builder().build::<_, hyper::Body>(https);
+        let client = https_client()?;
 
         let request = match Request::get(url).body(Body::empty()) {
             Ok(b) => b,
@@ -43,15 +82,7 @@ where
         while let Some(chunk) = response.body_mut().data().await {
             buffer.append(&mut chunk?.to_vec());
         }
-        match toml::from_slice(&buffer) {
-            Err(e) => {
-                return Err(BootstrapError::TomlParseFailure(
-                    url.to_string(),
-                    e.to_string(),
-                ))
-            }
-            Ok(model) => return Ok(model),
-        };
+        Ok(buffer)
     });
     drop(runtime);
     results
@@ -68,9 +99,7 @@ pub async fn download_file<F>(
 where
     F: Fn(u64, u64) + Send + Sync + 'static,
 {
-    let mut https = HttpsConnector::new();
-    https.https_only(true);
-    let client = Client::builder().build::<_, hyper::Body>(https);
+    let client = https_client()?;
 
     let head_request = match Request::head(url).body(Body::empty()) {
         Ok(b) => b,
@@ -153,9 +182,7 @@ pub fn post(
     };
 
     let results = runtime.block_on(async {
-        let mut https = HttpsConnector::new();
-        https.https_only(true);
-        let client = Client::builder().build::<_, hyper::Body>(https);
+        let client = https_client()?;
 
         let mut builder = Request::builder().method("POST").uri(url);
         if let Some(headers) = builder.headers_mut() {
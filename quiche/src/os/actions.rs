@@ -0,0 +1,607 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{create_dir_all, remove_dir_all, remove_file, rename};
+use std::path::{Component, Path, PathBuf};
+
+/// the registry hives a post-install action can write to.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistryHive {
+    CurrentUser,
+    LocalMachine,
+}
+
+impl Default for RegistryHive {
+    fn default() -> Self {
+        RegistryHive::CurrentUser
+    }
+}
+
+/// a step a release runs after its files have been applied.
+/// paths are relative to the install path unless they are absolute, and every string
+/// can use the `{install_path}` and `{version}` placeholders.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// runs a bundled executable and waits for it to exit successfully.
+    /// if rollback_args are set the executable is run again with them on rollback.
+    /// it is run a second time if the update was interrupted while its actions ran,
+    /// so it must be safe to repeat.
+    Run {
+        path: String,
+        #[serde(default)]
+        args: Vec<String>,
+        rollback_args: Option<Vec<String>>,
+    },
+    /// registers a Windows service, nothing is done if it already exists.
+    /// the service is removed again on rollback.
+    RegisterService {
+        name: String,
+        display_name: String,
+        path: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// starts a Windows service, restarting it if it is already running.
+    /// the service is stopped on rollback.
+    StartService { name: String },
+    /// writes a string registry value, the previous value is restored on rollback.
+    SetRegistryValue {
+        #[serde(default)]
+        hive: RegistryHive,
+        key: String,
+        name: String,
+        value: String,
+    },
+    /// creates a shortcut to a target, the shortcut is removed on rollback.
+    CreateShortcut {
+        target: String,
+        shortcut: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// deletes a file or folder inside of the install path that a release no longer uses.
+    /// it is set aside until every action succeeds so it can be put back on rollback.
+    DeletePath { path: String },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Run { path, args, .. } => write!(f, "run {} {}", path, args.join(" ")),
+            Action::RegisterService { name, .. } => write!(f, "register service {}", name),
+            Action::StartService { name } => write!(f, "start service {}", name),
+            Action::SetRegistryValue { key, name, .. } => {
+                write!(f, "set registry value {}\\{}", key, name)
+            }
+            Action::CreateShortcut { shortcut, .. } => write!(f, "create shortcut {}", shortcut),
+            Action::DeletePath { path } => write!(f, "delete {}", path),
+        }
+    }
+}
+
+/// the operating system side of post-install actions.
+/// files are handled by the runner itself so only services, the registry, processes and shortcuts are needed.
+pub trait ActionHost {
+    /// runs an executable and returns an error if it fails to start or exits unsuccessfully.
+    fn run(&self, path: &Path, args: &[String]) -> Result<(), String>;
+    fn service_exists(&self, name: &str) -> bool;
+    fn register_service(
+        &self,
+        name: &str,
+        display_name: &str,
+        path: &Path,
+        args: &[String],
+    ) -> Result<(), String>;
+    fn remove_service(&self, name: &str) -> Result<(), String>;
+    fn start_service(&self, name: &str) -> Result<(), String>;
+    fn stop_service(&self, name: &str) -> Result<(), String>;
+    /// returns None if the value does not exist.
+    fn get_registry_value(&self, hive: RegistryHive, key: &str, name: &str) -> Option<String>;
+    fn set_registry_value(
+        &self,
+        hive: RegistryHive,
+        key: &str,
+        name: &str,
+        value: &str,
+    ) -> Result<(), String>;
+    fn delete_registry_value(
+        &self,
+        hive: RegistryHive,
+        key: &str,
+        name: &str,
+    ) -> Result<(), String>;
+    fn create_shortcut(
+        &self,
+        target: &Path,
+        shortcut: &Path,
+        args: &[String],
+    ) -> Result<(), String>;
+}
+
+/// where actions run and what they are substituted with.
+pub struct ActionContext {
+    /// the folder the release was applied to.
+    pub install_path: PathBuf,
+    /// the version of the release being applied.
+    pub version: String,
+    /// a scratch folder deleted paths are moved to until every action succeeds.
+    pub trash_path: PathBuf,
+}
+
+impl ActionContext {
+    /// replaces the placeholders inside of a string.
+    fn expand(&self, value: &str) -> String {
+        value
+            .replace("{install_path}", &self.install_path.to_string_lossy())
+            .replace("{version}", &self.version)
+    }
+
+    fn expand_all(&self, values: &[String]) -> Vec<String> {
+        values.iter().map(|v| self.expand(v)).collect()
+    }
+
+    /// expands a path and resolves it against the install path if it is relative.
+    fn resolve(&self, path: &str) -> PathBuf {
+        let expanded = PathBuf::from(self.expand(path));
+        if expanded.is_absolute() {
+            return expanded;
+        }
+        self.install_path.join(expanded)
+    }
+}
+
+/// how to undo an action which completed.
+enum Undo {
+    Nothing,
+    Run(PathBuf, Vec<String>),
+    RemoveService(String),
+    StopService(String),
+    RestoreRegistryValue(RegistryHive, String, String, Option<String>),
+    RemoveShortcut(PathBuf, Option<PathBuf>),
+    RestorePath(PathBuf, PathBuf),
+}
+
+/// checks that a list of actions is well formed so a mistake is caught while baking.
+pub fn validate_actions(actions: &[Action]) -> Result<(), String> {
+    for action in actions {
+        let valid = match action {
+            Action::Run { path, .. } => !path.trim().is_empty(),
+            Action::RegisterService {
+                name,
+                display_name,
+                path,
+                ..
+            } => {
+                !name.trim().is_empty()
+                    && !display_name.trim().is_empty()
+                    && !path.trim().is_empty()
+            }
+            Action::StartService { name } => !name.trim().is_empty(),
+            Action::SetRegistryValue { key, .. } => !key.trim().is_empty(),
+            Action::CreateShortcut {
+                target, shortcut, ..
+            } => !target.trim().is_empty() && !shortcut.trim().is_empty(),
+            Action::DeletePath { path } => is_inside_install_path(path),
+        };
+        if !valid {
+            return Err(format!(
+                "\"{}\" is not a valid post-install action.",
+                action
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// only relative paths which stay inside of the install path can be deleted.
+fn is_inside_install_path(path: &str) -> bool {
+    let path = path.trim().replace('\\', "/");
+    if path.is_empty() || path.contains(':') {
+        return false;
+    }
+    Path::new(&path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// runs every action in order. if one fails the actions which already completed are undone
+/// in reverse order and the error of the failed action is returned.
+pub fn run_actions(
+    actions: &[Action],
+    context: &ActionContext,
+    host: &dyn ActionHost,
+) -> Result<(), String> {
+    let mut completed: Vec<Undo> = Vec::new();
+    for (index, action) in actions.iter().enumerate() {
+        log::info!("running post-install action {}: {}", index + 1, action);
+        match run_action(action, index, context, host) {
+            Ok(undo) => completed.push(undo),
+            Err(e) => {
+                let error = format!("post-install action \"{}\" failed: {}", action, e);
+                log::error!("{}", error);
+                log::warn!("rolling back {} completed action(s).", completed.len());
+                for undo in completed.into_iter().rev() {
+                    if let Err(e) = undo_action(undo, host) {
+                        log::error!("failed to roll back a post-install action: {}", e);
+                    }
+                }
+                let _ = remove_dir_all(&context.trash_path);
+                return Err(error);
+            }
+        }
+    }
+    if context.trash_path.exists() {
+        if let Err(e) = remove_dir_all(&context.trash_path) {
+            log::warn!("unable to clean {}: {}", context.trash_path.display(), e);
+        }
+    }
+    Ok(())
+}
+
+fn run_action(
+    action: &Action,
+    index: usize,
+    context: &ActionContext,
+    host: &dyn ActionHost,
+) -> Result<Undo, String> {
+    match action {
+        Action::Run {
+            path,
+            args,
+            rollback_args,
+        } => {
+            let path = context.resolve(path);
+            host.run(&path, &context.expand_all(args))?;
+            Ok(match rollback_args {
+                Some(rollback_args) => Undo::Run(path, context.expand_all(rollback_args)),
+                None => Undo::Nothing,
+            })
+        }
+        Action::RegisterService {
+            name,
+            display_name,
+            path,
+            args,
+        } => {
+            if host.service_exists(name) {
+                log::info!("{} is already registered.", name);
+                return Ok(Undo::Nothing);
+            }
+            host.register_service(
+                name,
+                &context.expand(display_name),
+                &context.resolve(path),
+                &context.expand_all(args),
+            )?;
+            Ok(Undo::RemoveService(name.to_string()))
+        }
+        Action::StartService { name } => {
+            host.start_service(name)?;
+            Ok(Undo::StopService(name.to_string()))
+        }
+        Action::SetRegistryValue {
+            hive,
+            key,
+            name,
+            value,
+        } => {
+            let key = context.expand(key);
+            let previous = host.get_registry_value(*hive, &key, name);
+            host.set_registry_value(*hive, &key, name, &context.expand(value))?;
+            Ok(Undo::RestoreRegistryValue(
+                *hive,
+                key,
+                name.to_string(),
+                previous,
+            ))
+        }
+        Action::CreateShortcut {
+            target,
+            shortcut,
+            args,
+        } => {
+            let shortcut = context.resolve(shortcut);
+            let backup = match shortcut.exists() {
+                true => Some(set_aside(&shortcut, index, context)?),
+                false => None,
+            };
+            if let Err(e) = host.create_shortcut(
+                &context.resolve(target),
+                &shortcut,
+                &context.expand_all(args),
+            ) {
+                if let Some(backup) = backup {
+                    let _ = rename(&backup, &shortcut);
+                }
+                return Err(e);
+            }
+            Ok(Undo::RemoveShortcut(shortcut, backup))
+        }
+        Action::DeletePath { path } => {
+            if !is_inside_install_path(path) {
+                return Err(format!("{} is outside of the install path.", path));
+            }
+            let path = context.resolve(path);
+            if !path.exists() {
+                return Ok(Undo::Nothing);
+            }
+            let trashed = set_aside(&path, index, context)?;
+            Ok(Undo::RestorePath(trashed, path))
+        }
+    }
+}
+
+/// moves a path into the trash folder so it can be restored later.
+fn set_aside(path: &Path, index: usize, context: &ActionContext) -> Result<PathBuf, String> {
+    let mut trashed = context.trash_path.clone();
+    trashed.push(index.to_string());
+    create_dir_all(&trashed).map_err(|e| e.to_string())?;
+    trashed.push(path.file_name().unwrap_or_default());
+    rename(path, &trashed).map_err(|e| format!("unable to move {}: {}", path.display(), e))?;
+    Ok(trashed)
+}
+
+fn undo_action(undo: Undo, host: &dyn ActionHost) -> Result<(), String> {
+    match undo {
+        Undo::Nothing => Ok(()),
+        Undo::Run(path, args) => host.run(&path, &args),
+        Undo::RemoveService(name) => host.remove_service(&name),
+        Undo::StopService(name) => host.stop_service(&name),
+        Undo::RestoreRegistryValue(hive, key, name, previous) => match previous {
+            Some(value) => host.set_registry_value(hive, &key, &name, &value),
+            None => host.delete_registry_value(hive, &key, &name),
+        },
+        Undo::RemoveShortcut(shortcut, backup) => {
+            if shortcut.exists() {
+                remove_file(&shortcut).map_err(|e| e.to_string())?;
+            }
+            match backup {
+                Some(backup) => rename(&backup, &shortcut).map_err(|e| e.to_string()),
+                None => Ok(()),
+            }
+        }
+        Undo::RestorePath(trashed, path) => rename(&trashed, &path).map_err(|e| e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{run_actions, validate_actions, Action, ActionContext, ActionHost, RegistryHive};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::path::{Path, PathBuf};
+
+    #[derive(Default)]
+    struct FakeHost {
+        registry: RefCell<HashMap<String, String>>,
+        services: RefCell<Vec<String>>,
+        log: RefCell<Vec<String>>,
+    }
+
+    impl ActionHost for FakeHost {
+        fn run(&self, path: &Path, args: &[String]) -> Result<(), String> {
+            if path.ends_with("fail.exe") {
+                return Err("exited with 1".to_string());
+            }
+            self.log
+                .borrow_mut()
+                .push(format!("run {}", args.join(" ")));
+            Ok(())
+        }
+        fn service_exists(&self, name: &str) -> bool {
+            self.services.borrow().iter().any(|s| s == name)
+        }
+        fn register_service(
+            &self,
+            name: &str,
+            _: &str,
+            _: &Path,
+            _: &[String],
+        ) -> Result<(), String> {
+            self.services.borrow_mut().push(name.to_string());
+            Ok(())
+        }
+        fn remove_service(&self, name: &str) -> Result<(), String> {
+            self.services.borrow_mut().retain(|s| s != name);
+            Ok(())
+        }
+        fn start_service(&self, name: &str) -> Result<(), String> {
+            self.log.borrow_mut().push(format!("start {}", name));
+            Ok(())
+        }
+        fn stop_service(&self, name: &str) -> Result<(), String> {
+            self.log.borrow_mut().push(format!("stop {}", name));
+            Ok(())
+        }
+        fn get_registry_value(&self, _: RegistryHive, key: &str, name: &str) -> Option<String> {
+            self.registry
+                .borrow()
+                .get(&format!("{}\\{}", key, name))
+                .cloned()
+        }
+        fn set_registry_value(
+            &self,
+            _: RegistryHive,
+            key: &str,
+            name: &str,
+            value: &str,
+        ) -> Result<(), String> {
+            self.registry
+                .borrow_mut()
+                .insert(format!("{}\\{}", key, name), value.to_string());
+            Ok(())
+        }
+        fn delete_registry_value(
+            &self,
+            _: RegistryHive,
+            key: &str,
+            name: &str,
+        ) -> Result<(), String> {
+            self.registry
+                .borrow_mut()
+                .remove(&format!("{}\\{}", key, name));
+            Ok(())
+        }
+        fn create_shortcut(&self, _: &Path, shortcut: &Path, _: &[String]) -> Result<(), String> {
+            write(shortcut, b"lnk").map_err(|e| e.to_string())
+        }
+    }
+
+    fn context(name: &str) -> ActionContext {
+        let mut root = temp_dir();
+        root.push(format!("quiche_actions_{}_{}", name, std::process::id()));
+        let _ = remove_dir_all(&root);
+        let install_path: PathBuf = root.join("install");
+        create_dir_all(install_path.join("plugins")).unwrap();
+        write(install_path.join("plugins").join("old.dll"), b"old").unwrap();
+        ActionContext {
+            install_path,
+            version: "2.0.0".to_string(),
+            trash_path: root.join("trash"),
+        }
+    }
+
+    fn actions(last: &str) -> Vec<Action> {
+        vec![
+            Action::SetRegistryValue {
+                hive: RegistryHive::LocalMachine,
+                key: "SOFTWARE\\Rainway".to_string(),
+                name: "Version".to_string(),
+                value: "{version}".to_string(),
+            },
+            Action::RegisterService {
+                name: "Rainway".to_string(),
+                display_name: "Rainway Service".to_string(),
+                path: "Rainway.Service.exe".to_string(),
+                args: vec![],
+            },
+            Action::StartService {
+                name: "Rainway".to_string(),
+            },
+            Action::DeletePath {
+                path: "plugins/old.dll".to_string(),
+            },
+            Action::CreateShortcut {
+                target: "Rainway.exe".to_string(),
+                shortcut: "Rainway.lnk".to_string(),
+                args: vec![],
+            },
+            Action::Run {
+                path: last.to_string(),
+                args: vec!["--install".to_string()],
+                rollback_args: Some(vec!["--uninstall".to_string()]),
+            },
+        ]
+    }
+
+    #[test]
+    fn actions_complete() {
+        let context = context("complete");
+        let host = FakeHost::default();
+        host.registry.borrow_mut().insert(
+            "SOFTWARE\\Rainway\\Version".to_string(),
+            "1.0.0".to_string(),
+        );
+        assert_eq!(
+            run_actions(&actions("setup.exe"), &context, &host).is_ok(),
+            true
+        );
+        assert_eq!(
+            host.get_registry_value(RegistryHive::LocalMachine, "SOFTWARE\\Rainway", "Version"),
+            Some("2.0.0".to_string())
+        );
+        assert_eq!(host.service_exists("Rainway"), true);
+        assert_eq!(
+            context
+                .install_path
+                .join("plugins")
+                .join("old.dll")
+                .exists(),
+            false
+        );
+        assert_eq!(context.install_path.join("Rainway.lnk").exists(), true);
+        assert_eq!(context.trash_path.exists(), false);
+    }
+
+    #[test]
+    fn actions_roll_back() {
+        let context = context("roll_back");
+        let host = FakeHost::default();
+        host.registry.borrow_mut().insert(
+            "SOFTWARE\\Rainway\\Version".to_string(),
+            "1.0.0".to_string(),
+        );
+        assert_eq!(
+            run_actions(&actions("fail.exe"), &context, &host).is_err(),
+            true
+        );
+        assert_eq!(
+            host.get_registry_value(RegistryHive::LocalMachine, "SOFTWARE\\Rainway", "Version"),
+            Some("1.0.0".to_string())
+        );
+        assert_eq!(host.service_exists("Rainway"), false);
+        assert_eq!(
+            context
+                .install_path
+                .join("plugins")
+                .join("old.dll")
+                .exists(),
+            true
+        );
+        assert_eq!(context.install_path.join("Rainway.lnk").exists(), false);
+        assert_eq!(
+            host.log.borrow().clone(),
+            vec!["start Rainway".to_string(), "stop Rainway".to_string()]
+        );
+    }
+
+    #[test]
+    fn actions_run_again() {
+        let context = context("run_again");
+        let host = FakeHost::default();
+        // an apply that was interrupted after its first actions is recovered by running all of them.
+        let all = actions("setup.exe");
+        assert_eq!(run_actions(&all[..4], &context, &host).is_ok(), true);
+        assert_eq!(run_actions(&all, &context, &host).is_ok(), true);
+        assert_eq!(
+            host.get_registry_value(RegistryHive::LocalMachine, "SOFTWARE\\Rainway", "Version"),
+            Some("2.0.0".to_string())
+        );
+        assert_eq!(host.services.borrow().clone(), vec!["Rainway".to_string()]);
+        assert_eq!(
+            context
+                .install_path
+                .join("plugins")
+                .join("old.dll")
+                .exists(),
+            false
+        );
+        assert_eq!(context.install_path.join("Rainway.lnk").exists(), true);
+        assert_eq!(
+            host.log.borrow().clone(),
+            vec![
+                "start Rainway".to_string(),
+                "start Rainway".to_string(),
+                "run --install".to_string()
+            ]
+        );
+        let _ = remove_dir_all(context.install_path.parent().unwrap());
+    }
+
+    #[test]
+    fn delete_outside_install_path() {
+        let delete = |path: &str| {
+            validate_actions(&[Action::DeletePath {
+                path: path.to_string(),
+            }])
+        };
+        assert_eq!(delete("plugins/old.dll").is_ok(), true);
+        assert_eq!(delete("../Windows").is_err(), true);
+        assert_eq!(delete("plugins\\..\\..\\Windows").is_err(), true);
+        assert_eq!(delete("C:\\Windows").is_err(), true);
+        assert_eq!(delete("/etc").is_err(), true);
+    }
+}
@@ -1,7 +1,20 @@
+pub mod actions;
+#[cfg(windows)]
 mod dacl;
+#[cfg(windows)]
 pub mod files;
+#[cfg(windows)]
 pub mod guid;
+#[cfg(windows)]
 pub mod process;
+pub mod requirements;
+#[cfg(windows)]
 pub mod service;
+#[cfg(not(windows))]
+mod unsupported;
+#[cfg(not(windows))]
+pub use unsupported::{files, process, windows};
+#[cfg(windows)]
 pub mod windows;
+#[cfg(windows)]
 pub mod winver;
@@ -0,0 +1,283 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// the CPU architectures a release can declare support for.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Architecture {
+    X86,
+    X64,
+    Arm64,
+}
+
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Architecture::X86 => write!(f, "x86"),
+            Architecture::X64 => write!(f, "x64"),
+            Architecture::Arm64 => write!(f, "arm64"),
+        }
+    }
+}
+
+/// the system requirements a release declares in its manifest.
+/// every requirement is optional, a requirement that is not set always passes.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Requirements {
+    /// the minimum Windows version as major.minor.build, such as 10.0.17763.
+    pub minimum_os_version: Option<String>,
+    /// the CPU architectures the release runs on. any architecture is allowed when empty.
+    #[serde(default)]
+    pub architectures: Vec<Architecture>,
+    /// the minimum .NET Framework release number, such as 461808 for 4.7.2.
+    pub dotnet_release: Option<u32>,
+    /// the free space needed on the install drive in megabytes.
+    pub disk_space_mb: Option<u64>,
+    /// the physical memory needed in megabytes.
+    pub memory_mb: Option<u64>,
+}
+
+/// a probe reads the facts about the current system that requirements are checked against.
+/// returning None means the fact could not be determined.
+pub trait SystemProbe {
+    /// the operating system version as its numeric parts, such as [10, 0, 17763].
+    fn os_version(&self) -> Option<Vec<u32>>;
+    fn architecture(&self) -> Option<Architecture>;
+    /// the installed .NET Framework release number.
+    fn dotnet_release(&self) -> Option<u32>;
+    /// the free space on the install drive in bytes.
+    fn free_disk_space(&self) -> Option<u64>;
+    /// the total physical memory in bytes.
+    fn total_memory(&self) -> Option<u64>;
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Requirement {
+    OsVersion,
+    Architecture,
+    DotNet,
+    DiskSpace,
+    Memory,
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Requirement::OsVersion => write!(f, "Windows version"),
+            Requirement::Architecture => write!(f, "CPU architecture"),
+            Requirement::DotNet => write!(f, ".NET Framework release"),
+            Requirement::DiskSpace => write!(f, "Free disk space"),
+            Requirement::Memory => write!(f, "Memory"),
+        }
+    }
+}
+
+/// the outcome of checking a single requirement.
+#[derive(Serialize, Debug, Clone)]
+pub struct RequirementCheck {
+    pub requirement: Requirement,
+    /// what the release requires, formatted for display.
+    pub required: String,
+    /// what was found on the system, None if the probe could not tell.
+    pub found: Option<String>,
+    pub passed: bool,
+}
+
+impl fmt::Display for RequirementCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} required, {} found",
+            self.requirement,
+            self.required,
+            self.found.as_deref().unwrap_or("nothing")
+        )
+    }
+}
+
+/// a pass/fail report of every requirement a release declares.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RequirementReport {
+    pub checks: Vec<RequirementCheck>,
+}
+
+impl RequirementReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.passed)
+    }
+
+    pub fn failures(&self) -> Vec<&RequirementCheck> {
+        self.checks.iter().filter(|c| !c.passed).collect()
+    }
+}
+
+impl fmt::Display for RequirementReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in self.failures() {
+            writeln!(f, "- {}", check)?;
+        }
+        Ok(())
+    }
+}
+
+impl Requirements {
+    pub fn is_empty(&self) -> bool {
+        self == &Requirements::default()
+    }
+
+    /// checks that the minimum OS version can be parsed so a typo is caught while baking.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(version) = &self.minimum_os_version {
+            if parse_version(version).is_none() {
+                return Err(format!(
+                    "{} is not a valid minimum OS version, expected major.minor.build.",
+                    version
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// checks every declared requirement against the facts returned by a probe.
+    /// the OS version, architecture and .NET release fail when they cannot be determined,
+    /// while disk space and memory are given the benefit of the doubt.
+    pub fn evaluate(&self, probe: &dyn SystemProbe) -> RequirementReport {
+        let mut report = RequirementReport::default();
+        if let Some(minimum) = &self.minimum_os_version {
+            let found = probe.os_version();
+            let passed = match (&found, parse_version(minimum)) {
+                (Some(found), Some(minimum)) => compare_versions(found, &minimum) != Ordering::Less,
+                _ => false,
+            };
+            report.checks.push(RequirementCheck {
+                requirement: Requirement::OsVersion,
+                required: format!("{} or later", minimum),
+                found: found.map(|v| format_version(&v)),
+                passed,
+            });
+        }
+        if !self.architectures.is_empty() {
+            let found = probe.architecture();
+            report.checks.push(RequirementCheck {
+                requirement: Requirement::Architecture,
+                required: self
+                    .architectures
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" or "),
+                found: found.map(|a| a.to_string()),
+                passed: found.map_or(false, |a| self.architectures.contains(&a)),
+            });
+        }
+        if let Some(minimum) = self.dotnet_release {
+            let found = probe.dotnet_release();
+            report.checks.push(RequirementCheck {
+                requirement: Requirement::DotNet,
+                required: format!("{} or later", minimum),
+                found: found.map(|r| r.to_string()),
+                passed: found.map_or(false, |r| r >= minimum),
+            });
+        }
+        if let Some(minimum) = self.disk_space_mb {
+            let found = probe.free_disk_space().map(|b| b / 1_048_576);
+            report.checks.push(RequirementCheck {
+                requirement: Requirement::DiskSpace,
+                required: format!("{} MB", minimum),
+                found: found.map(|mb| format!("{} MB", mb)),
+                passed: found.map_or(true, |mb| mb >= minimum),
+            });
+        }
+        if let Some(minimum) = self.memory_mb {
+            let found = probe.total_memory().map(|b| b / 1_048_576);
+            report.checks.push(RequirementCheck {
+                requirement: Requirement::Memory,
+                required: format!("{} MB", minimum),
+                found: found.map(|mb| format!("{} MB", mb)),
+                passed: found.map_or(true, |mb| mb >= minimum),
+            });
+        }
+        report
+    }
+}
+
+/// parses a dotted version such as 10.0.17763 into its numeric parts.
+pub fn parse_version(version: &str) -> Option<Vec<u32>> {
+    version
+        .trim()
+        .split('.')
+        .map(|part| part.parse::<u32>().ok())
+        .collect()
+}
+
+fn format_version(version: &[u32]) -> String {
+    version
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+/// compares two versions part by part, missing parts count as zero.
+fn compare_versions(a: &[u32], b: &[u32]) -> Ordering {
+    for i in 0..a.len().max(b.len()) {
+        let ordering = a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Architecture, Requirement, Requirements, SystemProbe};
+
+    struct FakeProbe;
+
+    impl SystemProbe for FakeProbe {
+        fn os_version(&self) -> Option<Vec<u32>> {
+            Some(vec![10, 0, 17763])
+        }
+        fn architecture(&self) -> Option<Architecture> {
+            Some(Architecture::X64)
+        }
+        fn dotnet_release(&self) -> Option<u32> {
+            None
+        }
+        fn free_disk_space(&self) -> Option<u64> {
+            Some(500 * 1_048_576)
+        }
+        fn total_memory(&self) -> Option<u64> {
+            None
+        }
+    }
+
+    #[test]
+    fn report_failures() {
+        let requirements = Requirements {
+            minimum_os_version: Some("10.0.14393".to_string()),
+            architectures: vec![Architecture::X64, Architecture::Arm64],
+            dotnet_release: Some(461808),
+            disk_space_mb: Some(1024),
+            memory_mb: Some(4096),
+        };
+        let report = requirements.evaluate(&FakeProbe);
+        assert_eq!(report.checks.len(), 5);
+        assert_eq!(report.passed(), false);
+        let failed: Vec<Requirement> = report.failures().iter().map(|c| c.requirement).collect();
+        assert_eq!(failed, vec![Requirement::DotNet, Requirement::DiskSpace]);
+    }
+
+    #[test]
+    fn newer_os_required() {
+        let requirements = Requirements {
+            minimum_os_version: Some("10.0.19041".to_string()),
+            ..Requirements::default()
+        };
+        assert_eq!(requirements.evaluate(&FakeProbe).passed(), false);
+        assert_eq!(Requirements::default().evaluate(&FakeProbe).passed(), true);
+    }
+}
@@ -112,3 +112,54 @@ pub fn start_service(service_name: &str) -> Result<bool, BootstrapError> {
         Err(_e) => Err(BootstrapError::ServiceOpenFailure),
     }
 }
+
+/// Stops a windows service by name if it is running.
+pub fn stop_service(service_name: &str) -> Result<bool, BootstrapError> {
+    if !service_exist(service_name) {
+        return Err(BootstrapError::ServiceMissing(service_name.to_string()));
+    }
+    let manager_access = ServiceManagerAccess::CONNECT;
+    let service_manager = match ServiceManager::local_computer(None::<&str>, manager_access) {
+        Ok(sm) => sm,
+        Err(_e) => return Err(BootstrapError::ServiceConnectionFailure),
+    };
+    let service_access = ServiceAccess::QUERY_STATUS | ServiceAccess::STOP;
+    let service = match service_manager.open_service(service_name, service_access) {
+        Ok(s) => s,
+        Err(_e) => return Err(BootstrapError::ServiceOpenFailure),
+    };
+    let service_status = match service.query_status() {
+        Ok(s) => s,
+        Err(_e) => return Err(BootstrapError::ServiceQueryFailed),
+    };
+    if service_status.current_state == ServiceState::Stopped {
+        return Ok(false);
+    }
+    match service.stop() {
+        Ok(_o) => Ok(true),
+        Err(_e) => Err(BootstrapError::ServiceOpenFailure),
+    }
+}
+
+/// Stops and removes a windows service by name.
+pub fn delete_service(service_name: &str) -> Result<bool, BootstrapError> {
+    if !service_exist(service_name) {
+        return Err(BootstrapError::ServiceMissing(service_name.to_string()));
+    }
+    if let Err(e) = stop_service(service_name) {
+        log::warn!("unable to stop {} before removing it: {}", service_name, e);
+    }
+    let manager_access = ServiceManagerAccess::CONNECT;
+    let service_manager = match ServiceManager::local_computer(None::<&str>, manager_access) {
+        Ok(sm) => sm,
+        Err(_e) => return Err(BootstrapError::ServiceConnectionFailure),
+    };
+    let service = match service_manager.open_service(service_name, ServiceAccess::DELETE) {
+        Ok(s) => s,
+        Err(_e) => return Err(BootstrapError::ServiceOpenFailure),
+    };
+    match service.delete() {
+        Ok(_o) => Ok(true),
+        Err(_e) => Err(BootstrapError::ServiceOpenFailure),
+    }
+}
@@ -0,0 +1,202 @@
+//! stand-ins for the Windows only modules on every other host, so the bakery and the
+//! readers that do not depend on Windows build and are tested there too.
+//! nothing can be installed or updated through them, each call fails or finds nothing.
+
+pub mod files {
+    use std::io::{Error, ErrorKind};
+    use std::path::PathBuf;
+
+    pub fn take_ownership_of_dir(_dir: &PathBuf) -> bool {
+        false
+    }
+
+    pub fn grant_full_permissions(_dir: &PathBuf) -> bool {
+        false
+    }
+
+    pub fn unblock_file(_file: PathBuf) {}
+
+    pub fn unblock_path(_path: &PathBuf) -> Result<(), Error> {
+        Err(Error::new(
+            ErrorKind::Other,
+            "Files can only be unblocked on Windows.",
+        ))
+    }
+}
+
+pub mod process {
+    use std::io::Error;
+    use std::path::Path;
+
+    /// a process that holds a file open, none are ever found off Windows.
+    pub struct Process {
+        name: String,
+    }
+
+    impl Process {
+        pub fn name(&self) -> &str {
+            self.name.as_str()
+        }
+
+        pub fn kill(&self) -> bool {
+            false
+        }
+
+        pub fn is_running(&self) -> bool {
+            false
+        }
+    }
+
+    pub fn get_procs_using_path<P: AsRef<Path>>(_path: P) -> Result<Vec<Process>, Error> {
+        Ok(Vec::new())
+    }
+}
+
+pub mod windows {
+    use crate::etc::constants::BootstrapError;
+    use crate::os::actions::{ActionHost, RegistryHive};
+    use std::ffi::OsStr;
+    use std::io::{Error, ErrorKind};
+    use std::path::Path;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    pub enum RegistryHandle {
+        #[default]
+        CurrentUser,
+        LocalMachine,
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub struct InstalledApp {
+        pub uninstall_string: String,
+        pub install_location: String,
+        pub name: String,
+        pub version: String,
+        pub branch: String,
+        pub handle: RegistryHandle,
+        pub key: String,
+    }
+
+    /// any value the registry could be given.
+    pub trait ToRegValue {}
+    impl<T> ToRegValue for T {}
+
+    /// a registry key, there is no registry to read or write.
+    pub struct RegKey;
+
+    impl RegKey {
+        pub fn get_value<T, N: AsRef<OsStr>>(&self, _name: N) -> Result<T, Error> {
+            Err(Error::from(ErrorKind::NotFound))
+        }
+
+        pub fn set_value<T: ToRegValue, N: AsRef<OsStr>>(
+            &self,
+            _name: N,
+            _value: &T,
+        ) -> Result<(), Error> {
+            Err(Error::new(
+                ErrorKind::Other,
+                "The registry is only available on Windows.",
+            ))
+        }
+    }
+
+    pub fn set_uninstall_value<T: ToRegValue>(
+        _name: &str,
+        _value: &T,
+        sub_key: &String,
+        _handle: RegistryHandle,
+    ) -> Result<(), BootstrapError> {
+        Err(BootstrapError::RegistryKeyNotFound(sub_key.to_string()))
+    }
+
+    pub fn get_uninstallers() -> Result<Vec<InstalledApp>, BootstrapError> {
+        Ok(Vec::new())
+    }
+
+    pub fn get_reg_key(_handle: RegistryHandle, key: &str) -> Result<RegKey, BootstrapError> {
+        Err(BootstrapError::RegistryKeyNotFound(key.to_string()))
+    }
+
+    pub fn delete_reg_key(_handle: RegistryHandle, _key: &str) {}
+
+    pub fn create_reg_key(_handle: RegistryHandle, key: &str) -> Result<RegKey, BootstrapError> {
+        Err(BootstrapError::RegistryKeyNotFound(key.to_string()))
+    }
+
+    /// refuses every post-install action.
+    pub struct WindowsActionHost;
+
+    fn unsupported() -> Result<(), String> {
+        Err("Post-install actions can only run on Windows.".to_string())
+    }
+
+    impl ActionHost for WindowsActionHost {
+        fn run(&self, _path: &Path, _args: &[String]) -> Result<(), String> {
+            unsupported()
+        }
+
+        fn service_exists(&self, _name: &str) -> bool {
+            false
+        }
+
+        fn register_service(
+            &self,
+            _name: &str,
+            _display_name: &str,
+            _path: &Path,
+            _args: &[String],
+        ) -> Result<(), String> {
+            unsupported()
+        }
+
+        fn remove_service(&self, _name: &str) -> Result<(), String> {
+            unsupported()
+        }
+
+        fn start_service(&self, _name: &str) -> Result<(), String> {
+            unsupported()
+        }
+
+        fn stop_service(&self, _name: &str) -> Result<(), String> {
+            unsupported()
+        }
+
+        fn get_registry_value(
+            &self,
+            _hive: RegistryHive,
+            _key: &str,
+            _name: &str,
+        ) -> Option<String> {
+            None
+        }
+
+        fn set_registry_value(
+            &self,
+            _hive: RegistryHive,
+            _key: &str,
+            _name: &str,
+            _value: &str,
+        ) -> Result<(), String> {
+            unsupported()
+        }
+
+        fn delete_registry_value(
+            &self,
+            _hive: RegistryHive,
+            _key: &str,
+            _name: &str,
+        ) -> Result<(), String> {
+            unsupported()
+        }
+
+        fn create_shortcut(
+            &self,
+            _target: &Path,
+            _shortcut: &Path,
+            _args: &[String],
+        ) -> Result<(), String> {
+            unsupported()
+        }
+    }
+}
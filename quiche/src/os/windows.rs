@@ -1,5 +1,10 @@
 use crate::etc::constants::BootstrapError;
+use crate::os::actions::{ActionHost, RegistryHive};
 use crate::os::process::get_current_process;
+use crate::os::requirements::{parse_version, Architecture, SystemProbe};
+use crate::os::service::{
+    delete_service, install_service, service_exist, start_service, stop_service, WindowsService,
+};
 use crate::os::winver::{
     is_windows10_or_greater, is_windows7_or_greater, is_windows8_or_greater,
     is_windows8_point1_or_greater, is_windows_server, is_windows_vista_or_greater,
@@ -7,13 +12,13 @@ use crate::os::winver::{
 };
 use regex::Regex;
 use std::env::var_os;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use winapi::um::winnt::KEY_READ;
 use winapi::um::winnt::KEY_WOW64_64KEY;
 use winapi::um::winuser::{GetSystemMetrics, SM_REMOTESESSION};
 use winreg::enums::HKEY_LOCAL_MACHINE;
 use winreg::enums::KEY_ALL_ACCESS;
-use winreg::types::ToRegValue;
+pub use winreg::types::ToRegValue;
 use winreg::RegKey;
 use winreg::HKEY;
 /// dism.exe will return exit code 740 if it is launched
@@ -105,13 +110,207 @@ pub fn get_system_info() -> Result<SystemInfo, BootstrapError> {
 
 /// Parses the registry to determine if the host OS is x32 or x64.
 fn is_x64() -> bool {
+    get_architecture() == Some(Architecture::X64)
+}
+
+/// Returns the native CPU architecture of the host OS, even when running under WOW64.
+pub fn get_architecture() -> Option<Architecture> {
     use winapi::um::sysinfoapi;
     let mut out: sysinfoapi::SYSTEM_INFO = unsafe { std::mem::MaybeUninit::zeroed().assume_init() };
     unsafe { sysinfoapi::GetNativeSystemInfo(&mut out as sysinfoapi::LPSYSTEM_INFO) };
     match unsafe { out.u.s() }.wProcessorArchitecture {
         //x64 (AMD or Intel)
-        winapi::um::winnt::PROCESSOR_ARCHITECTURE_AMD64 => true,
-        _ => false,
+        winapi::um::winnt::PROCESSOR_ARCHITECTURE_AMD64 => Some(Architecture::X64),
+        winapi::um::winnt::PROCESSOR_ARCHITECTURE_INTEL => Some(Architecture::X86),
+        winapi::um::winnt::PROCESSOR_ARCHITECTURE_ARM64 => Some(Architecture::Arm64),
+        _ => None,
+    }
+}
+
+/// Reads the version of the host OS as major.minor.build from the registry.
+/// Windows 8.1 and older only have the CurrentVersion string, so it is used as a fallback.
+pub fn get_os_version() -> Option<Vec<u32>> {
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let cur_ver = hklm
+        .open_subkey("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion")
+        .ok()?;
+    let major: Result<u32, _> = cur_ver.get_value("CurrentMajorVersionNumber");
+    let minor: Result<u32, _> = cur_ver.get_value("CurrentMinorVersionNumber");
+    let mut version = match (major, minor) {
+        (Ok(major), Ok(minor)) => vec![major, minor],
+        _ => {
+            let current: String = cur_ver.get_value("CurrentVersion").ok()?;
+            parse_version(&current)?
+        }
+    };
+    let build: String = cur_ver.get_value("CurrentBuildNumber").ok()?;
+    version.push(build.trim().parse().ok()?);
+    Some(version)
+}
+
+/// Returns the free space in bytes available to the current user on the drive of a path.
+/// The path does not need to exist yet, the closest existing parent is used.
+pub fn get_free_disk_space(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::shared::ntdef::ULARGE_INTEGER;
+    use winapi::um::fileapi::GetDiskFreeSpaceExW;
+    let existing = path.ancestors().find(|p| p.exists())?;
+    let wide: Vec<u16> = existing
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut free: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+    let result = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut free,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if result == 0 {
+        return None;
+    }
+    Some(unsafe { *free.QuadPart() })
+}
+
+/// Returns the total physical memory of the host in bytes.
+pub fn get_total_memory() -> Option<u64> {
+    use winapi::um::sysinfoapi::{GlobalMemoryStatusEx, MEMORYSTATUSEX};
+    let mut status: MEMORYSTATUSEX = unsafe { std::mem::zeroed() };
+    status.dwLength = std::mem::size_of::<MEMORYSTATUSEX>() as u32;
+    if unsafe { GlobalMemoryStatusEx(&mut status) } == 0 {
+        return None;
+    }
+    Some(status.ullTotalPhys)
+}
+
+/// Probes the host for the facts that release requirements are checked against.
+pub struct WindowsProbe {
+    /// where the release will be installed, used to find the drive to check free space on.
+    pub install_path: PathBuf,
+}
+
+impl SystemProbe for WindowsProbe {
+    fn os_version(&self) -> Option<Vec<u32>> {
+        get_os_version()
+    }
+    fn architecture(&self) -> Option<Architecture> {
+        get_architecture()
+    }
+    fn dotnet_release(&self) -> Option<u32> {
+        get_dotnet_framework_version()
+    }
+    fn free_disk_space(&self) -> Option<u64> {
+        get_free_disk_space(&self.install_path)
+    }
+    fn total_memory(&self) -> Option<u64> {
+        get_total_memory()
+    }
+}
+
+/// Runs post-install actions against the services and registry of the host.
+pub struct WindowsActionHost;
+
+impl From<RegistryHive> for RegistryHandle {
+    fn from(hive: RegistryHive) -> Self {
+        match hive {
+            RegistryHive::CurrentUser => RegistryHandle::CurrentUser,
+            RegistryHive::LocalMachine => RegistryHandle::LocalMachine,
+        }
+    }
+}
+
+impl ActionHost for WindowsActionHost {
+    fn run(&self, path: &Path, args: &[String]) -> Result<(), String> {
+        use std::os::windows::process::CommandExt;
+        use std::process::Command;
+        let status = match Command::new(path)
+            .args(args)
+            .creation_flags(0x08000000)
+            .status()
+        {
+            Ok(s) => s,
+            Err(e) => return Err(format!("unable to run {}: {}", path.display(), e)),
+        };
+        if !status.success() {
+            return Err(format!("{} exited with {}", path.display(), status));
+        }
+        Ok(())
+    }
+    fn service_exists(&self, name: &str) -> bool {
+        service_exist(name)
+    }
+    fn register_service(
+        &self,
+        name: &str,
+        display_name: &str,
+        path: &Path,
+        args: &[String],
+    ) -> Result<(), String> {
+        let service = WindowsService {
+            name: name.to_string(),
+            display_name: display_name.to_string(),
+            arguments: args.to_vec(),
+            executable_path: path.to_path_buf(),
+        };
+        install_service(service).map(|_| ()).map_err(|e| e.to_string())
+    }
+    fn remove_service(&self, name: &str) -> Result<(), String> {
+        delete_service(name).map(|_| ()).map_err(|e| e.to_string())
+    }
+    fn start_service(&self, name: &str) -> Result<(), String> {
+        start_service(name).map(|_| ()).map_err(|e| e.to_string())
+    }
+    fn stop_service(&self, name: &str) -> Result<(), String> {
+        stop_service(name).map(|_| ()).map_err(|e| e.to_string())
+    }
+    fn get_registry_value(&self, hive: RegistryHive, key: &str, name: &str) -> Option<String> {
+        get_reg_key(hive.into(), key).ok()?.get_value(name).ok()
+    }
+    fn set_registry_value(
+        &self,
+        hive: RegistryHive,
+        key: &str,
+        name: &str,
+        value: &str,
+    ) -> Result<(), String> {
+        let reg_key = create_reg_key(hive.into(), key).map_err(|e| e.to_string())?;
+        reg_key
+            .set_value(name, &value.to_string())
+            .map_err(|e| BootstrapError::UnableToSetRegKey(e.to_string()).to_string())
+    }
+    fn delete_registry_value(
+        &self,
+        hive: RegistryHive,
+        key: &str,
+        name: &str,
+    ) -> Result<(), String> {
+        let reg_key = get_reg_key(hive.into(), key).map_err(|e| e.to_string())?;
+        reg_key.delete_value(name).map_err(|e| e.to_string())
+    }
+    /// shortcuts are created through the WScript.Shell COM object.
+    fn create_shortcut(&self, target: &Path, shortcut: &Path, args: &[String]) -> Result<(), String> {
+        let quote = |value: &str| format!("'{}'", value.replace('\'', "''"));
+        let script = format!(
+            "$s = (New-Object -ComObject WScript.Shell).CreateShortcut({}); $s.TargetPath = {}; $s.Arguments = {}; $s.Save()",
+            quote(&shortcut.to_string_lossy()),
+            quote(&target.to_string_lossy()),
+            quote(&args.join(" "))
+        );
+        if let Some(parent) = shortcut.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        self.run(
+            Path::new("powershell"),
+            &[
+                "-NoProfile".to_string(),
+                "-NonInteractive".to_string(),
+                "-Command".to_string(),
+                script,
+            ],
+        )
     }
 }
 